@@ -1,6 +1,15 @@
+use crate::error::{Error, Result};
+use crate::pure_dag::{self, PureDag, PureTaskInfo};
+use crate::source::{JobSink, TraceSource};
+use crate::trace_schema::TraceSchema;
+use csv::StringRecord;
 use petgraph::{graph::Graph, stable_graph::NodeIndex, Directed};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, collections::HashSet, fs::File, io::Read, io::Write, path::Path};
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+use std::{collections::HashMap, collections::HashSet};
 
 #[derive(Serialize, Deserialize)]
 struct Instance {
@@ -34,9 +43,7 @@ struct TaskInstInfo {
 
 type DAG = Graph<TaskInfo, u64, Directed>;
 
-const BATCH_TASK_FILE: &str = "../datasets/batch_task.csv";
-const BATCH_INSTANCE_FILENAME: &str = "../datasets/batch_instance.csv";
-pub const INS_INPUT_FILENAME: &str = "../datasets/save_result_ins.json";
+pub const SPILL_BUCKETS: usize = 64;
 
 fn extend_dags(old_dag: &DAG) -> InstDag {
     let mut result = InstDag::new();
@@ -56,111 +63,185 @@ fn extend_dags(old_dag: &DAG) -> InstDag {
     return result;
 }
 
-#[allow(dead_code)]
-pub fn main_tasks() {
-    let mut jobs = HashMap::<String, Graph<TaskInfo, u64, Directed>>::new();
-    let mut task_to_index = HashMap::<String, HashMap<String, NodeIndex>>::new();
+type TaskIndexesT = HashMap<String, HashMap<String, NodeIndex>>;
+
+fn record_error(record: &StringRecord, reason: String) -> Error {
+    return Error::Record {
+        line: record.position().map(|pos| pos.line()).unwrap_or(0),
+        reason: reason,
+    };
+}
 
-    let mut rdr = csv::Reader::from_path(BATCH_TASK_FILE).unwrap();
+fn field<'a>(record: &'a StringRecord, ind: usize) -> Result<&'a str> {
+    return record
+        .get(ind)
+        .ok_or_else(|| record_error(record, format!("no column {}", ind)));
+}
 
-    let mut unterminated_jobs: HashSet<String> = HashSet::new();
+fn parse_field<T: FromStr>(record: &StringRecord, ind: usize) -> Result<T> {
+    let value = field(record, ind)?;
+    return value
+        .parse::<T>()
+        .map_err(|_| record_error(record, format!("can't parse column {}: {}", ind, value)));
+}
 
-    for result in rdr.records() {
-        let record = result.expect("a CSV record");
-        let dependences: Vec<&str> = record.get(0).unwrap().split('_').collect();
+// M1_2_3 -> task1, R5 -> task5. None for independent tasks (task_*) and unknown format
+fn parse_task_name(task_name: &str) -> Option<String> {
+    if task_name.starts_with("task") || task_name.len() < 2 {
+        return None;
+    }
+    return match task_name[1..].parse::<u64>() {
+        Ok(task_number) => Some(format!("task{}", task_number)),
+        Err(_) => None,
+    };
+}
+
+fn add_task_record(
+    record: &StringRecord,
+    schema: &TraceSchema,
+    jobs: &mut HashMap<String, DAG>,
+    task_to_index: &mut TaskIndexesT,
+    unterminated_jobs: &mut HashSet<String>,
+) -> Result<()> {
+    let columns = &schema.task;
+    let dependences: Vec<&str> = field(record, columns.task_name)?.split('_').collect();
+
+    let task_name = match parse_task_name(dependences[0]) {
+        Some(task_name) => task_name,
+        None => return Ok(()),
+    };
+
+    let jobs_name = field(record, columns.job)?;
+    if unterminated_jobs.contains(jobs_name) {
+        return Ok(());
+    }
+    if !schema.accepts(field(record, columns.status)?) {
+        unterminated_jobs.insert(jobs_name.to_string());
+        return Ok(());
+    }
+    let jobs_name = jobs_name.to_string();
+
+    let ver_info = TaskInfo {
+        name: task_name.to_string(),
+        instance_cnt: parse_field(record, columns.instance_cnt)?,
+        start_time: parse_field(record, columns.start_time)?,
+        end_time: parse_field(record, columns.end_time)?,
+        dependences: dependences
+            .iter()
+            .skip(1)
+            .filter_map(|str| match str.parse::<u32>() {
+                Ok(num) => Some(num),
+                Err(_) => None,
+            })
+            .collect(),
+    };
+
+    if !jobs.contains_key(&jobs_name) {
+        jobs.insert(jobs_name.to_string(), Graph::new());
+        task_to_index.insert(jobs_name.to_string(), HashMap::new());
+    }
+    let graph = jobs.get_mut(&jobs_name).unwrap();
+    let job_indexs = task_to_index.get_mut(&jobs_name).unwrap();
+
+    // we could insert empty nodes if some node apeared before its dependances
+    let task_index: NodeIndex = if job_indexs.contains_key(&task_name) {
+        let task_index = job_indexs[&task_name];
+        let node_wight = graph.node_weight_mut(task_index).unwrap();
+        node_wight.instance_cnt = ver_info.instance_cnt;
+        node_wight.start_time = ver_info.start_time;
+        node_wight.end_time = ver_info.end_time;
+        node_wight.dependences = ver_info.dependences;
+        task_index
+    } else {
+        let task_index = graph.add_node(ver_info);
+        job_indexs.insert(task_name.to_string(), task_index);
+        task_index
+    };
 
-        let task_name = dependences[0].to_string();
-        if task_name.starts_with("task") {
+    for dependance in dependences.iter().skip(1) {
+        if dependance.len() == 0 {
             continue;
         }
-        let task_name = match task_name[1..].parse::<u64>() {
-            Ok(task_number) => {
-                format!("task{}", task_number)
+
+        let dependance_name = match dependance.parse::<u64>() {
+            Ok(dependance_num) => {
+                format!("task{}", dependance_num)
             }
             Err(_) => {
-                continue;
+                unterminated_jobs.insert(jobs_name);
+                break;
             }
         };
 
-        let jobs_name = record.get(2).unwrap();
-        if unterminated_jobs.contains(jobs_name) {
-            continue;
-        }
-        if !record.get(4).unwrap().eq("Terminated") {
-            unterminated_jobs.insert(jobs_name.to_string());
-            continue;
-        }
-        let jobs_name = jobs_name.to_string();
+        if !job_indexs.contains_key(&dependance_name) {
+            let empty_info = TaskInfo {
+                name: dependance_name.to_string(),
+                instance_cnt: 0,
+                start_time: 0,
+                end_time: 0,
+                dependences: Vec::new(),
+            };
 
-        let mut graph = jobs.get_mut(&jobs_name);
-        if graph.is_none() {
-            jobs.insert(jobs_name.to_string(), Graph::new());
-            graph = jobs.get_mut(&jobs_name);
-            task_to_index.insert(jobs_name.to_string(), HashMap::new());
+            let empty_task_index = graph.add_node(empty_info);
+            job_indexs.insert(dependance_name.to_string(), empty_task_index);
         }
 
-        let job_indexs = task_to_index.get_mut(&jobs_name).unwrap();
-        let graph = graph.expect("Failed to create new job");
+        graph.add_edge(job_indexs[&dependance_name], task_index, 1);
+    }
+    return Ok(());
+}
 
-        let ver_info = TaskInfo {
-            name: task_name.to_string(),
-            instance_cnt: record.get(1).unwrap().parse().unwrap(),
-            start_time: record.get(5).unwrap().parse().unwrap(),
-            end_time: record.get(6).unwrap().parse().unwrap(),
-            dependences: dependences
-                .iter()
-                .skip(1)
-                .filter_map(|str| match str.parse::<u32>() {
-                    Ok(num) => Some(num),
-                    Err(_) => None,
-                })
-                .collect(),
-        };
+fn open_csv(filename: &str, has_header: bool) -> Result<csv::Reader<File>> {
+    return csv::ReaderBuilder::new()
+        .has_headers(has_header)
+        .flexible(true)
+        .from_path(filename)
+        .map_err(|why| Error::Csv {
+            file: filename.to_string(),
+            source: why,
+        });
+}
 
-        // we could insert empty nodes if some node apeared before its dependances
-        let task_index: NodeIndex = if job_indexs.contains_key(&task_name) {
-            let task_index = job_indexs[&task_name];
-            let node_wight = graph.node_weight_mut(task_index).unwrap();
-            node_wight.instance_cnt = ver_info.instance_cnt;
-            node_wight.start_time = ver_info.start_time;
-            node_wight.end_time = ver_info.end_time;
-            node_wight.dependences = ver_info.dependences;
-            task_index
-        } else {
-            let task_index = graph.add_node(ver_info);
-            job_indexs.insert(task_name.to_string(), task_index);
-            task_index
-        };
+// spilled files never have header
+fn read_tasks(
+    batch_task_file: &str,
+    schema: &TraceSchema,
+) -> Result<(HashMap<String, DAG>, TaskIndexesT)> {
+    let mut jobs = HashMap::<String, Graph<TaskInfo, u64, Directed>>::new();
+    let mut task_to_index = HashMap::<String, HashMap<String, NodeIndex>>::new();
 
-        for dependance in dependences.iter().skip(1) {
-            if dependance.len() == 0 {
+    let mut rdr = open_csv(batch_task_file, false)?;
+
+    let mut unterminated_jobs: HashSet<String> = HashSet::new();
+
+    for result in rdr.records() {
+        let record = match result {
+            Ok(record) => record,
+            Err(why) => {
+                eprintln!(
+                    "skip {}",
+                    Error::Csv {
+                        file: batch_task_file.to_string(),
+                        source: why,
+                    }
+                );
                 continue;
             }
-
-            let dependance_name = match dependance.parse::<u64>() {
-                Ok(dependance_num) => {
-                    format!("task{}", dependance_num)
-                }
-                Err(_) => {
-                    unterminated_jobs.insert(jobs_name);
-                    break;
+        };
+        if let Err(err) = add_task_record(
+            &record,
+            schema,
+            &mut jobs,
+            &mut task_to_index,
+            &mut unterminated_jobs,
+        ) {
+            match record.get(schema.task.job) {
+                Some(job_name) => {
+                    eprintln!("skip {}", err.in_job(job_name));
+                    unterminated_jobs.insert(job_name.to_string());
                 }
-            };
-
-            if !job_indexs.contains_key(&dependance_name) {
-                let empty_info = TaskInfo {
-                    name: dependance_name.to_string(),
-                    instance_cnt: 0,
-                    start_time: 0,
-                    end_time: 0,
-                    dependences: Vec::new(),
-                };
-
-                let empty_task_index = graph.add_node(empty_info);
-                job_indexs.insert(dependance_name.to_string(), empty_task_index);
+                None => eprintln!("skip {}", err),
             }
-
-            graph.add_edge(job_indexs[&dependance_name], task_index, 1);
         }
     }
 
@@ -171,83 +252,104 @@ pub fn main_tasks() {
     }
 
     println!("Stayed jobs: {}", jobs.len());
-    let j = serde_json::to_string(&jobs).unwrap();
+    return Ok((jobs, task_to_index));
+}
 
-    let path = Path::new("../save_result.json");
-    let mut file = match File::create(&path) {
-        Err(why) => panic!("cant open file to write {}", why),
-        Ok(file) => file,
+fn add_instance_record(
+    record: &StringRecord,
+    schema: &TraceSchema,
+    jobs: &HashMap<String, DAG>,
+    task_to_index: &TaskIndexesT,
+    unterminated_jobs: &mut HashSet<String>,
+    jobs_with_instances: &mut HashMap<String, InstDag>,
+    just_pass: &mut u64,
+) -> Result<()> {
+    let columns = &schema.instance;
+    let job_name = field(record, columns.job)?;
+
+    if schema.skip_jobs.iter().any(|x| x == job_name) {
+        return Ok(());
+    }
+    if unterminated_jobs.contains(job_name) {
+        return Ok(());
+    }
+    let job_ins = match jobs.get(job_name) {
+        Some(job_ins) => job_ins,
+        None => {
+            *just_pass += 1;
+            if *just_pass % 100000 == 0 {
+                println!("pass not found {}", just_pass);
+            }
+            return Ok(());
+        }
     };
 
-    match file.write_all(j.as_bytes()) {
-        Err(why) => panic!("cant save serialization {}", why),
-        Ok(_) => {}
-    }
+    let dependences: Vec<&str> = field(record, columns.task_name)?.split('_').collect();
 
-    let path = Path::new("../save_indexes.json");
+    let task_name = match parse_task_name(dependences[0]) {
+        Some(task_name) => task_name,
+        None => return Ok(()),
+    };
 
-    let mut file = match File::create(&path) {
-        Err(why) => panic!("cant open file to write {}", why),
-        Ok(file) => file,
+    let task_ind = match task_to_index[job_name].get(&task_name) {
+        Some(task_ind) => task_ind,
+        None => {
+            println!("failed to find task {} for job {}", task_name, job_name);
+            return Ok(());
+        }
     };
 
-    let j = serde_json::to_string(&task_to_index).unwrap();
-    match file.write_all(j.as_bytes()) {
-        Err(why) => panic!("cant save serialization {}", why),
-        Ok(_) => {}
+    if !schema.accepts(field(record, columns.status)?) {
+        unterminated_jobs.insert(job_name.to_string());
+        return Ok(());
     }
-}
 
-type TaskIndexesT = HashMap<String, HashMap<String, NodeIndex>>;
+    let start_time = parse_field::<u64>(record, columns.start_time)?;
+    let end_time = parse_field::<u64>(record, columns.end_time)?;
+    if end_time < start_time {
+        return Err(Error::Task {
+            task: task_name,
+            reason: format!("instance ends at {} before start {}", end_time, start_time),
+        });
+    }
+    let ins_duraction = end_time - start_time;
 
-fn get_task_indexes() -> TaskIndexesT {
-    let path = Path::new("../save_indexes.json");
-    let mut file = match File::open(&path) {
-        Err(why) => panic!("cant open file to write {}", why),
-        Ok(file) => file,
-    };
-    let mut contents = String::new();
-    match file.read_to_string(&mut contents) {
-        Err(why) => panic!("couldn't read: {}", why),
-        Ok(_) => {}
+    let avg_cpu_sage = parse_field::<f64>(record, columns.cpu_avg)?;
+    let max_cpu_sage = parse_field::<f64>(record, columns.cpu_max)?;
+    let avg_mem_sage = parse_field::<f64>(record, columns.mem_avg)?;
+    let max_mem_sage = parse_field::<f64>(record, columns.mem_max)?;
+
+    if !jobs_with_instances.contains_key(job_name) {
+        jobs_with_instances.insert(job_name.to_string(), extend_dags(&job_ins));
     }
-    return serde_json::from_str(&contents).unwrap();
-}
-fn get_graphs(filename: &str) -> HashMap<String, DAG> {
-    let path = Path::new(filename);
-    let mut file = match File::open(&path) {
-        Err(why) => panic!("cant open file to write {}", why),
-        Ok(file) => file,
-    };
-    let mut contents = String::new();
-    match file.read_to_string(&mut contents) {
-        Err(why) => panic!("couldn't read: {}", why),
-        Ok(_) => {}
+    let graph = jobs_with_instances.get_mut(job_name).unwrap();
+
+    match graph.node_weight_mut(*task_ind) {
+        None => {
+            return Err(Error::Task {
+                task: task_name,
+                reason: format!("no correct index {:?}", task_ind),
+            });
+        }
+
+        Some(task_info) => task_info.instances.push(Instance {
+            time: ins_duraction,
+            cpu_avg: avg_cpu_sage,
+            cpu_diff_max: max_cpu_sage - avg_cpu_sage,
+            mem_avg: avg_mem_sage,
+            mem_diff_max: max_mem_sage - avg_mem_sage,
+        }),
     }
-    return serde_json::from_str(&contents).unwrap();
+    return Ok(());
 }
 
-pub fn main_instances() {
-    let jobs = get_graphs("../save_result.json");
-    let mut task_to_index = get_task_indexes();
-
-    // skip them because of wrong task_name format
-    let pass_jobs = vec![
-        "j_4015961",
-        "j_1127318",
-        "j_2583771",
-        "j_3734942",
-        "j_2212822",
-        "j_1465012",
-        "j_1053726",
-        "j_3061955",
-        "j_2123594",
-        "j_94055",
-        "j_2428957",
-        "j_2598590",
-        "j_1575128",
-    ];
-    let mut rdr = csv::Reader::from_path(BATCH_INSTANCE_FILENAME).unwrap();
+fn read_instances(
+    schema: &TraceSchema,
+    jobs: &HashMap<String, DAG>,
+    task_to_index: &mut TaskIndexesT,
+    batch_instance_file: &str,
+) -> Result<HashMap<String, InstDag>> {
+    let mut rdr = open_csv(batch_instance_file, false)?;
 
     println!("real work starts");
     let mut unterminated_jobs: HashSet<String> = HashSet::new();
@@ -257,138 +359,265 @@ pub fn main_instances() {
 
     let mut jobs_with_instances = HashMap::<String, InstDag>::new();
     for result in rdr.records() {
-        let record = result.expect("a CSV record");
         lines += 1;
         if lines % 1000000 == 0 {
             println!("overal lines {}", lines);
         }
-
-        let job_name = record.get(2).unwrap();
-
-        if !job_name[2..].parse::<u32>().unwrap() / 200000 == 0 {
-            continue;
-        }
-
-        if pass_jobs.contains(&job_name) {
-            continue;
-        }
-        if unterminated_jobs.contains(job_name) {
-            continue;
-        }
-        let job_ins = jobs.get(job_name);
-        if job_ins.is_none() {
-            just_pass += 1;
-            if just_pass % 100000 == 0 {
-                println!("pass not found {}", just_pass);
-            }
-            continue;
-        }
-        let job_ins = job_ins.unwrap();
-
-        let dependences: Vec<&str> = record.get(1).unwrap().split('_').collect();
-
-        let task_name = dependences[0].to_string();
-        if task_name.starts_with("task") {
-            continue;
-        }
-        let task_name = match task_name[1..].parse::<u64>() {
-            Ok(task_number) => {
-                format!("task{}", task_number)
-            }
-            Err(err) => {
-                println!("{} {:?}", task_name, err);
+        let record = match result {
+            Ok(record) => record,
+            Err(why) => {
+                eprintln!(
+                    "skip {}",
+                    Error::Csv {
+                        file: batch_instance_file.to_string(),
+                        source: why,
+                    }
+                );
                 continue;
             }
         };
 
-        let task_ind = task_to_index[job_name].get(&task_name);
-        if task_ind.is_none() {
-            println!("failed to find task {} for job {}", task_name, job_name);
-            continue;
+        if let Err(err) = add_instance_record(
+            &record,
+            schema,
+            jobs,
+            task_to_index,
+            &mut unterminated_jobs,
+            &mut jobs_with_instances,
+            &mut just_pass,
+        ) {
+            match record.get(schema.instance.job) {
+                Some(job_name) => {
+                    eprintln!("skip {}", err.in_job(job_name));
+                    unterminated_jobs.insert(job_name.to_string());
+                }
+                None => eprintln!("skip {}", err),
+            }
         }
-        let task_ind = task_ind.unwrap();
+    }
+    println!("unterminated: {}", unterminated_jobs.len());
+    for unterminate_job in unterminated_jobs.iter() {
+        jobs_with_instances.remove(unterminate_job);
+        task_to_index.remove(unterminate_job);
+    }
 
-        if !record.get(4).unwrap().eq("Terminated") {
-            unterminated_jobs.insert(job_name.to_string());
-            continue;
-        }
+    println!("Stayed jobs: {}", jobs_with_instances.len());
+    return Ok(jobs_with_instances);
+}
+fn to_pure_dag(job: &InstDag) -> PureDag {
+    let mut result = PureDag::new();
+    for node in job.node_weights() {
+        result.add_node(PureTaskInfo {
+            name: node.name.clone(),
+            instance_cnt: node.instance_cnt,
+            start_time: node.start_time,
+            end_time: node.end_time,
+            dependences: node.dependences.clone(),
+            instances: node
+                .instances
+                .iter()
+                .map(|instance| pure_dag::Instance {
+                    time: instance.time,
+                    cpu_avg: instance.cpu_avg,
+                    cpu_diff_max: instance.cpu_diff_max,
+                    mem_avg: instance.mem_avg,
+                    mem_diff_max: instance.mem_diff_max,
+                })
+                .collect(),
+        });
+    }
+    for edge in job.raw_edges() {
+        result.add_edge(edge.source(), edge.target(), edge.weight);
+    }
+    return result;
+}
 
-        let start_time = record.get(5).unwrap().parse::<u64>().unwrap();
-        let end_time = record.get(6).unwrap().parse::<u64>().unwrap();
-        if end_time < start_time {
-            println!("{:?}", record);
-            panic!("start {} < end {}", start_time, end_time);
-        }
-        let ins_duraction = end_time - start_time;
+fn job_bucket(job_name: &str, buckets: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    job_name.hash(&mut hasher);
+    return (hasher.finish() % buckets as u64) as usize;
+}
 
-        if !jobs_with_instances.contains_key(job_name) {
-            jobs_with_instances.insert(job_name.to_string(), extend_dags(&job_ins));
-        }
-        let graph = jobs_with_instances.get_mut(job_name).unwrap();
-        let avg_cpu_sage = match record.get(10).unwrap().parse::<f64>() {
-            Ok(avg_cpu_sage) => avg_cpu_sage,
-            Err(_) => {
-                unterminated_jobs.insert(job_name.to_string());
-                continue;
-            }
-        };
-        let max_cpu_sage = match record.get(11).unwrap().parse::<f64>() {
-            Ok(max_cpu_sage) => max_cpu_sage,
-            Err(_) => {
-                unterminated_jobs.insert(job_name.to_string());
-                continue;
-            }
-        };
+fn bucket_file(spill_dir: &str, prefix: &str, bucket: usize) -> String {
+    return format!("{}/{}{}.csv", spill_dir, prefix, bucket);
+}
 
-        let avg_mem_sage = match record.get(12).unwrap().parse::<f64>() {
-            Ok(avg_mem_sage) => avg_mem_sage,
-            Err(_) => {
-                unterminated_jobs.insert(job_name.to_string());
-                continue;
-            }
-        };
-        let max_mem_sage = match record.get(13).unwrap().parse::<f64>() {
-            Ok(max_mem_sage) => max_mem_sage,
-            Err(_) => {
-                unterminated_jobs.insert(job_name.to_string());
+// single pass over trace file, rows of one job always get into the same bucket
+fn spill_by_job(
+    filename: &str,
+    has_header: bool,
+    job_column: usize,
+    spill_dir: &str,
+    prefix: &str,
+    buckets: usize,
+) -> Result<()> {
+    let mut writers = Vec::new();
+    for bucket in 0..buckets {
+        let bucket_name = bucket_file(spill_dir, prefix, bucket);
+        writers.push(
+            csv::WriterBuilder::new()
+                .flexible(true)
+                .from_path(&bucket_name)
+                .map_err(|why| Error::Csv {
+                    file: bucket_name,
+                    source: why,
+                })?,
+        );
+    }
+    let to_error = |why: csv::Error| Error::Csv {
+        file: filename.to_string(),
+        source: why,
+    };
+
+    let mut rdr = open_csv(filename, has_header)?;
+    let mut lines: u64 = 0;
+    for result in rdr.records() {
+        lines += 1;
+        if lines % 10000000 == 0 {
+            println!("{}: spilled lines {}", prefix, lines);
+        }
+        let record = match result {
+            Ok(record) => record,
+            Err(why) => {
+                eprintln!("skip {}", to_error(why));
                 continue;
             }
         };
-
-        match graph.node_weight_mut(*task_ind) {
+        let job_name = match record.get(job_column) {
+            Some(job_name) => job_name,
             None => {
-                panic!(
-                    "somehow job {} has no correct index for task {}: {:?}",
-                    job_name, task_name, task_ind
+                eprintln!(
+                    "skip {}",
+                    record_error(&record, format!("no column {}", job_column))
                 );
+                continue;
             }
+        };
+        writers[job_bucket(job_name, buckets)]
+            .write_record(&record)
+            .map_err(|why| Error::Csv {
+                file: spill_dir.to_string(),
+                source: why,
+            })?;
+    }
+    for writer in writers.iter_mut() {
+        writer.flush().map_err(|why| Error::io(spill_dir, why))?;
+    }
+    return Ok(());
+}
 
-            Some(task_info) => task_info.instances.push(Instance {
-                time: ins_duraction,
-                cpu_avg: avg_cpu_sage,
-                cpu_diff_max: max_cpu_sage - avg_cpu_sage,
-                mem_avg: avg_mem_sage,
-                mem_diff_max: max_mem_sage - avg_mem_sage,
-            }),
+// Whole trace is partitioned by job into spill files, then buckets are processed
+// one by one, so memory is bounded by the biggest bucket instead of whole trace.
+// Spill files go to a fresh subdirectory of spill_dir, only it is removed afterwards
+pub fn ingest_stream(
+    schema: &TraceSchema,
+    batch_task_file: &str,
+    batch_instance_file: &str,
+    spill_dir: &str,
+    buckets: usize,
+    sink: &mut dyn JobSink,
+) -> Result<()> {
+    fs::create_dir_all(spill_dir).map_err(|why| Error::io(spill_dir, why))?;
+    let spill_dir = format!("{}/cursach_spill_{}", spill_dir, std::process::id());
+    let spill_dir = spill_dir.as_str();
+    fs::create_dir(spill_dir).map_err(|why| Error::io(spill_dir, why))?;
+    spill_by_job(
+        batch_task_file,
+        schema.has_header,
+        schema.task.job,
+        spill_dir,
+        "task",
+        buckets,
+    )?;
+    spill_by_job(
+        batch_instance_file,
+        schema.has_header,
+        schema.instance.job,
+        spill_dir,
+        "instance",
+        buckets,
+    )?;
+
+    for bucket in 0..buckets {
+        let task_file = bucket_file(spill_dir, "task", bucket);
+        let instance_file = bucket_file(spill_dir, "instance", bucket);
+
+        let (jobs, mut task_to_index) = read_tasks(&task_file, schema)?;
+        let jobs_with_instances =
+            read_instances(schema, &jobs, &mut task_to_index, &instance_file)?;
+        drop(jobs);
+
+        let mut jobs_with_instances: Vec<(String, InstDag)> =
+            jobs_with_instances.into_iter().collect();
+        jobs_with_instances.sort_by(|a, b| a.0.cmp(&b.0));
+        for (job_name, job) in jobs_with_instances.into_iter() {
+            let dag = to_pure_dag(&job);
+            sink.job(job_name, dag)?;
         }
+        sink.bucket_done(bucket)?;
+
+        fs::remove_file(&task_file).map_err(|why| Error::io(&task_file, why))?;
+        fs::remove_file(&instance_file).map_err(|why| Error::io(&instance_file, why))?;
     }
-    println!("unterminated: {}", unterminated_jobs.len());
-    for unterminate_job in unterminated_jobs.iter() {
-        jobs_with_instances.remove(unterminate_job);
-        task_to_index.remove(unterminate_job);
-    }
+    fs::remove_dir_all(spill_dir).map_err(|why| Error::io(spill_dir, why))?;
+    return Ok(());
+}
 
-    println!("Stayed jobs: {}", jobs_with_instances.len());
-    let j = serde_json::to_string(&jobs_with_instances).unwrap();
+pub struct AlibabaSource {
+    pub schema: TraceSchema,
+    pub batch_task_file: String,
+    pub batch_instance_file: String,
+    pub spill_dir: String,
+    pub buckets: usize,
+}
 
-    let path = Path::new(INS_INPUT_FILENAME);
-    let mut file = match File::create(&path) {
-        Err(why) => panic!("cant open file to write {}", why),
-        Ok(file) => file,
-    };
+impl TraceSource for AlibabaSource {
+    fn ingest_stream(&self, sink: &mut dyn JobSink) -> Result<()> {
+        return ingest_stream(
+            &self.schema,
+            &self.batch_task_file,
+            &self.batch_instance_file,
+            &self.spill_dir,
+            self.buckets,
+            sink,
+        );
+    }
+}
 
-    match file.write_all(j.as_bytes()) {
-        Err(why) => panic!("cant save serialization {}", why),
-        Ok(_) => {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pure_dag::PureDags;
+    use std::path::Path;
+
+    #[test]
+    fn spill_dir_content_is_kept() {
+        let dir = format!("{}/cursach_spill_test", std::env::temp_dir().display());
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        let task_file = format!("{}/batch_task.csv", dir);
+        let instance_file = format!("{}/batch_instance.csv", dir);
+        fs::write(
+            &task_file,
+            "M1,2,j_1,1,Terminated,100,200\nR2_1,1,j_1,1,Terminated,200,300\n",
+        )
+        .unwrap();
+        fs::write(
+            &instance_file,
+            "i1,M1,j_1,1,Terminated,100,150,m1,1,1,50,60,0.5,0.6\n\
+             i2,M1,j_1,1,Terminated,100,190,m1,1,1,50,60,0.5,0.6\n\
+             i3,R2_1,j_1,1,Terminated,200,290,m1,1,1,80,90,0.7,0.8\n",
+        )
+        .unwrap();
+
+        let mut dags = PureDags::new();
+        let schema = TraceSchema::alibaba();
+        ingest_stream(&schema, &task_file, &instance_file, &dir, 4, &mut dags).unwrap();
+        assert_eq!(dags.dags["j_1"].node_count(), 2);
+        assert_eq!(dags.dags["j_1"].edge_count(), 1);
+        // the trace itself is in the spill dir, only the own subdirectory is removed
+        assert!(Path::new(&task_file).exists());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        fs::remove_dir_all(dir).unwrap();
     }
 }
@@ -0,0 +1,228 @@
+use crate::error::Result;
+use crate::pure_dag::*;
+use crate::series_parallel::is_series_parallel;
+use crate::source::JobSink;
+use crate::structure::StructureMetrics;
+use petgraph::stable_graph::NodeIndex;
+use petgraph::Direction::{Incoming, Outgoing};
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GraphType {
+    Chain,
+    TreeIncr,
+    TreeDecr,
+    Diamond,
+    ForkJoin,
+    LayeredBipartite,
+    SeriesParallel,
+    // general dags, none of the above
+    Other,
+}
+
+// in the order of checks in classify_dag, more specific first
+pub const ALL_GRAPH_TYPES: [GraphType; 8] = [
+    GraphType::Chain,
+    GraphType::TreeIncr,
+    GraphType::TreeDecr,
+    GraphType::Diamond,
+    GraphType::ForkJoin,
+    GraphType::LayeredBipartite,
+    GraphType::SeriesParallel,
+    GraphType::Other,
+];
+
+impl GraphType {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            GraphType::Chain => "chain",
+            GraphType::TreeIncr => "tree_incr",
+            GraphType::TreeDecr => "tree_decr",
+            GraphType::Diamond => "diamond",
+            GraphType::ForkJoin => "fork_join",
+            GraphType::LayeredBipartite => "layered_bipartite",
+            GraphType::SeriesParallel => "series_parallel",
+            GraphType::Other => "other",
+        };
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        return ALL_GRAPH_TYPES.iter().find(|x| x.name() == name).copied();
+    }
+
+    // prefix of alib_art output files
+    pub fn short_name(&self) -> &'static str {
+        return match self {
+            GraphType::TreeIncr => "incr",
+            GraphType::TreeDecr => "decr",
+            _ => self.name(),
+        };
+    }
+}
+
+fn in_degree(graph: &PureDag, ind: NodeIndex) -> usize {
+    return graph.neighbors_directed(ind, Incoming).count();
+}
+
+fn out_degree(graph: &PureDag, ind: NodeIndex) -> usize {
+    return graph.neighbors_directed(ind, Outgoing).count();
+}
+
+// one source forks into parallel chains that meet in one sink, chains may be
+// before the fork and after the join
+fn is_fork_join(graph: &PureDag) -> bool {
+    let forks = graph
+        .node_indices()
+        .filter(|x| out_degree(graph, *x) > 1)
+        .count();
+    let joins = graph
+        .node_indices()
+        .filter(|x| in_degree(graph, *x) > 1)
+        .count();
+    return forks == 1 && joins == 1 && graph.sources() == 1 && graph.sinks() == 1;
+}
+
+// fork-join without chains: source, one level of tasks, sink
+fn is_diamond(graph: &PureDag) -> bool {
+    if !is_fork_join(graph) {
+        return false;
+    }
+    return match graph.width_profile() {
+        Ok(widths) => widths.len() == 3,
+        Err(_) => false,
+    };
+}
+
+// edges only between neighbour levels, each of them is complete bipartite graph
+fn is_layered_bipartite(graph: &PureDag) -> bool {
+    let levels = match graph.levels() {
+        Ok(levels) => levels,
+        Err(_) => return false,
+    };
+    let mut widths = vec![0; levels.iter().max().map(|x| x + 1).unwrap_or(0)];
+    for level in levels.iter() {
+        widths[*level] += 1;
+    }
+    if widths.iter().all(|x| *x == 1) {
+        return false;
+    }
+    for ind in graph.node_indices() {
+        let level = levels[ind.index()];
+        let mut children: Vec<NodeIndex> = graph.neighbors_directed(ind, Outgoing).collect();
+        children.sort();
+        children.dedup();
+        if children.iter().any(|x| levels[x.index()] != level + 1) {
+            return false;
+        }
+        if level + 1 < widths.len() && children.len() != widths[level + 1] {
+            return false;
+        }
+    }
+    return true;
+}
+
+pub fn classify_dag(graph: &PureDag) -> GraphType {
+    let node_cnt = graph.node_count();
+    let mut depths = vec![0; node_cnt];
+    let mut used = vec![0; node_cnt];
+
+    let mut is_tree = true;
+    let mut is_rev_tree = true;
+    let mut is_chain = true;
+
+    for ind in graph.node_indices() {
+        let depend_len = graph.node_weight(ind).unwrap().dependences.len();
+        if depend_len == 0 {
+            if graph.dfs(ind, &mut depths, &mut used, &mut is_tree) != 0 {}
+        } else if depend_len > 1 {
+            is_chain = false;
+        }
+        match graph.neighbors(ind).count() {
+            0..=1 => {}
+            _ => {
+                is_rev_tree = false;
+                is_chain = false;
+            }
+        }
+    }
+    if is_chain {
+        return GraphType::Chain;
+    }
+    if is_tree {
+        return GraphType::TreeIncr;
+    }
+    if is_rev_tree {
+        return GraphType::TreeDecr;
+    }
+    if is_diamond(graph) {
+        return GraphType::Diamond;
+    }
+    if is_fork_join(graph) {
+        return GraphType::ForkJoin;
+    }
+    if is_layered_bipartite(graph) {
+        return GraphType::LayeredBipartite;
+    }
+    if is_series_parallel(graph) {
+        return GraphType::SeriesParallel;
+    }
+    return GraphType::Other;
+}
+
+pub fn classify(jobs: PureDags) -> HashMap<GraphType, PureDags> {
+    let mut result: HashMap<GraphType, PureDags> = ALL_GRAPH_TYPES
+        .iter()
+        .map(|graph_type| (*graph_type, PureDags::new()))
+        .collect();
+
+    for (job_name, graph) in jobs.dags.into_iter() {
+        let graph_type = classify_dag(&graph);
+        result.get_mut(&graph_type).unwrap().insert(job_name, graph);
+    }
+    return result;
+}
+
+// classifies jobs as they come from ingestion, every bucket is saved as its own part
+pub struct ClassifySink {
+    output_dir: String,
+    by_type: HashMap<GraphType, PureDags>,
+}
+
+impl ClassifySink {
+    pub fn new(output_dir: &str) -> Self {
+        return ClassifySink {
+            output_dir: output_dir.to_string(),
+            by_type: ALL_GRAPH_TYPES
+                .iter()
+                .map(|graph_type| (*graph_type, PureDags::new()))
+                .collect(),
+        };
+    }
+}
+
+impl JobSink for ClassifySink {
+    fn job(&mut self, job_name: String, dag: PureDag) -> Result<()> {
+        let graph_type = classify_dag(&dag);
+        self.by_type
+            .get_mut(&graph_type)
+            .unwrap()
+            .insert(job_name, dag);
+        return Ok(());
+    }
+
+    fn bucket_done(&mut self, bucket: usize) -> Result<()> {
+        for graph_type in ALL_GRAPH_TYPES.iter() {
+            let jobs_container = self.by_type.insert(*graph_type, PureDags::new()).unwrap();
+            println!(
+                "{}: {} has {} dags",
+                bucket,
+                graph_type.name(),
+                jobs_container.dags.len()
+            );
+            jobs_container.save_to_file(
+                format!("{}/{}{}.json", self.output_dir, graph_type.name(), bucket).as_str(),
+            )?;
+        }
+        return Ok(());
+    }
+}
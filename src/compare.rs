@@ -0,0 +1,557 @@
+use crate::classify::GraphType;
+use crate::dependency::DependencyPattern;
+use crate::dispersion::coefficient_of_variation;
+use crate::error::{read_dir_sorted, read_file, write_file, Error, Result};
+use crate::generator::seeded_rng;
+use crate::pipeline::{dag_name, generated_names, SAMPLE_CP_RANGES};
+use crate::pure_dag::*;
+use crate::series_parallel::{make_series_parallel, SpDecomposition};
+use crate::structure::{DagShape, StructureMetrics};
+use crate::task_dag::*;
+use serde::{Deserialize, Serialize};
+use std::cell::OnceCell;
+use std::path::Path;
+
+// histogram bins for Jensen-Shannon distance
+const JS_BINS: usize = 20;
+
+// task dags without instances are expanded with this seed, so they measure the same every run
+const EXPANSION_SEED: u64 = 0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Metric {
+    Sparity,
+    InDeg,
+    OutDeg,
+    ChainRatio,
+    InsRatio,
+    TimeRatio,
+    NarrowDependen,
+    UnionDependen,
+    WideDependen,
+    WidthProfile,
+    LongestPath,
+    AvgPathLength,
+    Sources,
+    Sinks,
+    TredEdges,
+    JumpRatio,
+    FanInGini,
+    FanOutGini,
+    Ccr,
+    Parallelism,
+    Regularity,
+    SpDepth,
+    SpBranching,
+    SpRemoved,
+    InstanceSpread,
+}
+
+// names are the same as file suffixes in get_dag_stat
+pub const ALL_METRICS: [Metric; 25] = [
+    Metric::Sparity,
+    Metric::InDeg,
+    Metric::OutDeg,
+    Metric::ChainRatio,
+    Metric::InsRatio,
+    Metric::TimeRatio,
+    Metric::NarrowDependen,
+    Metric::UnionDependen,
+    Metric::WideDependen,
+    Metric::WidthProfile,
+    Metric::LongestPath,
+    Metric::AvgPathLength,
+    Metric::Sources,
+    Metric::Sinks,
+    Metric::TredEdges,
+    Metric::JumpRatio,
+    Metric::FanInGini,
+    Metric::FanOutGini,
+    Metric::Ccr,
+    Metric::Parallelism,
+    Metric::Regularity,
+    Metric::SpDepth,
+    Metric::SpBranching,
+    Metric::SpRemoved,
+    Metric::InstanceSpread,
+];
+
+impl Metric {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Metric::Sparity => "sparity",
+            Metric::InDeg => "in_deg",
+            Metric::OutDeg => "out_deg",
+            Metric::ChainRatio => "chain_ration",
+            Metric::InsRatio => "ins_ratio",
+            Metric::TimeRatio => "time_ratio",
+            Metric::NarrowDependen => "narrow_dependen",
+            Metric::UnionDependen => "union_dependen",
+            Metric::WideDependen => "wide_dependen",
+            Metric::WidthProfile => "width_profile",
+            Metric::LongestPath => "longest_path",
+            Metric::AvgPathLength => "avg_path_len",
+            Metric::Sources => "sources",
+            Metric::Sinks => "sinks",
+            Metric::TredEdges => "tred_edges",
+            Metric::JumpRatio => "jump_ratio",
+            Metric::FanInGini => "fan_in_gini",
+            Metric::FanOutGini => "fan_out_gini",
+            Metric::Ccr => "ccr",
+            Metric::Parallelism => "parallelism",
+            Metric::Regularity => "regularity",
+            Metric::SpDepth => "sp_depth",
+            Metric::SpBranching => "sp_branching",
+            Metric::SpRemoved => "sp_removed",
+            Metric::InstanceSpread => "instance_spread",
+        };
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        return ALL_METRICS.iter().find(|x| x.name() == name).copied();
+    }
+
+    pub fn values(&self, measured: &MeasuredDag) -> Result<Vec<f64>> {
+        let graph = measured.graph;
+        let values = match self {
+            Metric::Sparity => vec![graph.sparity()],
+            Metric::InDeg => graph.in_degree().iter().map(|x| *x as f64).collect(),
+            Metric::OutDeg => graph.out_degree().iter().map(|x| *x as f64).collect(),
+            Metric::ChainRatio => vec![graph.chain_ratio()],
+            Metric::InsRatio => graph.pairwise_ins_ration(),
+            Metric::TimeRatio => graph.pairwise_flops_ration(),
+            // union is narrow dependency in spark too
+            Metric::NarrowDependen => {
+                vec![graph.dependency_ratio(|x| !x.is_wide())]
+            }
+            Metric::UnionDependen => {
+                vec![graph.dependency_ratio(|x| x == DependencyPattern::Union)]
+            }
+            Metric::WideDependen => vec![graph.dependency_ratio(|x| x.is_wide())],
+            Metric::WidthProfile => graph.width_profile()?.iter().map(|x| *x as f64).collect(),
+            Metric::LongestPath => vec![graph.longest_path()? as f64],
+            Metric::AvgPathLength => vec![graph.average_path_length()?],
+            Metric::Sources => vec![graph.sources() as f64],
+            Metric::Sinks => vec![graph.sinks() as f64],
+            Metric::TredEdges => vec![graph.transitive_reduction_edges()? as f64],
+            Metric::JumpRatio => vec![graph.jump_edge_ratio()?],
+            Metric::FanInGini => vec![graph.fan_in_gini()],
+            Metric::FanOutGini => vec![graph.fan_out_gini()],
+            Metric::Ccr => vec![measured.shape()?.ccr],
+            Metric::Parallelism => vec![measured.shape()?.parallelism],
+            Metric::Regularity => vec![measured.shape()?.regularity],
+            // dags which are not series-parallel are measured after removing edges
+            Metric::SpDepth => vec![measured.series_parallel()?.tree.nesting_depth() as f64],
+            Metric::SpBranching => measured
+                .series_parallel()?
+                .tree
+                .branching()
+                .iter()
+                .map(|x| *x as f64)
+                .collect(),
+            Metric::SpRemoved => {
+                let removed = measured.series_parallel()?.removed.len();
+                vec![removed as f64 / graph.edge_count().max(1) as f64]
+            }
+            // coefficient of variation of instance work inside tasks with several instances
+            Metric::InstanceSpread => measured
+                .instance_work()
+                .iter()
+                .filter_map(|x| coefficient_of_variation(x))
+                .collect(),
+        };
+        return Ok(values);
+    }
+}
+
+// Graph with measures several metrics share, each of them is computed once when first needed
+pub struct MeasuredDag<'a> {
+    pub graph: &'a TaskDag,
+    shape: OnceCell<DagShape>,
+    series_parallel: OnceCell<SpDecomposition>,
+    // work of every instance by task
+    instance_work: OnceCell<Vec<Vec<f64>>>,
+}
+
+// times of real instances by task
+pub fn instance_times(pure_dag: &PureDag) -> Vec<Vec<f64>> {
+    return pure_dag
+        .node_weights()
+        .map(|x| x.instances.iter().map(|x| x.time as f64).collect())
+        .collect();
+}
+
+impl<'a> MeasuredDag<'a> {
+    pub fn new(graph: &'a TaskDag) -> Self {
+        return MeasuredDag {
+            graph: graph,
+            shape: OnceCell::new(),
+            series_parallel: OnceCell::new(),
+            instance_work: OnceCell::new(),
+        };
+    }
+
+    // real dags are measured by their instances instead of expansion
+    pub fn with_instance_work(self, work: Vec<Vec<f64>>) -> Self {
+        return MeasuredDag {
+            instance_work: OnceCell::from(work),
+            ..self
+        };
+    }
+
+    fn shape(&self) -> Result<&DagShape> {
+        if let Some(shape) = self.shape.get() {
+            return Ok(shape);
+        }
+        let shape = self.graph.shape()?;
+        return Ok(self.shape.get_or_init(|| shape));
+    }
+
+    fn series_parallel(&self) -> Result<&SpDecomposition> {
+        if let Some(decomposition) = self.series_parallel.get() {
+            return Ok(decomposition);
+        }
+        let decomposition = make_series_parallel(self.graph)?;
+        return Ok(self.series_parallel.get_or_init(|| decomposition));
+    }
+
+    // flops convert_to_inst_dag samples, instances of a task are added one after another
+    fn instance_work(&self) -> &Vec<Vec<f64>> {
+        return self.instance_work.get_or_init(|| {
+            let instance_dag = self
+                .graph
+                .convert_to_inst_dag(&mut seeded_rng(EXPANSION_SEED), None);
+            let mut flops = instance_dag.node_weights().map(|x| x.flops);
+            return self
+                .graph
+                .node_weights()
+                .map(|x| {
+                    flops
+                        .by_ref()
+                        .take(x.instance_cnt.min(INSTANCE_CAP) as usize)
+                        .collect()
+                })
+                .collect();
+        });
+    }
+}
+
+// Wasserstein distance is divided by the range of both samples together, so one
+// threshold fits all metrics. Every distance is in [0, 1]
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Thresholds {
+    pub ks: f64,
+    pub wasserstein: f64,
+    pub js: f64,
+}
+
+impl Thresholds {
+    pub fn default() -> Self {
+        return Thresholds {
+            ks: 0.2,
+            wasserstein: 0.1,
+            js: 0.2,
+        };
+    }
+
+    pub fn load_from_file(filename: &str) -> Result<Self> {
+        let contents = read_file(filename)?;
+        let thresholds: Thresholds =
+            serde_json::from_str(&contents).map_err(|why| Error::json(filename, why))?;
+        return Ok(thresholds);
+    }
+}
+
+fn sorted(values: &[f64]) -> Vec<f64> {
+    let mut result: Vec<f64> = values.iter().copied().filter(|x| x.is_finite()).collect();
+    result.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    return result;
+}
+
+// fraction of sorted values that are <= x
+fn cdf(values: &[f64], x: f64) -> f64 {
+    return values.partition_point(|v| *v <= x) as f64 / values.len() as f64;
+}
+
+// metric without values on both sides matches, on one side it doesn't
+fn empty_distance(real: &[f64], generated: &[f64]) -> Option<f64> {
+    return match (real.is_empty(), generated.is_empty()) {
+        (true, true) => Some(0.0),
+        (false, false) => None,
+        _ => Some(1.0),
+    };
+}
+
+pub fn ks_distance(real: &[f64], generated: &[f64]) -> f64 {
+    let real = sorted(real);
+    let generated = sorted(generated);
+    if let Some(distance) = empty_distance(&real, &generated) {
+        return distance;
+    }
+    return real
+        .iter()
+        .chain(generated.iter())
+        .map(|x| (cdf(&real, *x) - cdf(&generated, *x)).abs())
+        .fold(0.0, f64::max);
+}
+
+// area between empirical cdfs
+pub fn wasserstein_distance(real: &[f64], generated: &[f64]) -> f64 {
+    let real = sorted(real);
+    let generated = sorted(generated);
+    if let Some(distance) = empty_distance(&real, &generated) {
+        return distance;
+    }
+    let mut points: Vec<f64> = real.iter().chain(generated.iter()).copied().collect();
+    points.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let range = points[points.len() - 1] - points[0];
+    if range == 0.0 {
+        return 0.0;
+    }
+    let mut result = 0.0;
+    for pair in points.windows(2) {
+        result += (cdf(&real, pair[0]) - cdf(&generated, pair[0])).abs() * (pair[1] - pair[0]);
+    }
+    return result / range;
+}
+
+// square root of Jensen-Shannon divergence with log base 2 over shared histogram
+pub fn js_distance(real: &[f64], generated: &[f64]) -> f64 {
+    let real = sorted(real);
+    let generated = sorted(generated);
+    if let Some(distance) = empty_distance(&real, &generated) {
+        return distance;
+    }
+    let low = real[0].min(generated[0]);
+    let high = real[real.len() - 1].max(generated[generated.len() - 1]);
+    let histogram = |values: &Vec<f64>| {
+        let mut bins = vec![0.0; JS_BINS];
+        for x in values.iter() {
+            let ind = if high == low {
+                0
+            } else {
+                (((x - low) / (high - low) * JS_BINS as f64) as usize).min(JS_BINS - 1)
+            };
+            bins[ind] += 1.0 / values.len() as f64;
+        }
+        bins
+    };
+    let p = histogram(&real);
+    let q = histogram(&generated);
+    let mut divergence = 0.0;
+    for (p, q) in p.iter().zip(q.iter()) {
+        let m = (p + q) / 2.0;
+        if *p > 0.0 {
+            divergence += p * (p / m).log2() / 2.0;
+        }
+        if *q > 0.0 {
+            divergence += q * (q / m).log2() / 2.0;
+        }
+    }
+    return divergence.max(0.0).sqrt();
+}
+
+// values of every metric, for every cp range and the last one for all dags
+struct Samples {
+    values: Vec<Vec<Vec<f64>>>,
+    dags_cnt: Vec<usize>,
+}
+
+impl Samples {
+    fn new() -> Self {
+        return Samples {
+            values: vec![vec![Vec::new(); ALL_METRICS.len()]; SAMPLE_CP_RANGES.len() + 1],
+            dags_cnt: vec![0; SAMPLE_CP_RANGES.len() + 1],
+        };
+    }
+
+    fn add(&mut self, measured: &MeasuredDag) -> Result<()> {
+        // longest path in tasks is critical path of pure dag
+        let cp = measured.graph.longest_path()? as u32;
+        let mut groups = vec![SAMPLE_CP_RANGES.len()];
+        for (ind, cp_range) in SAMPLE_CP_RANGES.iter().enumerate() {
+            if cp_range.0 <= cp && cp <= cp_range.1 {
+                groups.push(ind);
+            }
+        }
+        let mut metric_values = Vec::new();
+        for metric in ALL_METRICS.iter() {
+            metric_values.push(metric.values(measured)?);
+        }
+        for group in groups {
+            self.dags_cnt[group] += 1;
+            for (values, graph_values) in self.values[group].iter_mut().zip(metric_values.iter()) {
+                values.extend(graph_values.iter());
+            }
+        }
+        return Ok(());
+    }
+}
+
+fn range_name(group: usize) -> String {
+    return match SAMPLE_CP_RANGES.get(group) {
+        Some(cp_range) => format!("{}_{}", cp_range.0, cp_range.1),
+        None => String::from("all"),
+    };
+}
+
+fn real_samples(real_dir: &str, graph_type: GraphType) -> Result<Samples> {
+    let mut samples = Samples::new();
+    for path in read_dir_sorted(real_dir)? {
+        if !path.contains(graph_type.name()) {
+            continue;
+        }
+        let pure_dags = PureDags::get_from_file(path.as_str())?;
+        for (job_name, graph) in pure_dags.dags.iter() {
+            let added = <TaskDag as TaskDagFuncs>::from_pure_dag(graph).and_then(|task_dag| {
+                samples.add(&MeasuredDag::new(&task_dag).with_instance_work(instance_times(graph)))
+            });
+            if let Err(err) = added {
+                eprintln!("skip {}", err.in_job(job_name));
+            }
+        }
+    }
+    return Ok(samples);
+}
+
+// real samples exported to the same directory are skipped, see MANIFEST_FILENAME
+fn generated_samples(tasks_dir: &str) -> Result<Samples> {
+    let mut samples = Samples::new();
+    let generated = generated_names(tasks_dir)?;
+    for path in read_dir_sorted(tasks_dir)? {
+        if !path.ends_with(".json") || !generated.contains(&dag_name(&path)) {
+            continue;
+        }
+        let mut task_dag = TaskDag::new();
+        let added = task_dag
+            .load_from_file(path.as_str())
+            .and_then(|_| samples.add(&MeasuredDag::new(&task_dag)));
+        if let Err(err) = added {
+            eprintln!("skip {}", err);
+        }
+    }
+    return Ok(samples);
+}
+
+// Csv report with a row for every graph type, cp range and metric. Groups without real
+// or generated dags are left out
+pub fn compare_dags(
+    real_dir: &str,
+    work_root: &str,
+    graph_types: &[GraphType],
+    thresholds: &Thresholds,
+    output_file: &str,
+) -> Result<()> {
+    let mut str_bufer = String::from(
+        "graph_type,cp_range,metric,real_dags,gen_dags,real_values,gen_values,ks,wasserstein,js,pass\n",
+    );
+    let mut failed = 0;
+    for graph_type in graph_types.iter() {
+        let tasks_dir = format!("{}/{}/tasks", work_root, graph_type.name());
+        if !Path::new(&tasks_dir).exists() {
+            continue;
+        }
+        let real = real_samples(real_dir, *graph_type)?;
+        let generated = match generated_samples(&tasks_dir) {
+            Ok(generated) => generated,
+            Err(err) => {
+                eprintln!("skip {}", err);
+                continue;
+            }
+        };
+        for group in 0..=SAMPLE_CP_RANGES.len() {
+            if real.dags_cnt[group] == 0 || generated.dags_cnt[group] == 0 {
+                continue;
+            }
+            for (ind, metric) in ALL_METRICS.iter().enumerate() {
+                let real_values = &real.values[group][ind];
+                let gen_values = &generated.values[group][ind];
+                let ks = ks_distance(real_values, gen_values);
+                let wasserstein = wasserstein_distance(real_values, gen_values);
+                let js = js_distance(real_values, gen_values);
+                let pass = ks <= thresholds.ks
+                    && wasserstein <= thresholds.wasserstein
+                    && js <= thresholds.js;
+                if !pass {
+                    failed += 1;
+                }
+                str_bufer.push_str(&format!(
+                    "{},{},{},{},{},{},{},{},{},{},{}\n",
+                    graph_type.name(),
+                    range_name(group),
+                    metric.name(),
+                    real.dags_cnt[group],
+                    generated.dags_cnt[group],
+                    real_values.len(),
+                    gen_values.len(),
+                    ks,
+                    wasserstein,
+                    js,
+                    pass
+                ));
+            }
+        }
+    }
+    println!("failed comparisons: {}", failed);
+    return write_file(output_file, &str_bufer);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dispersion::Dispersion;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn empty_samples() {
+        let values = [1.0, 2.0, 3.0];
+        for distance in [ks_distance, wasserstein_distance, js_distance] {
+            assert_eq!(distance(&[], &[]), 0.0);
+            assert_eq!(distance(&values, &[]), 1.0);
+            assert_eq!(distance(&[], &values), 1.0);
+            assert_eq!(distance(&values, &values), 0.0);
+        }
+    }
+
+    fn two_tasks(dispersion: Dispersion) -> TaskDag {
+        let mut dag = TaskDag::new();
+        for instance_cnt in [1, 30] {
+            dag.add_node(DagVertex {
+                task_name: format!("t{}", instance_cnt),
+                dependencies: Vec::new(),
+                instance_cnt: instance_cnt,
+                flops: 10.0,
+                cpu: DEFAULT_CPU,
+                mem: DEFAULT_MEM,
+                dispersion: dispersion,
+                patterns: BTreeMap::new(),
+            });
+        }
+        return dag;
+    }
+
+    #[test]
+    fn instance_spread_is_measured_on_instances() {
+        let spread = |measured: &MeasuredDag| Metric::InstanceSpread.values(measured).unwrap();
+
+        let uniform = two_tasks(Dispersion::default());
+        assert_eq!(spread(&MeasuredDag::new(&uniform)), vec![0.0]);
+
+        let dispersed = two_tasks(Dispersion {
+            cv: 0.5,
+            straggler_prob: 0.0,
+            straggler_magnitude: 0.0,
+        });
+        let values = spread(&MeasuredDag::new(&dispersed));
+        assert_eq!(values.len(), 1);
+        assert!(values[0] > 0.2 && values[0] < 1.0);
+
+        // the fitted dispersion is not used for real dags
+        let real = MeasuredDag::new(&dispersed).with_instance_work(vec![
+            vec![5.0],
+            vec![1.0, 3.0],
+            vec![0.0, 0.0],
+        ]);
+        assert_eq!(spread(&real), vec![0.5]);
+    }
+}
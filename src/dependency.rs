@@ -0,0 +1,414 @@
+use crate::statistic::*;
+use crate::task_dag::*;
+use petgraph::stable_graph::NodeIndex;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+// parent sending less than this share of child input beside other parents is broadcast
+pub const BROADCAST_SHARE: f64 = 0.1;
+pub const DEFAULT_FAN_IN: u64 = 2;
+
+// How instances of parent are linked with instances of child in convert_to_inst_dag
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyPattern {
+    // map, filter: i-th to i-th
+    OneToOne,
+    // several parents, their instances together are the instances of child
+    Union,
+    // groupByKey: every instance of parent to every instance of child
+    AllToAll,
+    // sorted output of parent is cut into ranges, instances exchange overlapping parts
+    RangePartition,
+    // every child instance reads its keys from fan_in parent instances
+    HashPartition { fan_in: u64 },
+    // every child instance gets the whole output, small side of a join
+    Broadcast,
+    // several parent instances are merged into one child instance without shuffle
+    Coalesce,
+}
+
+pub const ALL_DEPENDENCY_PATTERNS: [DependencyPattern; 7] = [
+    DependencyPattern::OneToOne,
+    DependencyPattern::Union,
+    DependencyPattern::AllToAll,
+    DependencyPattern::RangePartition,
+    DependencyPattern::HashPartition {
+        fan_in: DEFAULT_FAN_IN,
+    },
+    DependencyPattern::Broadcast,
+    DependencyPattern::Coalesce,
+];
+
+impl DependencyPattern {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            DependencyPattern::OneToOne => "one_to_one",
+            DependencyPattern::Union => "union",
+            DependencyPattern::AllToAll => "all_to_all",
+            DependencyPattern::RangePartition => "range_partition",
+            DependencyPattern::HashPartition { .. } => "hash_partition",
+            DependencyPattern::Broadcast => "broadcast",
+            DependencyPattern::Coalesce => "coalesce",
+        };
+    }
+
+    // hash_partition gets DEFAULT_FAN_IN
+    pub fn from_name(name: &str) -> Option<Self> {
+        return ALL_DEPENDENCY_PATTERNS
+            .iter()
+            .find(|x| x.name() == name)
+            .copied();
+    }
+
+    // union needs other parents to fill the child, one_to_one the same instance counts
+    pub fn fits_edge(&self, parent_cnt: u64, child_cnt: u64, several_parents: bool) -> bool {
+        return match self {
+            DependencyPattern::Union => several_parents,
+            DependencyPattern::OneToOne => {
+                parent_cnt.min(INSTANCE_CAP) == child_cnt.min(INSTANCE_CAP)
+            }
+            _ => true,
+        };
+    }
+
+    // needs a shuffle between parent and child
+    pub fn is_wide(&self) -> bool {
+        return matches!(
+            self,
+            DependencyPattern::AllToAll
+                | DependencyPattern::RangePartition
+                | DependencyPattern::HashPartition { .. }
+        );
+    }
+
+    // (parent instance, child instance, share of task edge data) for every instance edge.
+    // Union fills child instances from offset on. Shares sum to 1, broadcast sends the
+    // whole data to every child instance
+    pub fn links<R: Rng>(
+        &self,
+        parent_cnt: usize,
+        child_cnt: usize,
+        offset: usize,
+        rnd: &mut R,
+    ) -> Vec<(usize, usize, f64)> {
+        let mut result = Vec::new();
+        if parent_cnt == 0 || child_cnt == 0 {
+            return result;
+        }
+        let (p, c) = (parent_cnt, child_cnt);
+        match self {
+            DependencyPattern::OneToOne => {
+                for i in 0..p {
+                    result.push((i, i % c, 1.0 / p as f64));
+                }
+            }
+            DependencyPattern::Union => {
+                for i in 0..p {
+                    result.push((i, (offset + i) % c, 1.0 / p as f64));
+                }
+            }
+            DependencyPattern::AllToAll => {
+                for i in 0..p {
+                    for j in 0..c {
+                        result.push((i, j, 1.0 / (p * c) as f64));
+                    }
+                }
+            }
+            DependencyPattern::RangePartition => {
+                // in units of 1 / (p * c) parent i holds [i * c, (i + 1) * c),
+                // child j holds [j * p, (j + 1) * p)
+                for i in 0..p {
+                    for j in (i * c / p)..=((i + 1) * c - 1) / p {
+                        let overlap = ((i + 1) * c).min((j + 1) * p) - (i * c).max(j * p);
+                        result.push((i, j, overlap as f64 / (p * c) as f64));
+                    }
+                }
+            }
+            DependencyPattern::HashPartition { fan_in } => {
+                let fan_in = (*fan_in as usize).clamp(1, p);
+                for j in 0..c {
+                    for i in rand::seq::index::sample(rnd, p, fan_in).into_iter() {
+                        result.push((i, j, 1.0 / (c * fan_in) as f64));
+                    }
+                }
+            }
+            DependencyPattern::Broadcast => {
+                for i in 0..p {
+                    for j in 0..c {
+                        result.push((i, j, 1.0 / p as f64));
+                    }
+                }
+            }
+            DependencyPattern::Coalesce => {
+                for i in 0..p {
+                    result.push((i, i * c / p, 1.0 / p as f64));
+                }
+            }
+        }
+        return result;
+    }
+}
+
+// From instance counts only, parent_cnts are of all parents of child
+pub fn infer_pattern(parent_cnt: u64, child_cnt: u64, parent_cnts: &[u64]) -> DependencyPattern {
+    if parent_cnts.len() > 1 && parent_cnts.iter().sum::<u64>() == child_cnt {
+        return DependencyPattern::Union;
+    }
+    if parent_cnt == child_cnt {
+        return DependencyPattern::OneToOne;
+    }
+    return DependencyPattern::AllToAll;
+}
+
+// all_to_all of infer_pattern is refined from the trace. child_fan_ins has parent instances
+// read by every child instance, it is empty for traces linking tasks, not instances. Every
+// child instance reading the same part of parent instances is hash partition. A small
+// share of child input is the broadcast side of a join, a child starting before its parent
+// ends has no shuffle barrier, so it coalesces or splits parent output
+pub fn fit_pattern(
+    parent_cnt: u64,
+    child_cnt: u64,
+    parent_cnts: &[u64],
+    child_fan_ins: &[u64],
+    data_share: f64,
+    overlaps: bool,
+) -> DependencyPattern {
+    let inferred = infer_pattern(parent_cnt, child_cnt, parent_cnts);
+    if inferred != DependencyPattern::AllToAll {
+        return inferred;
+    }
+    if let Some(fan_in) = child_fan_ins.first() {
+        if child_fan_ins.iter().all(|x| x == fan_in) && *fan_in > 0 && *fan_in < parent_cnt {
+            return DependencyPattern::HashPartition { fan_in: *fan_in };
+        }
+    }
+    if parent_cnts.len() > 1 && data_share < BROADCAST_SHARE {
+        return DependencyPattern::Broadcast;
+    }
+    if overlaps {
+        if child_cnt < parent_cnt {
+            return DependencyPattern::Coalesce;
+        }
+        return DependencyPattern::RangePartition;
+    }
+    return DependencyPattern::AllToAll;
+}
+
+// Patterns given in task dag win, others are inferred from instance counts
+pub fn dependency_patterns(
+    dag: &TaskDag,
+    node_ind: NodeIndex,
+) -> Vec<(NodeIndex, DependencyPattern)> {
+    let node_info = dag.node_weight(node_ind).unwrap();
+    let instances = |ind: NodeIndex| dag.node_weight(ind).unwrap().instance_cnt.min(INSTANCE_CAP);
+    let parents: Vec<NodeIndex> = node_info
+        .dependencies
+        .iter()
+        .map(|x| NodeIndex::new(*x as usize))
+        .collect();
+    let parent_cnts: Vec<u64> = parents.iter().map(|x| instances(*x)).collect();
+
+    return parents
+        .iter()
+        .map(|parent| {
+            let pattern = match node_info.patterns.get(&(parent.index() as u32)) {
+                Some(pattern) => *pattern,
+                None => infer_pattern(instances(*parent), instances(node_ind), &parent_cnts),
+            };
+            (*parent, pattern)
+        })
+        .collect();
+}
+
+fn relation_name(child_to_parent: Ordering) -> &'static str {
+    return match child_to_parent {
+        Ordering::Less => "fewer",
+        Ordering::Equal => "equal",
+        Ordering::Greater => "more",
+    };
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PatternBucket {
+    // instances of child relative to parent: fewer, equal or more
+    pub relation: String,
+    pub several_parents: bool,
+    pub patterns: Vec<(DependencyPattern, u64)>,
+}
+
+pub struct PatternStatistic {
+    // relation of instance counts, several parents -> pattern -> edges with it
+    stat: HashMap<(&'static str, bool), HashMap<DependencyPattern, u64>>,
+    stat_result: Vec<PatternBucket>,
+}
+
+impl SaveToFile for PatternStatistic {}
+
+impl StatBase for PatternStatistic {
+    fn form_stats(&mut self) {
+        self.stat_result.clear();
+        for ((relation, several_parents), patterns) in self.stat.iter() {
+            let mut result: Vec<(DependencyPattern, u64)> =
+                patterns.iter().map(|(x, y)| (*x, *y)).collect();
+            result.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.name().cmp(b.0.name())));
+            self.stat_result.push(PatternBucket {
+                relation: relation.to_string(),
+                several_parents: *several_parents,
+                patterns: result,
+            });
+        }
+        self.stat_result.sort_by(|a, b| {
+            (&a.relation, a.several_parents).cmp(&(&b.relation, b.several_parents))
+        });
+    }
+    fn get_string_obj(&self) -> String {
+        return serde_json::to_string(&self.stat_result).unwrap();
+    }
+    fn load_obj_from_string(&mut self, str: String) -> serde_json::Result<()> {
+        self.stat_result = serde_json::from_str(&str)?;
+        return Ok(());
+    }
+}
+
+impl PatternStatistic {
+    pub fn new() -> Self {
+        return PatternStatistic {
+            stat: HashMap::new(),
+            stat_result: Vec::new(),
+        };
+    }
+
+    pub fn add(&mut self, graph: &TaskDag) {
+        for node_ind in graph.node_indices() {
+            let node_info = graph.node_weight(node_ind).unwrap();
+            let several_parents = node_info.dependencies.len() > 1;
+            for (parent, pattern) in dependency_patterns(graph, node_ind) {
+                let parent_cnt = graph.node_weight(parent).unwrap().instance_cnt;
+                let relation = relation_name(node_info.instance_cnt.cmp(&parent_cnt));
+                *self
+                    .stat
+                    .entry((relation, several_parents))
+                    .or_default()
+                    .entry(pattern)
+                    .or_insert(0) += 1;
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.stat_result.is_empty();
+    }
+
+    // The same relation with other number of parents if this one was never seen. Patterns
+    // which do not fit the edge are left out, all_to_all if none is left
+    pub fn sample<R: Rng>(
+        &self,
+        parent_cnt: u64,
+        child_cnt: u64,
+        several_parents: bool,
+        rnd: &mut R,
+    ) -> Option<DependencyPattern> {
+        let relation = relation_name(child_cnt.cmp(&parent_cnt));
+        let bucket = self
+            .stat_result
+            .iter()
+            .filter(|x| x.relation == relation)
+            .min_by_key(|x| x.several_parents != several_parents)?;
+        let fitting: Vec<&(DependencyPattern, u64)> = bucket
+            .patterns
+            .iter()
+            .filter(|x| x.0.fits_edge(parent_cnt, child_cnt, several_parents))
+            .collect();
+        return match fitting.choose_weighted(rnd, |x| x.1) {
+            Ok(pattern) => Some(pattern.0),
+            Err(_) => Some(DependencyPattern::AllToAll),
+        };
+    }
+}
+
+// Every task edge gets pattern fitted for its instance counts, without fitted patterns
+// they are inferred on expansion
+pub fn asign_patterns<R: Rng>(patterns: &PatternStatistic, result_dag: &mut TaskDag, rnd: &mut R) {
+    if patterns.is_empty() {
+        return;
+    }
+    for i in 0..result_dag.node_count() {
+        let node_ind = NodeIndex::new(i);
+        let node_info = result_dag.node_weight(node_ind).unwrap();
+        let child_cnt = node_info.instance_cnt;
+        let several_parents = node_info.dependencies.len() > 1;
+        let mut result = Vec::new();
+        for parent in node_info.dependencies.iter() {
+            let parent_cnt = result_dag
+                .node_weight(NodeIndex::new(*parent as usize))
+                .unwrap()
+                .instance_cnt;
+            if let Some(pattern) = patterns.sample(parent_cnt, child_cnt, several_parents, rnd) {
+                result.push((*parent, pattern));
+            }
+        }
+        result_dag
+            .node_weight_mut(node_ind)
+            .unwrap()
+            .patterns
+            .extend(result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::seeded_rng;
+
+    #[test]
+    fn sampled_patterns_fit_the_edge() {
+        let mut patterns = PatternStatistic::new();
+        patterns
+            .load_obj_from_string(String::from(
+                r#"[{"relation": "equal", "several_parents": true, "patterns": [["union", 5]]},
+                    {"relation": "more", "several_parents": false, "patterns": [["one_to_one", 5]]}]"#,
+            ))
+            .unwrap();
+        let mut rnd = seeded_rng(1);
+        let sample = |rnd: &mut _, p, c, several| patterns.sample(p, c, several, rnd);
+
+        assert_eq!(sample(&mut rnd, 4, 4, true), Some(DependencyPattern::Union));
+        // only the bucket of several parents is known for equal counts
+        assert_eq!(
+            sample(&mut rnd, 4, 4, false),
+            Some(DependencyPattern::AllToAll)
+        );
+        assert_eq!(
+            sample(&mut rnd, 4, 8, false),
+            Some(DependencyPattern::AllToAll)
+        );
+        // both are cut to INSTANCE_CAP on expansion
+        assert_eq!(
+            sample(&mut rnd, INSTANCE_CAP + 1, INSTANCE_CAP + 2, false),
+            Some(DependencyPattern::OneToOne)
+        );
+        assert_eq!(sample(&mut rnd, 8, 4, false), None);
+    }
+
+    #[test]
+    fn equal_partial_fan_in_is_hash_partition() {
+        let fit = |child_fan_ins: &[u64]| fit_pattern(8, 4, &[8], child_fan_ins, 1.0, false);
+        assert_eq!(
+            fit(&[2, 2, 2, 2]),
+            DependencyPattern::HashPartition { fan_in: 2 }
+        );
+        // uneven or full fan in and task links only are shuffles of everything
+        assert_eq!(fit(&[2, 3, 2, 2]), DependencyPattern::AllToAll);
+        assert_eq!(fit(&[8, 8, 8, 8]), DependencyPattern::AllToAll);
+        assert_eq!(fit(&[]), DependencyPattern::AllToAll);
+        // instance counts decide first
+        assert_eq!(
+            fit_pattern(4, 4, &[4], &[1, 1, 1, 1], 1.0, false),
+            DependencyPattern::OneToOne
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use crate::distribution::Family;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+// instance is a straggler if it runs longer than this times the median instance of its task
+pub const STRAGGLER_FACTOR: f64 = 1.5;
+
+// Spread of instance work inside a task. Instances apart from stragglers get lognormal work
+// with mean 1 and coefficient of variation cv, stragglers do straggler_magnitude times more.
+// Zeros give every instance the same work
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Dispersion {
+    pub cv: f64,
+    pub straggler_prob: f64,
+    pub straggler_magnitude: f64,
+}
+
+fn mean(values: &[f64]) -> f64 {
+    return values.iter().sum::<f64>() / values.len() as f64;
+}
+
+// None for less than two values or without positive mean
+pub fn coefficient_of_variation(values: &[f64]) -> Option<f64> {
+    if values.len() < 2 || mean(values) <= 0.0 {
+        return None;
+    }
+    let values_mean = mean(values);
+    let variance = values
+        .iter()
+        .map(|x| (x - values_mean).powi(2))
+        .sum::<f64>()
+        / values.len() as f64;
+    return Some(variance.sqrt() / values_mean);
+}
+
+impl Dispersion {
+    // None for tasks with less than two instances or without work
+    pub fn from_times(times: &[f64]) -> Option<Self> {
+        if times.len() < 2 {
+            return None;
+        }
+        let mut sorted = times.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let median = (sorted[(sorted.len() - 1) / 2] + sorted[sorted.len() / 2]) / 2.0;
+        let (stragglers, regular): (Vec<f64>, Vec<f64>) =
+            times.iter().partition(|x| **x > STRAGGLER_FACTOR * median);
+        if regular.is_empty() || mean(&regular) <= 0.0 {
+            return None;
+        }
+        let regular_mean = mean(&regular);
+        return Some(Dispersion {
+            cv: coefficient_of_variation(&regular).unwrap_or(0.0),
+            straggler_prob: stragglers.len() as f64 / times.len() as f64,
+            straggler_magnitude: if stragglers.is_empty() {
+                0.0
+            } else {
+                mean(&stragglers) / regular_mean
+            },
+        });
+    }
+
+    fn has_stragglers(&self) -> bool {
+        return self.straggler_prob > 0.0 && self.straggler_magnitude > 1.0;
+    }
+
+    pub fn is_uniform(&self) -> bool {
+        return self.cv <= 0.0 && !self.has_stragglers();
+    }
+
+    // work of instance relative to flops of its task
+    pub fn sample_factor<R: Rng>(&self, rnd: &mut R) -> f64 {
+        let mut factor = 1.0;
+        if self.cv > 0.0 {
+            let sigma_sq = (1.0 + self.cv * self.cv).ln();
+            factor = Family::LogNormal {
+                mu: -sigma_sq / 2.0,
+                sigma: sigma_sq.sqrt(),
+            }
+            .sample(rnd);
+        }
+        if self.has_stragglers() && rnd.gen_bool(self.straggler_prob.min(1.0)) {
+            factor *= self.straggler_magnitude;
+        }
+        return factor;
+    }
+}
@@ -0,0 +1,303 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+// quantiles kept per level statistic, fewer if there are fewer values
+pub const SKETCH_POINTS: usize = 101;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Family {
+    LogNormal { mu: f64, sigma: f64 },
+    Pareto { scale: f64, shape: f64 },
+    Weibull { scale: f64, shape: f64 },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct Fit {
+    pub family: Family,
+    pub aic: f64,
+}
+
+// Values at evenly spaced probabilities from 0 to 1, both ends included
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct QuantileSketch {
+    pub count: u64,
+    pub quantiles: Vec<f64>,
+    #[serde(default)]
+    pub fit: Option<Fit>,
+}
+
+impl Family {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Family::LogNormal { .. } => "lognormal",
+            Family::Pareto { .. } => "pareto",
+            Family::Weibull { .. } => "weibull",
+        };
+    }
+
+    fn ln_pdf(&self, x: f64) -> f64 {
+        return match *self {
+            Family::LogNormal { mu, sigma } => {
+                -(x * sigma * (2.0 * std::f64::consts::PI).sqrt()).ln()
+                    - (x.ln() - mu).powi(2) / (2.0 * sigma * sigma)
+            }
+            Family::Pareto { scale, shape } => {
+                if x < scale {
+                    f64::NEG_INFINITY
+                } else {
+                    shape.ln() + shape * scale.ln() - (shape + 1.0) * x.ln()
+                }
+            }
+            Family::Weibull { scale, shape } => {
+                (shape / scale).ln() + (shape - 1.0) * (x / scale).ln() - (x / scale).powf(shape)
+            }
+        };
+    }
+
+    pub fn sample<R: Rng>(&self, rnd: &mut R) -> f64 {
+        // in (0, 1] to keep logarithms finite
+        let u: f64 = 1.0 - rnd.gen_range(0.0..1.0);
+        return match *self {
+            Family::LogNormal { mu, sigma } => {
+                // Box-Muller
+                let angle = 2.0 * std::f64::consts::PI * rnd.gen_range(0.0..1.0);
+                (mu + sigma * (-2.0 * u.ln()).sqrt() * angle.cos()).exp()
+            }
+            Family::Pareto { scale, shape } => scale / u.powf(1.0 / shape),
+            Family::Weibull { scale, shape } => scale * (-u.ln()).powf(1.0 / shape),
+        };
+    }
+}
+
+fn fit_lognormal(values: &[f64]) -> Option<Family> {
+    let n = values.len() as f64;
+    let mu = values.iter().map(|x| x.ln()).sum::<f64>() / n;
+    let sigma = (values.iter().map(|x| (x.ln() - mu).powi(2)).sum::<f64>() / n).sqrt();
+    if sigma <= 0.0 {
+        return None;
+    }
+    return Some(Family::LogNormal {
+        mu: mu,
+        sigma: sigma,
+    });
+}
+
+fn fit_pareto(values: &[f64]) -> Option<Family> {
+    let scale = values.iter().copied().fold(f64::MAX, f64::min);
+    let log_sum = values.iter().map(|x| (x / scale).ln()).sum::<f64>();
+    if log_sum <= 0.0 {
+        return None;
+    }
+    return Some(Family::Pareto {
+        scale: scale,
+        shape: values.len() as f64 / log_sum,
+    });
+}
+
+// shape solves sum(x^k ln x) / sum(x^k) - 1 / k = mean(ln x), left side grows with k.
+// Values are divided by the largest one so powers do not overflow
+fn fit_weibull(values: &[f64]) -> Option<Family> {
+    let max = values.iter().copied().fold(0.0, f64::max);
+    let scaled: Vec<f64> = values.iter().map(|x| x / max).collect();
+    let mean_ln = scaled.iter().map(|x| x.ln()).sum::<f64>() / scaled.len() as f64;
+    let equation = |k: f64| {
+        let powers: Vec<f64> = scaled.iter().map(|x| x.powf(k)).collect();
+        let weighted = powers
+            .iter()
+            .zip(scaled.iter())
+            .map(|(p, x)| p * x.ln())
+            .sum::<f64>();
+        return weighted / powers.iter().sum::<f64>() - 1.0 / k - mean_ln;
+    };
+    let (mut low, mut high) = (0.01, 100.0);
+    if equation(low) > 0.0 || equation(high) < 0.0 {
+        return None;
+    }
+    for _ in 0..100 {
+        let mid = (low + high) / 2.0;
+        if equation(mid) < 0.0 {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    let shape = (low + high) / 2.0;
+    let mean_power = scaled.iter().map(|x| x.powf(shape)).sum::<f64>() / scaled.len() as f64;
+    return Some(Family::Weibull {
+        scale: max * mean_power.powf(1.0 / shape),
+        shape: shape,
+    });
+}
+
+impl QuantileSketch {
+    pub fn empty() -> Self {
+        return QuantileSketch {
+            count: 0,
+            quantiles: vec![0.0; 2],
+            fit: None,
+        };
+    }
+
+    // probabilities for quantiles of a sketch from count values
+    pub fn probabilities(count: usize) -> Vec<f64> {
+        let points = count.min(SKETCH_POINTS).max(2);
+        return (0..points)
+            .map(|x| x as f64 / (points - 1) as f64)
+            .collect();
+    }
+
+    // quantiles are taken at arbitrary increasing probabilities, stats saved before
+    // sketches had only a few percentiles and no count, the percentiles are counted instead
+    pub fn from_percentiles(probabilities: &[f64], values: &[f64]) -> Self {
+        let grid = QuantileSketch::probabilities(SKETCH_POINTS);
+        let quantiles = grid
+            .iter()
+            .map(|p| {
+                let ind = probabilities
+                    .iter()
+                    .position(|x| x >= p)
+                    .unwrap_or(probabilities.len() - 1)
+                    .max(1);
+                let (p0, p1) = (probabilities[ind - 1], probabilities[ind]);
+                let (v0, v1) = (values[ind - 1], values[ind]);
+                if p1 == p0 {
+                    v1
+                } else {
+                    v0 + (v1 - v0) * (p - p0) / (p1 - p0)
+                }
+            })
+            .collect();
+        return QuantileSketch {
+            count: values.len() as u64,
+            quantiles: quantiles,
+            fit: None,
+        };
+    }
+
+    // Maximum likelihood fit of every family to the sketch, every quantile between the
+    // ends stands for the same share of values. Families need positive values
+    pub fn fit_best(&mut self) {
+        let points = self.quantiles.len();
+        let values: Vec<f64> = (0..points - 1)
+            .map(|x| (self.quantiles[x] + self.quantiles[x + 1]) / 2.0)
+            .collect();
+        if self.count < 2 || values.iter().any(|x| *x <= 0.0) {
+            return;
+        }
+        let weight = self.count as f64 / values.len() as f64;
+        self.fit = [
+            fit_lognormal(&values),
+            fit_pareto(&values),
+            fit_weibull(&values),
+        ]
+        .iter()
+        .flatten()
+        .map(|family| {
+            let ln_likelihood = values.iter().map(|x| family.ln_pdf(*x)).sum::<f64>() * weight;
+            // every family has two parameters
+            Fit {
+                family: *family,
+                aic: 4.0 - 2.0 * ln_likelihood,
+            }
+        })
+        .filter(|x| x.aic.is_finite())
+        .min_by(|a, b| a.aic.partial_cmp(&b.aic).unwrap());
+    }
+
+    // From fitted family if there is one, its tail goes beyond observed values. Otherwise
+    // inverse transform over quantiles
+    pub fn sample<R: Rng>(&self, rnd: &mut R) -> f64 {
+        if let Some(fit) = &self.fit {
+            return fit.family.sample(rnd);
+        }
+        let max = self.quantiles[self.quantiles.len() - 1];
+        let pos = rnd.gen_range(0.0..1.0) * (self.quantiles.len() - 1) as f64;
+        let ind = pos.floor() as usize;
+        if ind + 1 >= self.quantiles.len() {
+            return max;
+        }
+        let (v0, v1) = (self.quantiles[ind], self.quantiles[ind + 1]);
+        return v0 + (v1 - v0) * (pos - ind as f64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::seeded_rng;
+
+    fn sketch_of(family: Family, count: usize, seed: u64) -> QuantileSketch {
+        let mut rnd = seeded_rng(seed);
+        let mut values: Vec<f64> = (0..count).map(|_| family.sample(&mut rnd)).collect();
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let quantiles = QuantileSketch::probabilities(count)
+            .iter()
+            .map(|p| values[(p * (count - 1) as f64).round() as usize])
+            .collect();
+        return QuantileSketch {
+            count: count as u64,
+            quantiles: quantiles,
+            fit: None,
+        };
+    }
+
+    #[test]
+    fn aic_picks_lognormal() {
+        let mut sketch = sketch_of(
+            Family::LogNormal {
+                mu: 1.0,
+                sigma: 0.5,
+            },
+            2000,
+            1,
+        );
+        sketch.fit_best();
+        match sketch.fit.unwrap().family {
+            Family::LogNormal { mu, sigma } => {
+                assert!((mu - 1.0).abs() < 0.05, "mu {}", mu);
+                assert!((sigma - 0.5).abs() < 0.05, "sigma {}", sigma);
+            }
+            family => panic!("{} is picked", family.name()),
+        }
+    }
+
+    #[test]
+    fn fitted_sketch_round_trips() {
+        let mut sketch = sketch_of(
+            Family::Weibull {
+                scale: 2.0,
+                shape: 1.5,
+            },
+            500,
+            2,
+        );
+        sketch.fit_best();
+        let loaded: QuantileSketch =
+            serde_json::from_str(&serde_json::to_string(&sketch).unwrap()).unwrap();
+        // json may lose the last bit of a float
+        let close = |x: f64, y: f64| (x - y).abs() <= 1e-12 * x.abs().max(y.abs());
+        assert_eq!(loaded.count, sketch.count);
+        assert!(sketch
+            .quantiles
+            .iter()
+            .zip(loaded.quantiles.iter())
+            .all(|(x, y)| close(*x, *y)));
+        let fitted = |sketch: &QuantileSketch| match sketch.fit.unwrap().family {
+            Family::Weibull { scale, shape } => (scale, shape),
+            family => panic!("{} is picked", family.name()),
+        };
+        let (scale, shape) = fitted(&sketch);
+        let (loaded_scale, loaded_shape) = fitted(&loaded);
+        assert!(close(scale, loaded_scale) && close(shape, loaded_shape));
+        assert!((scale - 2.0).abs() < 0.2 && (shape - 1.5).abs() < 0.2);
+
+        let (mut first, mut second) = (seeded_rng(3), seeded_rng(3));
+        let samples: Vec<f64> = (0..20000).map(|_| sketch.sample(&mut first)).collect();
+        for value in samples.iter() {
+            assert!(close(*value, loaded.sample(&mut second)));
+        }
+        // the tail is not cut at the largest observed value
+        let max = sketch.quantiles[sketch.quantiles.len() - 1];
+        assert!(samples.iter().any(|x| *x > max));
+    }
+}
@@ -0,0 +1,239 @@
+use crate::dependency::{dependency_patterns, DependencyPattern};
+use crate::distribution::QuantileSketch;
+use crate::statistic::*;
+use crate::task_dag::*;
+use crate::wfcommons::REFERENCE_MACHINE_MEMORY;
+use petgraph::stable_graph::NodeIndex;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// flops and instance counts of parent are bucketed by powers of two
+const MAX_FLOPS_BUCKET: u32 = 24;
+const MAX_INSTANCE_BUCKET: u32 = 6;
+
+fn log_bucket(value: f64, max_bucket: u32) -> u32 {
+    return (value.max(1.0).log2().floor() as u32).min(max_bucket);
+}
+
+// how data_size estimates sizes missing in the trace, for reports
+pub const DATA_SIZE_ESTIMATE: &str = "instances x memory share x 64 GiB of parent";
+
+// Bytes parent task writes for child. Traces without data sizes have edges of weight 1,
+// there the size is only an estimate: instance_cnt x mem% x 64 GiB of reference machine,
+// memory held by parent instances standing for their output
+pub fn data_size(weight: u64, parent: &DagVertex) -> u64 {
+    if weight > 1 {
+        return weight;
+    }
+    let proxy = parent.instance_cnt as f64 * parent.mem / 100.0 * REFERENCE_MACHINE_MEMORY as f64;
+    return (proxy.round() as u64).max(1);
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EdgeDataBucket {
+    pub kind: String,
+    pub flops_bucket: u32,
+    pub instance_bucket: u32,
+    pub sizes: QuantileSketch,
+}
+
+pub struct EdgeDataStatistic {
+    // dependency pattern, flops and instance buckets of parent -> data sizes of edges
+    stat: HashMap<(&'static str, u32, u32), MetricSerial>,
+    stat_result: Vec<EdgeDataBucket>,
+}
+
+impl SaveToFile for EdgeDataStatistic {}
+
+impl StatBase for EdgeDataStatistic {
+    fn form_stats(&mut self) {
+        self.stat_result.clear();
+        for ((kind, flops_bucket, instance_bucket), sizes) in self.stat.iter() {
+            let probabilities = QuantileSketch::probabilities(sizes.count());
+            self.stat_result.push(EdgeDataBucket {
+                kind: kind.to_string(),
+                flops_bucket: *flops_bucket,
+                instance_bucket: *instance_bucket,
+                sizes: QuantileSketch {
+                    count: sizes.count() as u64,
+                    quantiles: sizes.percentiles(&probabilities).unwrap().unwrap(),
+                    fit: None,
+                },
+            });
+        }
+        self.stat_result.sort_by(|a, b| {
+            (&a.kind, a.flops_bucket, a.instance_bucket).cmp(&(
+                &b.kind,
+                b.flops_bucket,
+                b.instance_bucket,
+            ))
+        });
+    }
+    fn get_string_obj(&self) -> String {
+        return serde_json::to_string(&self.stat_result).unwrap();
+    }
+    fn load_obj_from_string(&mut self, str: String) -> serde_json::Result<()> {
+        self.stat_result = serde_json::from_str(&str)?;
+        return Ok(());
+    }
+}
+
+impl EdgeDataStatistic {
+    pub fn new() -> Self {
+        return EdgeDataStatistic {
+            stat: HashMap::new(),
+            stat_result: Vec::new(),
+        };
+    }
+
+    pub fn add(&mut self, graph: &TaskDag) {
+        for node_ind in graph.node_indices() {
+            for (parent, pattern) in dependency_patterns(graph, node_ind) {
+                let size = match graph.find_edge(parent, node_ind) {
+                    Some(edge) => graph[edge],
+                    None => continue,
+                };
+                let parent_info = graph.node_weight(parent).unwrap();
+                let key = (
+                    pattern.name(),
+                    log_bucket(parent_info.flops, MAX_FLOPS_BUCKET),
+                    log_bucket(parent_info.instance_cnt as f64, MAX_INSTANCE_BUCKET),
+                );
+                self.stat.entry(key).or_default().add(size as f64);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.stat_result.is_empty();
+    }
+
+    // Sizes come from the nearest bucket of the same pattern, other patterns are used only
+    // if this one was never seen
+    pub fn sample<R: Rng>(
+        &self,
+        pattern: DependencyPattern,
+        flops: f64,
+        instance_cnt: u64,
+        rnd: &mut R,
+    ) -> Option<f64> {
+        let flops_bucket = log_bucket(flops, MAX_FLOPS_BUCKET) as i64;
+        let instance_bucket = log_bucket(instance_cnt as f64, MAX_INSTANCE_BUCKET) as i64;
+        let bucket = self.stat_result.iter().min_by_key(|x| {
+            (
+                x.kind != pattern.name(),
+                (x.flops_bucket as i64 - flops_bucket).abs()
+                    + (x.instance_bucket as i64 - instance_bucket).abs(),
+            )
+        })?;
+        return Some(bucket.sizes.sample(rnd));
+    }
+}
+
+// Every task edge gets data size by dependency pattern and parent task, edges are left as
+// they are if stats have no sizes
+pub fn asign_edge_sizes<R: Rng>(
+    edge_data: &EdgeDataStatistic,
+    result_dag: &mut TaskDag,
+    rnd: &mut R,
+) {
+    if edge_data.is_empty() {
+        return;
+    }
+    for i in 0..result_dag.node_count() {
+        let node_ind = NodeIndex::new(i);
+        for (parent, pattern) in dependency_patterns(result_dag, node_ind) {
+            let parent_info = result_dag.node_weight(parent).unwrap();
+            let size = edge_data.sample(pattern, parent_info.flops, parent_info.instance_cnt, rnd);
+            if let (Some(size), Some(edge)) = (size, result_dag.find_edge(parent, node_ind)) {
+                result_dag[edge] = (size.round() as u64).max(1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dispersion::Dispersion;
+    use crate::generator::seeded_rng;
+    use std::collections::BTreeMap;
+
+    // a -> b is one_to_one by instance counts, a -> c is broadcast as given
+    fn fork(one_to_one: u64, broadcast: u64) -> TaskDag {
+        let mut dag = TaskDag::new();
+        for (name, instance_cnt) in [("a", 2), ("b", 2), ("c", 1)] {
+            dag.add_node(DagVertex {
+                task_name: name.to_string(),
+                dependencies: Vec::new(),
+                instance_cnt: instance_cnt,
+                flops: 10.0,
+                cpu: DEFAULT_CPU,
+                mem: DEFAULT_MEM,
+                dispersion: Dispersion::default(),
+                patterns: BTreeMap::new(),
+            });
+        }
+        dag.add_task_endge(NodeIndex::new(1), NodeIndex::new(0));
+        dag.add_task_endge(NodeIndex::new(2), NodeIndex::new(0));
+        dag.node_weight_mut(NodeIndex::new(2))
+            .unwrap()
+            .patterns
+            .insert(0, DependencyPattern::Broadcast);
+        let edge = dag.find_edge(NodeIndex::new(0), NodeIndex::new(1)).unwrap();
+        dag[edge] = one_to_one;
+        let edge = dag.find_edge(NodeIndex::new(0), NodeIndex::new(2)).unwrap();
+        dag[edge] = broadcast;
+        return dag;
+    }
+
+    #[test]
+    fn sizes_are_kept_by_pattern() {
+        let mut edge_data = EdgeDataStatistic::new();
+        edge_data.add(&fork(1000, 50));
+        edge_data.add(&fork(3000, 50));
+        edge_data.form_stats();
+        let kinds: Vec<&str> = edge_data
+            .stat_result
+            .iter()
+            .map(|x| x.kind.as_str())
+            .collect();
+        assert_eq!(kinds, vec!["broadcast", "one_to_one"]);
+
+        let mut rnd = seeded_rng(1);
+        for _ in 0..20 {
+            let size = edge_data
+                .sample(DependencyPattern::OneToOne, 10.0, 2, &mut rnd)
+                .unwrap();
+            assert!((1000.0..=3000.0).contains(&size));
+            // a far bucket of the same pattern wins over other patterns
+            let size = edge_data
+                .sample(DependencyPattern::Broadcast, 1e6, 40, &mut rnd)
+                .unwrap();
+            assert_eq!(size, 50.0);
+        }
+        assert!(EdgeDataStatistic::new()
+            .sample(DependencyPattern::OneToOne, 10.0, 2, &mut rnd)
+            .is_none());
+
+        let mut dag = fork(1, 1);
+        asign_edge_sizes(&edge_data, &mut dag, &mut rnd);
+        let broadcast = dag.find_edge(NodeIndex::new(0), NodeIndex::new(2)).unwrap();
+        assert_eq!(dag[broadcast], 50);
+        let one_to_one = dag.find_edge(NodeIndex::new(0), NodeIndex::new(1)).unwrap();
+        assert!((1000..=3000).contains(&dag[one_to_one]));
+    }
+
+    #[test]
+    fn missing_sizes_are_estimated_from_memory() {
+        let dag = fork(1, 1);
+        let parent = dag.node_weight(NodeIndex::new(0)).unwrap();
+        assert_eq!(data_size(5, parent), 5);
+        // 2 instances holding 1% of 64 GiB each
+        assert_eq!(
+            data_size(1, parent),
+            (0.02 * REFERENCE_MACHINE_MEMORY as f64).round() as u64
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("{file}: {source}")]
+    Io {
+        file: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("{file}: malformed json: {source}")]
+    Json {
+        file: String,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error("{file}: malformed csv: {source}")]
+    Csv {
+        file: String,
+        #[source]
+        source: csv::Error,
+    },
+    #[error("line {line}: {reason}")]
+    Record { line: u64, reason: String },
+    #[error("task {task}: name is not in taskN format")]
+    TaskName { task: String },
+    #[error("task {task}: {reason}")]
+    Task { task: String, reason: String },
+    #[error("found cycle in graph")]
+    Cycle,
+    #[error("levels weren't calculated for all nodes")]
+    Levels,
+    #[error("no {name} statistic for cp {cp}, part {part}")]
+    NoStatistic { name: String, cp: u32, part: u32 },
+    #[error("{0}")]
+    Config(String),
+    #[error("job {job}: {source}")]
+    Job {
+        job: String,
+        #[source]
+        source: Box<Error>,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn in_job(self, job: &str) -> Error {
+        return Error::Job {
+            job: job.to_string(),
+            source: Box::new(self),
+        };
+    }
+    pub fn io(file: &str, source: std::io::Error) -> Error {
+        return Error::Io {
+            file: file.to_string(),
+            source: source,
+        };
+    }
+    pub fn json(file: &str, source: serde_json::Error) -> Error {
+        return Error::Json {
+            file: file.to_string(),
+            source: source,
+        };
+    }
+}
+
+// paths of directory entries in stable order
+pub fn read_dir_sorted(dirname: &str) -> Result<Vec<String>> {
+    let mut result = Vec::new();
+    for path in fs::read_dir(dirname).map_err(|why| Error::io(dirname, why))? {
+        let path = path.map_err(|why| Error::io(dirname, why))?;
+        result.push(path.path().display().to_string());
+    }
+    result.sort();
+    return Ok(result);
+}
+
+pub fn recreate_dir(dirname: &str) -> Result<()> {
+    if Path::new(dirname).exists() {
+        fs::remove_dir_all(dirname).map_err(|why| Error::io(dirname, why))?;
+    }
+    fs::create_dir(dirname).map_err(|why| Error::io(dirname, why))?;
+    return Ok(());
+}
+
+pub fn read_file(filename: &str) -> Result<String> {
+    let mut file = File::open(Path::new(filename)).map_err(|why| Error::io(filename, why))?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)
+        .map_err(|why| Error::io(filename, why))?;
+    return Ok(contents);
+}
+
+pub fn write_file(filename: &str, contents: &str) -> Result<()> {
+    let mut file = File::create(Path::new(filename)).map_err(|why| Error::io(filename, why))?;
+    file.write_all(contents.as_bytes())
+        .map_err(|why| Error::io(filename, why))?;
+    return Ok(());
+}
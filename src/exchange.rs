@@ -0,0 +1,859 @@
+use crate::dependency::DependencyPattern;
+use crate::dispersion::Dispersion;
+use crate::error::{read_file, write_file, Error, Result};
+use crate::instance::{AddEdge, InstDagVertex, InstanceDag};
+use crate::task_dag::{cores, DagVertex, TaskDag, TaskDagFuncs, DEFAULT_CPU, DEFAULT_MEM};
+use petgraph::stable_graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+// common view of TaskDag and InstanceDag for exchange formats
+struct PlainDag {
+    names: Vec<String>,
+    costs: Vec<f64>,
+    instance_cnt: Vec<u64>,
+    cpu: Vec<f64>,
+    mem: Vec<f64>,
+    dispersion: Vec<Dispersion>,
+    // parent -> pattern like in DagVertex
+    patterns: Vec<BTreeMap<u32, DependencyPattern>>,
+    // parent, child, communication cost
+    edges: Vec<(usize, usize, f64)>,
+}
+
+impl PlainDag {
+    fn with_nodes(node_cnt: usize) -> Self {
+        return PlainDag {
+            names: (0..node_cnt).map(|x| format!("task_{}", x)).collect(),
+            costs: vec![0.0; node_cnt],
+            instance_cnt: vec![1; node_cnt],
+            cpu: vec![DEFAULT_CPU; node_cnt],
+            mem: vec![DEFAULT_MEM; node_cnt],
+            dispersion: vec![Dispersion::default(); node_cnt],
+            patterns: vec![BTreeMap::new(); node_cnt],
+            edges: Vec::new(),
+        };
+    }
+
+    fn parents(&self) -> Vec<Vec<usize>> {
+        let mut result = vec![Vec::new(); self.names.len()];
+        for (parent, child, _) in self.edges.iter() {
+            result[*child].push(*parent);
+        }
+        return result;
+    }
+}
+
+fn plain_from_task_dag(dag: &TaskDag) -> PlainDag {
+    let mut result = PlainDag::with_nodes(0);
+    for node_info in dag.node_weights() {
+        result.names.push(node_info.task_name.clone());
+        result.costs.push(node_info.flops);
+        result.instance_cnt.push(node_info.instance_cnt);
+        result.cpu.push(node_info.cpu);
+        result.mem.push(node_info.mem);
+        result.dispersion.push(node_info.dispersion);
+        result.patterns.push(node_info.patterns.clone());
+    }
+    for edge in dag.edge_references() {
+        result.edges.push((
+            edge.source().index(),
+            edge.target().index(),
+            *edge.weight() as f64,
+        ));
+    }
+    return result;
+}
+
+fn plain_from_instance_dag(dag: &InstanceDag) -> PlainDag {
+    let mut result = PlainDag::with_nodes(0);
+    for node_info in dag.node_weights() {
+        result.names.push(node_info.inst_name.clone());
+        result.costs.push(node_info.flops);
+        result.instance_cnt.push(1);
+        result.cpu.push(node_info.cpu);
+        result.mem.push(node_info.mem);
+        result.dispersion.push(Dispersion::default());
+        result.patterns.push(BTreeMap::new());
+    }
+    for edge in dag.edge_references() {
+        result
+            .edges
+            .push((edge.source().index(), edge.target().index(), *edge.weight()));
+    }
+    return result;
+}
+
+fn task_dag_from_plain(plain: PlainDag) -> Result<TaskDag> {
+    let mut result = TaskDag::new();
+    for ind in 0..plain.names.len() {
+        result.add_node(DagVertex {
+            task_name: plain.names[ind].clone(),
+            dependencies: Vec::new(),
+            instance_cnt: plain.instance_cnt[ind],
+            flops: plain.costs[ind],
+            cpu: plain.cpu[ind],
+            mem: plain.mem[ind],
+            dispersion: plain.dispersion[ind],
+            patterns: plain.patterns[ind].clone(),
+        });
+    }
+    for (parent, child, cost) in plain.edges.iter() {
+        result.add_edge(
+            NodeIndex::new(*parent),
+            NodeIndex::new(*child),
+            cost.round() as u64,
+        );
+        result
+            .node_weight_mut(NodeIndex::new(*child))
+            .unwrap()
+            .dependencies
+            .push(*parent as u32);
+    }
+    result.check_dependencies()?;
+    return Ok(result);
+}
+
+fn instance_dag_from_plain(plain: PlainDag) -> InstanceDag {
+    let mut result = InstanceDag::new();
+    for ind in 0..plain.names.len() {
+        result.add_node(InstDagVertex {
+            inst_name: plain.names[ind].clone(),
+            dependencies: Vec::new(),
+            flops: plain.costs[ind],
+            cpu: plain.cpu[ind],
+            mem: plain.mem[ind],
+        });
+    }
+    for (parent, child, cost) in plain.edges.iter() {
+        result.add_ins_edge(*parent, *child, *cost);
+    }
+    return result;
+}
+
+fn format_error(filename: &str, line: usize, reason: &str) -> Error {
+    return Error::Config(format!("{}:{}: {}", filename, line, reason));
+}
+
+// names in STG comments are single words
+fn escape_name(text: &str) -> String {
+    return text
+        .replace('%', "%25")
+        .replace(' ', "%20")
+        .replace('\t', "%09")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A");
+}
+
+fn unescape_name(text: &str) -> String {
+    return text
+        .replace("%0A", "\n")
+        .replace("%0D", "\r")
+        .replace("%09", "\t")
+        .replace("%20", " ")
+        .replace("%25", "%");
+}
+
+// Standard Task Graph: task count, dummy entry 0, tasks 1..n as "id cost pred_cnt preds...",
+// dummy exit n+1. STG has integer costs and no communication, exact costs, names,
+// instance counts, cpu, mem, dispersion, edge costs and patterns are kept in trailing
+// comments, dispersion and patterns only if they are set. Other comments are skipped
+fn write_stg(plain: &PlainDag, filename: &str) -> Result<()> {
+    let node_cnt = plain.names.len();
+    let parents = plain.parents();
+    let mut has_children = vec![false; node_cnt];
+    for (parent, _, _) in plain.edges.iter() {
+        has_children[*parent] = true;
+    }
+
+    let mut output = format!("{}\n", node_cnt);
+    output.push_str(&format!("{:>6} {:>6} {:>6}\n", 0, 0, 0));
+    for ind in 0..node_cnt {
+        let preds: Vec<usize> = if parents[ind].is_empty() {
+            vec![0]
+        } else {
+            parents[ind].iter().map(|x| x + 1).collect()
+        };
+        output.push_str(&format!(
+            "{:>6} {:>6} {:>6}",
+            ind + 1,
+            plain.costs[ind].ceil() as u64,
+            preds.len()
+        ));
+        for pred in preds.iter() {
+            output.push_str(&format!(" {:>6}", pred));
+        }
+        output.push('\n');
+    }
+    let sinks: Vec<usize> = (0..node_cnt).filter(|x| !has_children[*x]).collect();
+    output.push_str(&format!("{:>6} {:>6} {:>6}", node_cnt + 1, 0, sinks.len()));
+    for sink in sinks.iter() {
+        output.push_str(&format!(" {:>6}", sink + 1));
+    }
+    output.push('\n');
+
+    output.push_str("# generated by cursach_content\n");
+    for ind in 0..node_cnt {
+        output.push_str(&format!(
+            "# task {} {} {} {} {} {}",
+            ind + 1,
+            escape_name(&plain.names[ind]),
+            plain.instance_cnt[ind],
+            plain.costs[ind],
+            plain.cpu[ind],
+            plain.mem[ind]
+        ));
+        let dispersion = plain.dispersion[ind];
+        if dispersion != Dispersion::default() {
+            output.push_str(&format!(
+                " {} {} {}",
+                dispersion.cv, dispersion.straggler_prob, dispersion.straggler_magnitude
+            ));
+        }
+        output.push('\n');
+    }
+    for (parent, child, cost) in plain.edges.iter() {
+        output.push_str(&format!("# edge {} {} {}", parent + 1, child + 1, cost));
+        if let Some(pattern) = plain.patterns[*child].get(&(*parent as u32)) {
+            output.push_str(&format!(" {}", pattern_word(pattern)));
+        }
+        output.push('\n');
+    }
+    return write_file(filename, &output);
+}
+
+// compact json has no spaces, so it is one word of a comment or profile
+fn pattern_word(pattern: &DependencyPattern) -> String {
+    return serde_json::to_string(pattern).unwrap();
+}
+
+fn parse_pattern_word(word: &str) -> Option<DependencyPattern> {
+    return serde_json::from_str(word).ok();
+}
+
+fn parse_stg(filename: &str) -> Result<PlainDag> {
+    let contents = read_file(filename)?;
+    let mut numbers: Vec<(usize, Vec<&str>)> = Vec::new();
+    let mut task_info = HashMap::<usize, (String, u64, f64)>::new();
+    // files written before demands were kept have no cpu and mem
+    let mut task_demand = HashMap::<usize, (f64, f64)>::new();
+    let mut task_dispersion = HashMap::<usize, Dispersion>::new();
+    let mut edge_costs = HashMap::<(usize, usize), f64>::new();
+    let mut edge_patterns = HashMap::<(usize, usize), DependencyPattern>::new();
+
+    for (line_ind, line) in contents.lines().enumerate() {
+        let line_no = line_ind + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(comment) = line.strip_prefix('#') {
+            let words: Vec<&str> = comment.split_whitespace().collect();
+            let bad = || format_error(filename, line_no, "broken metadata comment");
+            match words.first() {
+                Some(&"task") if [5, 7, 10].contains(&words.len()) => {
+                    let id = words[1].parse().map_err(|_| bad())?;
+                    task_info.insert(
+                        id,
+                        (
+                            unescape_name(words[2]),
+                            words[3].parse().map_err(|_| bad())?,
+                            words[4].parse().map_err(|_| bad())?,
+                        ),
+                    );
+                    if words.len() >= 7 {
+                        task_demand.insert(
+                            id,
+                            (
+                                words[5].parse().map_err(|_| bad())?,
+                                words[6].parse().map_err(|_| bad())?,
+                            ),
+                        );
+                    }
+                    if words.len() == 10 {
+                        task_dispersion.insert(
+                            id,
+                            Dispersion {
+                                cv: words[7].parse().map_err(|_| bad())?,
+                                straggler_prob: words[8].parse().map_err(|_| bad())?,
+                                straggler_magnitude: words[9].parse().map_err(|_| bad())?,
+                            },
+                        );
+                    }
+                }
+                Some(&"edge") if words.len() == 4 || words.len() == 5 => {
+                    let ids = (
+                        words[1].parse().map_err(|_| bad())?,
+                        words[2].parse().map_err(|_| bad())?,
+                    );
+                    edge_costs.insert(ids, words[3].parse().map_err(|_| bad())?);
+                    if words.len() == 5 {
+                        edge_patterns.insert(ids, parse_pattern_word(words[4]).ok_or_else(bad)?);
+                    }
+                }
+                Some(&"task") | Some(&"edge") => return Err(bad()),
+                _ => {}
+            }
+            continue;
+        }
+        numbers.push((line_no, line.split_whitespace().collect()));
+    }
+
+    let (first_line, header) = match numbers.first() {
+        Some(header) => header,
+        None => return Err(format_error(filename, 0, "empty graph")),
+    };
+    let node_cnt: usize = header
+        .first()
+        .and_then(|x| x.parse().ok())
+        .ok_or_else(|| format_error(filename, *first_line, "no task count"))?;
+    if numbers.len() < node_cnt + 3 {
+        return Err(format_error(
+            filename,
+            *first_line,
+            &format!("expected {} tasks with dummy entry and exit", node_cnt),
+        ));
+    }
+
+    let mut result = PlainDag::with_nodes(node_cnt);
+    for (line_no, words) in numbers[2..node_cnt + 2].iter() {
+        let values: Vec<usize> = words
+            .iter()
+            .map(|x| x.parse::<usize>())
+            .collect::<std::result::Result<_, _>>()
+            .map_err(|_| format_error(filename, *line_no, "not a number"))?;
+        if values.len() < 3 || values.len() != 3 + values[2] || values[0] == 0 {
+            return Err(format_error(filename, *line_no, "wrong task line"));
+        }
+        let id = values[0];
+        if id > node_cnt {
+            return Err(format_error(filename, *line_no, "task id is out of graph"));
+        }
+        result.costs[id - 1] = values[1] as f64;
+        for pred in values[3..].iter() {
+            if *pred == 0 {
+                continue;
+            }
+            if *pred > node_cnt {
+                return Err(format_error(
+                    filename,
+                    *line_no,
+                    "predecessor is out of graph",
+                ));
+            }
+            let cost = edge_costs.get(&(*pred, id)).copied().unwrap_or(0.0);
+            result.edges.push((pred - 1, id - 1, cost));
+            if let Some(pattern) = edge_patterns.get(&(*pred, id)) {
+                result.patterns[id - 1].insert((pred - 1) as u32, *pattern);
+            }
+        }
+    }
+    for (id, (name, instance_cnt, cost)) in task_info.into_iter() {
+        if id == 0 || id > node_cnt {
+            continue;
+        }
+        result.names[id - 1] = name;
+        result.instance_cnt[id - 1] = instance_cnt;
+        result.costs[id - 1] = cost;
+    }
+    for (id, (cpu, mem)) in task_demand.into_iter() {
+        if id == 0 || id > node_cnt {
+            continue;
+        }
+        result.cpu[id - 1] = cpu;
+        result.mem[id - 1] = mem;
+    }
+    for (id, dispersion) in task_dispersion.into_iter() {
+        if id == 0 || id > node_cnt {
+            continue;
+        }
+        result.dispersion[id - 1] = dispersion;
+    }
+    return Ok(result);
+}
+
+fn escape_xml(text: &str) -> String {
+    return text
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;");
+}
+
+fn unescape_xml(text: &str) -> String {
+    return text
+        .replace("&quot;", "\"")
+        .replace("&gt;", ">")
+        .replace("&lt;", "<")
+        .replace("&amp;", "&");
+}
+
+fn job_id(ind: usize) -> String {
+    return format!("ID{:05}", ind);
+}
+
+fn file_name(plain: &PlainDag, parent: usize, child: usize) -> String {
+    return escape_xml(&format!("{}_{}", plain.names[parent], plain.names[child]));
+}
+
+// Pegasus DAX 3.6, communication cost is size of file passed between jobs. Dispersion and
+// patterns are cursach profiles if they are set, pattern profile is "parent_id pattern"
+fn write_dax(plain: &PlainDag, filename: &str) -> Result<()> {
+    let node_cnt = plain.names.len();
+    let parents = plain.parents();
+    let name = Path::new(filename)
+        .file_stem()
+        .map(|x| x.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let child_cnt = parents.iter().filter(|x| !x.is_empty()).count();
+
+    let mut output = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    output.push_str("<!-- generated by cursach_content -->\n");
+    output.push_str(&format!(
+        "<adag xmlns=\"http://pegasus.isi.edu/schema/DAX\" version=\"3.6\" name=\"{}\" jobCount=\"{}\" fileCount=\"0\" childCount=\"{}\">\n",
+        escape_xml(&name),
+        node_cnt,
+        child_cnt
+    ));
+    for ind in 0..node_cnt {
+        output.push_str(&format!(
+            "  <job id=\"{}\" namespace=\"cursach\" name=\"{}\" version=\"1.0\" runtime=\"{}\">\n",
+            job_id(ind),
+            escape_xml(&plain.names[ind]),
+            plain.costs[ind]
+        ));
+        output.push_str(&format!(
+            "    <profile namespace=\"cursach\" key=\"instance_cnt\">{}</profile>\n",
+            plain.instance_cnt[ind]
+        ));
+        output.push_str(&format!(
+            "    <profile namespace=\"cursach\" key=\"cpu\">{}</profile>\n",
+            plain.cpu[ind]
+        ));
+        output.push_str(&format!(
+            "    <profile namespace=\"cursach\" key=\"mem\">{}</profile>\n",
+            plain.mem[ind]
+        ));
+        output.push_str(&format!(
+            "    <profile namespace=\"pegasus\" key=\"cores\">{}</profile>\n",
+            cores(plain.cpu[ind])
+        ));
+        let dispersion = plain.dispersion[ind];
+        if dispersion != Dispersion::default() {
+            for (key, value) in [
+                ("cv", dispersion.cv),
+                ("straggler_prob", dispersion.straggler_prob),
+                ("straggler_magnitude", dispersion.straggler_magnitude),
+            ] {
+                output.push_str(&format!(
+                    "    <profile namespace=\"cursach\" key=\"{}\">{}</profile>\n",
+                    key, value
+                ));
+            }
+        }
+        for (parent, pattern) in plain.patterns[ind].iter() {
+            output.push_str(&format!(
+                "    <profile namespace=\"cursach\" key=\"pattern\">{} {}</profile>\n",
+                job_id(*parent as usize),
+                escape_xml(&pattern_word(pattern))
+            ));
+        }
+        for (parent, child, cost) in plain.edges.iter() {
+            if *child == ind {
+                output.push_str(&format!(
+                    "    <uses file=\"{}\" link=\"input\" size=\"{}\"/>\n",
+                    file_name(plain, *parent, *child),
+                    cost
+                ));
+            }
+            if *parent == ind {
+                output.push_str(&format!(
+                    "    <uses file=\"{}\" link=\"output\" size=\"{}\"/>\n",
+                    file_name(plain, *parent, *child),
+                    cost
+                ));
+            }
+        }
+        output.push_str("  </job>\n");
+    }
+    for ind in 0..node_cnt {
+        if parents[ind].is_empty() {
+            continue;
+        }
+        output.push_str(&format!("  <child ref=\"{}\">\n", job_id(ind)));
+        for parent in parents[ind].iter() {
+            output.push_str(&format!("    <parent ref=\"{}\"/>\n", job_id(*parent)));
+        }
+        output.push_str("  </child>\n");
+    }
+    output.push_str("</adag>\n");
+    return write_file(filename, &output);
+}
+
+struct XmlTag {
+    name: String,
+    closing: bool,
+    attrs: HashMap<String, String>,
+    // text right after the tag
+    text: String,
+}
+
+// enough of xml for DAX: tags with attributes and plain text, no CDATA
+fn parse_tags(filename: &str, contents: &str) -> Result<Vec<XmlTag>> {
+    let mut result = Vec::new();
+    let mut rest = contents;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        if rest.starts_with("!--") {
+            let end = rest
+                .find("-->")
+                .ok_or_else(|| format_error(filename, 0, "unclosed comment"))?;
+            rest = &rest[end + 3..];
+            continue;
+        }
+        let end = rest
+            .find('>')
+            .ok_or_else(|| format_error(filename, 0, "unclosed tag"))?;
+        let tag = rest[..end].trim_end_matches('/').trim();
+        rest = &rest[end + 1..];
+        if tag.starts_with('?') || tag.starts_with('!') {
+            continue;
+        }
+        let text = rest[..rest.find('<').unwrap_or(rest.len())].trim();
+
+        let (closing, tag) = match tag.strip_prefix('/') {
+            Some(tag) => (true, tag),
+            None => (false, tag),
+        };
+        let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+        let mut attrs = HashMap::new();
+        let mut attr_text = &tag[name_end..];
+        while let Some(eq) = attr_text.find('=') {
+            let key = attr_text[..eq].trim().to_string();
+            let value_text = attr_text[eq + 1..].trim_start();
+            let quote = match value_text.chars().next() {
+                Some(quote) if quote == '"' || quote == '\'' => quote,
+                _ => return Err(format_error(filename, 0, "attribute without quotes")),
+            };
+            let value_end = value_text[1..]
+                .find(quote)
+                .ok_or_else(|| format_error(filename, 0, "unclosed attribute"))?;
+            attrs.insert(key, unescape_xml(&value_text[1..value_end + 1]));
+            attr_text = &value_text[value_end + 2..];
+        }
+        result.push(XmlTag {
+            name: tag[..name_end].to_string(),
+            closing: closing,
+            attrs: attrs,
+            text: unescape_xml(text),
+        });
+    }
+    return Ok(result);
+}
+
+fn parse_dax(filename: &str) -> Result<PlainDag> {
+    let contents = read_file(filename)?;
+    let tags = parse_tags(filename, &contents)?;
+
+    let mut result = PlainDag::with_nodes(0);
+    let mut positions = HashMap::<String, usize>::new();
+    let mut inputs = Vec::<HashMap<String, f64>>::new();
+    let mut outputs = Vec::<HashMap<String, f64>>::new();
+    let mut current_job: Option<usize> = None;
+    let mut current_child: Option<usize> = None;
+    let mut links = Vec::<(String, usize)>::new();
+    // parent id, child, pattern
+    let mut patterns = Vec::<(String, usize, DependencyPattern)>::new();
+
+    for tag in tags.iter() {
+        match (tag.name.as_str(), tag.closing) {
+            ("job", false) => {
+                let id = tag
+                    .attrs
+                    .get("id")
+                    .ok_or_else(|| format_error(filename, 0, "job without id"))?;
+                let ind = result.names.len();
+                positions.insert(id.clone(), ind);
+                result
+                    .names
+                    .push(tag.attrs.get("name").cloned().unwrap_or(id.clone()));
+                result.costs.push(
+                    tag.attrs
+                        .get("runtime")
+                        .and_then(|x| x.parse().ok())
+                        .unwrap_or(0.0),
+                );
+                result.instance_cnt.push(1);
+                result.cpu.push(DEFAULT_CPU);
+                result.mem.push(DEFAULT_MEM);
+                result.dispersion.push(Dispersion::default());
+                result.patterns.push(BTreeMap::new());
+                inputs.push(HashMap::new());
+                outputs.push(HashMap::new());
+                current_job = Some(ind);
+            }
+            ("job", true) => current_job = None,
+            ("profile", false) => {
+                if let Some(ind) = current_job {
+                    match tag.attrs.get("key").map(|x| x.as_str()) {
+                        Some("instance_cnt") => {
+                            result.instance_cnt[ind] = tag.text.parse().unwrap_or(1)
+                        }
+                        Some("cpu") => result.cpu[ind] = tag.text.parse().unwrap_or(DEFAULT_CPU),
+                        Some("mem") => result.mem[ind] = tag.text.parse().unwrap_or(DEFAULT_MEM),
+                        Some("cv") => result.dispersion[ind].cv = tag.text.parse().unwrap_or(0.0),
+                        Some("straggler_prob") => {
+                            result.dispersion[ind].straggler_prob = tag.text.parse().unwrap_or(0.0)
+                        }
+                        Some("straggler_magnitude") => {
+                            result.dispersion[ind].straggler_magnitude =
+                                tag.text.parse().unwrap_or(0.0)
+                        }
+                        Some("pattern") => {
+                            let pattern = tag.text.split_once(' ').and_then(|(parent, word)| {
+                                Some((parent.to_string(), parse_pattern_word(word)?))
+                            });
+                            match pattern {
+                                Some((parent, pattern)) => patterns.push((parent, ind, pattern)),
+                                None => {
+                                    return Err(format_error(filename, 0, "broken pattern profile"))
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            ("uses", false) => {
+                if let Some(ind) = current_job {
+                    let file = tag
+                        .attrs
+                        .get("file")
+                        .or(tag.attrs.get("name"))
+                        .cloned()
+                        .unwrap_or_default();
+                    let size = tag
+                        .attrs
+                        .get("size")
+                        .and_then(|x| x.parse().ok())
+                        .unwrap_or(0.0);
+                    match tag.attrs.get("link").map(|x| x.as_str()) {
+                        Some("input") => inputs[ind].insert(file, size),
+                        Some("output") => outputs[ind].insert(file, size),
+                        _ => None,
+                    };
+                }
+            }
+            ("child", false) => {
+                let id = tag.attrs.get("ref").cloned().unwrap_or_default();
+                current_child = match positions.get(&id) {
+                    Some(ind) => Some(*ind),
+                    None => {
+                        return Err(format_error(filename, 0, &format!("unknown child {}", id)))
+                    }
+                };
+            }
+            ("child", true) => current_child = None,
+            ("parent", false) => {
+                if let Some(child) = current_child {
+                    links.push((tag.attrs.get("ref").cloned().unwrap_or_default(), child));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for (parent_id, child) in links.into_iter() {
+        let parent = match positions.get(&parent_id) {
+            Some(parent) => *parent,
+            None => {
+                return Err(format_error(
+                    filename,
+                    0,
+                    &format!("unknown parent {}", parent_id),
+                ))
+            }
+        };
+        let cost = inputs[child]
+            .iter()
+            .filter(|(file, _)| outputs[parent].contains_key(*file))
+            .map(|(_, size)| size)
+            .sum();
+        result.edges.push((parent, child, cost));
+    }
+    for (parent_id, child, pattern) in patterns.into_iter() {
+        match positions.get(&parent_id) {
+            Some(parent) => result.patterns[child].insert(*parent as u32, pattern),
+            None => {
+                return Err(format_error(
+                    filename,
+                    0,
+                    &format!("pattern of unknown parent {}", parent_id),
+                ))
+            }
+        };
+    }
+    return Ok(result);
+}
+
+// Formats of scheduling literature (STG) and Pegasus (DAX), costs are flops and edge weights
+pub trait ExchangeFormat: Sized {
+    fn save_to_stg(&self, filename: &str) -> Result<()>;
+    fn save_to_dax(&self, filename: &str) -> Result<()>;
+    fn load_from_stg(filename: &str) -> Result<Self>;
+    fn load_from_dax(filename: &str) -> Result<Self>;
+}
+
+impl ExchangeFormat for TaskDag {
+    fn save_to_stg(&self, filename: &str) -> Result<()> {
+        return write_stg(&plain_from_task_dag(self), filename);
+    }
+    fn save_to_dax(&self, filename: &str) -> Result<()> {
+        return write_dax(&plain_from_task_dag(self), filename);
+    }
+    fn load_from_stg(filename: &str) -> Result<Self> {
+        return task_dag_from_plain(parse_stg(filename)?);
+    }
+    fn load_from_dax(filename: &str) -> Result<Self> {
+        return task_dag_from_plain(parse_dax(filename)?);
+    }
+}
+
+impl ExchangeFormat for InstanceDag {
+    fn save_to_stg(&self, filename: &str) -> Result<()> {
+        return write_stg(&plain_from_instance_dag(self), filename);
+    }
+    fn save_to_dax(&self, filename: &str) -> Result<()> {
+        return write_dax(&plain_from_instance_dag(self), filename);
+    }
+    fn load_from_stg(filename: &str) -> Result<Self> {
+        return Ok(instance_dag_from_plain(parse_stg(filename)?));
+    }
+    fn load_from_dax(filename: &str) -> Result<Self> {
+        return Ok(instance_dag_from_plain(parse_dax(filename)?));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_file(name: &str) -> String {
+        return format!("{}/{}", std::env::temp_dir().display(), name);
+    }
+
+    // names with spaces and markup, fractional costs, a task with two children
+    fn small_task_dag() -> TaskDag {
+        let mut dag = TaskDag::new();
+        for (ind, name) in ["map input", "join <a&b>", "100% reduce", "sink"]
+            .iter()
+            .enumerate()
+        {
+            dag.add_node(DagVertex {
+                task_name: name.to_string(),
+                dependencies: Vec::new(),
+                instance_cnt: ind as u64 + 1,
+                flops: 10.5 * (ind + 1) as f64,
+                cpu: 50.0 + ind as f64,
+                mem: 0.25 * (ind + 1) as f64,
+                dispersion: Dispersion::default(),
+                patterns: BTreeMap::new(),
+            });
+        }
+        for (parent, child, size) in [(0, 1, 100), (0, 2, 7), (1, 3, 30), (2, 3, 1)] {
+            dag.add_task_endge(NodeIndex::new(child), NodeIndex::new(parent));
+            let edge = dag
+                .find_edge(NodeIndex::new(parent), NodeIndex::new(child))
+                .unwrap();
+            dag[edge] = size;
+        }
+        dag.node_weight_mut(NodeIndex::new(2)).unwrap().dispersion = Dispersion {
+            cv: 0.25,
+            straggler_prob: 0.1,
+            straggler_magnitude: 3.5,
+        };
+        let sink = dag.node_weight_mut(NodeIndex::new(3)).unwrap();
+        sink.patterns
+            .insert(1, DependencyPattern::HashPartition { fan_in: 3 });
+        sink.patterns.insert(2, DependencyPattern::Broadcast);
+        return dag;
+    }
+
+    fn assert_same_task_dag(left: &TaskDag, right: &TaskDag) {
+        assert_eq!(left.node_count(), right.node_count());
+        for (a, b) in left.node_weights().zip(right.node_weights()) {
+            assert_eq!(a.task_name, b.task_name);
+            assert_eq!(a.instance_cnt, b.instance_cnt);
+            assert_eq!(a.flops, b.flops);
+            assert_eq!(a.cpu, b.cpu);
+            assert_eq!(a.mem, b.mem);
+            assert_eq!(a.dispersion, b.dispersion);
+            assert_eq!(a.patterns, b.patterns);
+            assert_eq!(a.dependencies, b.dependencies);
+        }
+        let edges = |dag: &TaskDag| {
+            let mut result: Vec<(usize, usize, u64)> = dag
+                .edge_references()
+                .map(|x| (x.source().index(), x.target().index(), *x.weight()))
+                .collect();
+            result.sort();
+            result
+        };
+        assert_eq!(edges(left), edges(right));
+    }
+
+    #[test]
+    fn task_dag_round_trip() {
+        let dag = small_task_dag();
+        let stg = scratch_file("cursach_round_trip.stg");
+        dag.save_to_stg(&stg).unwrap();
+        assert_same_task_dag(&dag, &TaskDag::load_from_stg(&stg).unwrap());
+        let dax = scratch_file("cursach_round_trip.dax");
+        dag.save_to_dax(&dax).unwrap();
+        assert_same_task_dag(&dag, &TaskDag::load_from_dax(&dax).unwrap());
+        std::fs::remove_file(stg).unwrap();
+        std::fs::remove_file(dax).unwrap();
+    }
+
+    #[test]
+    fn instance_dag_round_trip() {
+        let dag = small_task_dag().convert_to_inst_dag(&mut crate::generator::seeded_rng(3), None);
+        for (filename, is_stg) in [
+            (scratch_file("cursach_inst_round_trip.stg"), true),
+            (scratch_file("cursach_inst_round_trip.dax"), false),
+        ] {
+            let loaded = if is_stg {
+                dag.save_to_stg(&filename).unwrap();
+                InstanceDag::load_from_stg(&filename).unwrap()
+            } else {
+                dag.save_to_dax(&filename).unwrap();
+                InstanceDag::load_from_dax(&filename).unwrap()
+            };
+            assert_eq!(dag.node_count(), loaded.node_count());
+            for (a, b) in dag.node_weights().zip(loaded.node_weights()) {
+                assert_eq!(
+                    (&a.inst_name, a.flops, a.cpu, a.mem, &a.dependencies),
+                    (&b.inst_name, b.flops, b.cpu, b.mem, &b.dependencies)
+                );
+            }
+            let total = |dag: &InstanceDag| dag.edge_weights().sum::<f64>();
+            assert!((total(&dag) - total(&loaded)).abs() < 1e-9);
+            std::fs::remove_file(filename).unwrap();
+        }
+    }
+
+    #[test]
+    fn broken_stg_metadata_is_an_error() {
+        let stg = scratch_file("cursach_broken.stg");
+        small_task_dag().save_to_stg(&stg).unwrap();
+        let contents = read_file(&stg).unwrap();
+        assert!(contents.contains("# task 1 map%20input 1"));
+        write_file(&stg, &contents.replace("map%20input", "map input")).unwrap();
+        assert!(TaskDag::load_from_stg(&stg).is_err());
+        assert!(contents.contains(" \"broadcast\"\n"));
+        write_file(&stg, &contents.replace("\"broadcast\"", "\"fan_out\"")).unwrap();
+        assert!(TaskDag::load_from_stg(&stg).is_err());
+        std::fs::remove_file(stg).unwrap();
+    }
+}
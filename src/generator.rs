@@ -0,0 +1,663 @@
+use crate::classify::GraphType;
+use crate::dependency::asign_patterns;
+use crate::dispersion::Dispersion;
+use crate::edge_data::asign_edge_sizes;
+use crate::error::{Error, Result};
+use crate::statistic::*;
+use crate::task_dag::*;
+use petgraph::stable_graph::NodeIndex;
+use queues::*;
+use rand::prelude::SliceRandom;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::BTreeMap;
+
+// the only rng constructor, so the same seed gives the same dags
+pub fn seeded_rng(seed: u64) -> StdRng {
+    return StdRng::seed_from_u64(seed);
+}
+
+// level places tasks by level statistics, motif composes dags from mined stage motifs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Generator {
+    Level,
+    Motif,
+}
+
+pub const ALL_GENERATORS: [Generator; 2] = [Generator::Level, Generator::Motif];
+
+impl Generator {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Generator::Level => "level",
+            Generator::Motif => "motif",
+        };
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        return ALL_GENERATORS.iter().find(|x| x.name() == name).copied();
+    }
+}
+
+fn gen_level<R: Rng>(
+    level_distr_gen: &StructStatistic,
+    rnd: &mut R,
+    cp: u32,
+    part: u32,
+) -> Result<u32> {
+    return level_distr_gen
+        .gen_level(rnd, cp, part)
+        .ok_or(Error::NoStatistic {
+            name: String::from("level_distribute"),
+            cp: cp,
+            part: part,
+        });
+}
+
+fn asign_edge_for_incr<R: Rng>(
+    node_cnt: u32,
+    cp: u32,
+    part: u32,
+    node_level: &mut Vec<u32>,
+    level_gen: &LevelGenerator,
+    result_dag: &mut TaskDag,
+    rnd: &mut R,
+    level_distr_gen: &StructStatistic,
+) -> Result<()> {
+    let mut by_level = vec![Vec::<NodeIndex>::new(); cp as usize];
+    for i in 0..cp as usize {
+        by_level[i].push(NodeIndex::new(i));
+    }
+    let mut queue_to_assign = queue![NodeIndex::new(0)];
+    let mut free_node_to_asign = node_cnt - cp;
+    let mut last_level: i32 = 0;
+    let mut last_node_ind = cp;
+    while let Ok(cur_node_index) = queue_to_assign.remove() {
+        let cur_ind = cur_node_index.index();
+        let node_lv = node_level[cur_ind];
+        if (node_lv as i32 != last_level || queue_to_assign.size() == 0)
+            && last_level + 1 < cp as i32
+        {
+            last_level += 1;
+            queue_to_assign
+                .add(NodeIndex::new(last_level as usize))
+                .unwrap();
+        }
+        let mut child_cnt = level_gen
+            .get_statistic(cp, part, node_lv, "childs_distribution", rnd)?
+            .ceil() as usize;
+        if cur_ind < cp as usize && child_cnt > 0 {
+            child_cnt -= 1;
+        }
+        for _ in 0..child_cnt {
+            free_node_to_asign -= 1;
+
+            let child_node_ind = NodeIndex::new(last_node_ind as usize);
+            last_node_ind += 1;
+            result_dag.add_task_endge(child_node_ind, cur_node_index);
+            queue_to_assign.add(child_node_ind).unwrap();
+
+            node_level[child_node_ind.index()] = node_lv + 1;
+            by_level[(node_lv + 1) as usize].push(child_node_ind);
+
+            if free_node_to_asign == 0 {
+                break;
+            }
+        }
+        if free_node_to_asign == 0 {
+            break;
+        }
+    }
+
+    for _ in 0..free_node_to_asign as usize {
+        node_level[last_node_ind as usize] = gen_level(level_distr_gen, rnd, cp, part)?.max(1);
+        let parent_level = node_level[last_node_ind as usize] - 1;
+        let parent_ind = rnd.gen_range(0..(by_level[parent_level as usize].len()));
+        result_dag.add_task_endge(
+            NodeIndex::new(last_node_ind as usize),
+            NodeIndex::new(parent_ind),
+        );
+        last_node_ind += 1;
+    }
+    return Ok(());
+}
+
+fn asign_edge_for_decr<R: Rng>(
+    node_cnt: u32,
+    cp: u32,
+    part: u32,
+    node_level: &mut Vec<u32>,
+    level_gen: &LevelGenerator,
+    result_dag: &mut TaskDag,
+    rnd: &mut R,
+    level_distr_gen: &StructStatistic,
+) -> Result<()> {
+    let mut by_level = vec![Vec::<NodeIndex>::new(); cp as usize];
+    for i in 0..cp as usize {
+        by_level[i].push(NodeIndex::new(i));
+    }
+
+    let mut queue_to_assign = queue![NodeIndex::new((cp - 1) as usize)];
+    let mut free_node_to_asign = node_cnt - cp;
+    let mut last_level: i32 = cp as i32 - 1;
+    let mut last_node_ind = cp;
+    while let Ok(cur_node_index) = queue_to_assign.remove() {
+        let cur_ind = cur_node_index.index();
+        let node_lv = node_level[cur_ind];
+        if (node_lv as i32 != last_level || queue_to_assign.size() == 0) && last_level > 0 {
+            last_level -= 1;
+            queue_to_assign
+                .add(NodeIndex::new(last_level as usize))
+                .unwrap();
+        }
+        let mut parent_cnt = level_gen
+            .get_statistic(cp, part, node_lv, "dependances_distribution", rnd)?
+            .ceil() as usize;
+        if cur_ind < cp as usize && parent_cnt > 0 {
+            parent_cnt -= 1;
+        }
+        for _ in 0..parent_cnt {
+            free_node_to_asign -= 1;
+
+            let parent_node_ind = NodeIndex::new(last_node_ind as usize);
+            by_level[(node_lv - 1) as usize].push(parent_node_ind);
+            last_node_ind += 1;
+            result_dag.add_task_endge(cur_node_index, parent_node_ind);
+            queue_to_assign.add(parent_node_ind).unwrap();
+
+            node_level[parent_node_ind.index()] = node_lv - 1;
+
+            if free_node_to_asign == 0 {
+                break;
+            }
+        }
+        if free_node_to_asign == 0 {
+            break;
+        }
+    }
+
+    for _ in 0..free_node_to_asign as usize {
+        node_level[last_node_ind as usize] = gen_level(level_distr_gen, rnd, cp, part)?.min(cp - 2);
+        let child_level = node_level[last_node_ind as usize] + 1;
+        let child_ind = rnd.gen_range(0..(by_level[child_level as usize].len()));
+        result_dag.add_task_endge(
+            NodeIndex::new(child_ind),
+            NodeIndex::new(last_node_ind as usize),
+        );
+        last_node_ind += 1;
+    }
+    return Ok(());
+}
+
+fn asign_edge_for_other<R: Rng>(
+    node_cnt: u32,
+    cp: u32,
+    part: u32,
+    node_level: &mut Vec<u32>,
+    level_gen: &LevelGenerator,
+    result_dag: &mut TaskDag,
+    rnd: &mut R,
+    level_distr_gen: &StructStatistic,
+) -> Result<()> {
+    let mut by_level = vec![Vec::<NodeIndex>::new(); cp as usize];
+    for i in 0..node_cnt {
+        let cur_node_level = if i < cp {
+            i as u32
+        } else {
+            gen_level(level_distr_gen, rnd, cp, part)?
+        };
+
+        node_level[i as usize] = cur_node_level;
+        by_level[cur_node_level as usize].push(NodeIndex::new(i as usize));
+    }
+    for level in 0..(cp - 1) {
+        for node in by_level[level as usize].iter() {
+            let child_cnt = level_gen
+                .get_statistic(cp, part, level, "childs_distribution", rnd)?
+                .ceil() as u32;
+            let child_cnt = child_cnt.max(1);
+            let mut next_level = by_level[(level + 1) as usize].clone();
+            next_level.shuffle(rnd);
+            for child_ind in 0..next_level.len().min(child_cnt as usize) {
+                if node.index() < cp as usize && next_level[child_ind].index() < cp as usize {
+                    continue;
+                }
+
+                result_dag.add_task_endge(next_level[child_ind], *node);
+            }
+        }
+    }
+    for node in by_level[cp as usize - 1].iter() {
+        if result_dag.node_weight(*node).unwrap().dependencies.len() == 0 {
+            let parent_ind = rnd.gen_range(0..by_level[cp as usize - 2].len());
+            result_dag.add_task_endge(*node, by_level[cp as usize - 2][parent_ind]);
+        }
+    }
+    return Ok(());
+}
+
+// critical path is already a chain
+fn asign_edge_for_chain<R: Rng>(
+    _node_cnt: u32,
+    _cp: u32,
+    _part: u32,
+    _node_level: &mut Vec<u32>,
+    _level_gen: &LevelGenerator,
+    _result_dag: &mut TaskDag,
+    _rnd: &mut R,
+    _level_distr_gen: &StructStatistic,
+) -> Result<()> {
+    return Ok(());
+}
+
+// chain of branch_len new tasks from one task of critical path to another
+fn add_branch(
+    from: u32,
+    to: u32,
+    branch_len: u32,
+    node_level: &mut Vec<u32>,
+    result_dag: &mut TaskDag,
+    last_node_ind: &mut u32,
+) {
+    let mut parent = NodeIndex::new(from as usize);
+    for i in 0..branch_len {
+        let node = NodeIndex::new(*last_node_ind as usize);
+        *last_node_ind += 1;
+        node_level[node.index()] = from + 1 + i;
+        result_dag.add_task_endge(node, parent);
+        parent = node;
+    }
+    result_dag.add_task_endge(NodeIndex::new(to as usize), parent);
+}
+
+// fork, the only level of parallel tasks and join
+pub const DIAMOND_CP: u32 = 3;
+
+// fork is the first task of critical path, join is the last one
+fn asign_edge_for_fork_join<R: Rng>(
+    node_cnt: u32,
+    cp: u32,
+    _part: u32,
+    node_level: &mut Vec<u32>,
+    _level_gen: &LevelGenerator,
+    result_dag: &mut TaskDag,
+    rnd: &mut R,
+    _level_distr_gen: &StructStatistic,
+) -> Result<()> {
+    let mut free_node_to_asign = node_cnt.saturating_sub(cp);
+    // branches are between fork and join
+    let max_branch_len = cp.saturating_sub(2);
+    if free_node_to_asign > 0 && max_branch_len == 0 {
+        return Err(Error::Config(format!(
+            "fork-join needs critical path of 3 tasks at least, got {}",
+            cp
+        )));
+    }
+    let mut last_node_ind = cp;
+    while free_node_to_asign > 0 {
+        let branch_len = rnd.gen_range(1..=max_branch_len.min(free_node_to_asign));
+        add_branch(
+            0,
+            cp - 1,
+            branch_len,
+            node_level,
+            result_dag,
+            &mut last_node_ind,
+        );
+        free_node_to_asign -= branch_len;
+    }
+    return Ok(());
+}
+
+// fork, one level of tasks, join: every task besides critical path is a branch of one task
+fn asign_edge_for_diamond<R: Rng>(
+    node_cnt: u32,
+    cp: u32,
+    _part: u32,
+    node_level: &mut Vec<u32>,
+    _level_gen: &LevelGenerator,
+    result_dag: &mut TaskDag,
+    _rnd: &mut R,
+    _level_distr_gen: &StructStatistic,
+) -> Result<()> {
+    if cp != DIAMOND_CP {
+        return Err(Error::Config(format!(
+            "diamond needs critical path of {} tasks, got {}",
+            DIAMOND_CP, cp
+        )));
+    }
+    let mut last_node_ind = cp;
+    for _ in cp..node_cnt {
+        add_branch(0, cp - 1, 1, node_level, result_dag, &mut last_node_ind);
+    }
+    return Ok(());
+}
+
+fn asign_edge_for_layered<R: Rng>(
+    node_cnt: u32,
+    cp: u32,
+    part: u32,
+    node_level: &mut Vec<u32>,
+    _level_gen: &LevelGenerator,
+    result_dag: &mut TaskDag,
+    rnd: &mut R,
+    level_distr_gen: &StructStatistic,
+) -> Result<()> {
+    let mut by_level = vec![Vec::<NodeIndex>::new(); cp as usize];
+    for i in 0..node_cnt {
+        let cur_node_level = if i < cp {
+            i as u32
+        } else {
+            gen_level(level_distr_gen, rnd, cp, part)?
+        };
+
+        node_level[i as usize] = cur_node_level;
+        by_level[cur_node_level as usize].push(NodeIndex::new(i as usize));
+    }
+    for level in 0..(cp - 1) as usize {
+        for node in by_level[level].iter() {
+            for child in by_level[level + 1].iter() {
+                if node.index() < cp as usize && child.index() < cp as usize {
+                    continue;
+                }
+                result_dag.add_task_endge(*child, *node);
+            }
+        }
+    }
+    return Ok(());
+}
+
+// Block of critical path tasks first..first + length - 1 gets extra tasks. It is split
+// in series into two blocks or gets parallel branches around its inner block
+fn asign_sp_block<R: Rng>(
+    first: u32,
+    length: u32,
+    extra: u32,
+    node_level: &mut Vec<u32>,
+    result_dag: &mut TaskDag,
+    last_node_ind: &mut u32,
+    rnd: &mut R,
+) -> Result<()> {
+    if extra == 0 {
+        return Ok(());
+    }
+    if length < 3 {
+        return Err(Error::Config(format!(
+            "no room for {} tasks in series-parallel block of {} levels",
+            extra, length
+        )));
+    }
+    if length >= 4 && rnd.gen_bool(0.5) {
+        let left = rnd.gen_range(1..length);
+        let right = length - left;
+        let left_extra = match (left >= 3, right >= 3) {
+            (true, true) => Some(rnd.gen_range(0..=extra)),
+            (true, false) => Some(extra),
+            (false, true) => Some(0),
+            (false, false) => None,
+        };
+        if let Some(left_extra) = left_extra {
+            asign_sp_block(
+                first,
+                left,
+                left_extra,
+                node_level,
+                result_dag,
+                last_node_ind,
+                rnd,
+            )?;
+            return asign_sp_block(
+                first + left,
+                right,
+                extra - left_extra,
+                node_level,
+                result_dag,
+                last_node_ind,
+                rnd,
+            );
+        }
+    }
+
+    let inner = length - 2;
+    let inner_extra = if inner >= 3 {
+        rnd.gen_range(0..=extra)
+    } else {
+        0
+    };
+    let mut branch_extra = extra - inner_extra;
+    while branch_extra > 0 {
+        let branch_len = rnd.gen_range(1..=inner.min(branch_extra));
+        add_branch(
+            first,
+            first + length - 1,
+            branch_len,
+            node_level,
+            result_dag,
+            last_node_ind,
+        );
+        branch_extra -= branch_len;
+    }
+    return asign_sp_block(
+        first + 1,
+        inner,
+        inner_extra,
+        node_level,
+        result_dag,
+        last_node_ind,
+        rnd,
+    );
+}
+
+fn asign_edge_for_sp<R: Rng>(
+    node_cnt: u32,
+    cp: u32,
+    _part: u32,
+    node_level: &mut Vec<u32>,
+    _level_gen: &LevelGenerator,
+    result_dag: &mut TaskDag,
+    rnd: &mut R,
+    _level_distr_gen: &StructStatistic,
+) -> Result<()> {
+    let mut last_node_ind = cp;
+    return asign_sp_block(
+        0,
+        cp,
+        node_cnt - cp,
+        node_level,
+        result_dag,
+        &mut last_node_ind,
+        rnd,
+    );
+}
+
+// ranges no dag of graph type fits in are wrong for every sample
+pub fn check_cp_range(graph_type: GraphType, min_cp: u32, max_cp: u32) -> Result<()> {
+    if graph_type == GraphType::Diamond && !(min_cp..=max_cp).contains(&DIAMOND_CP) {
+        return Err(Error::Config(format!(
+            "diamond has critical path of {} tasks, not in {}..={}",
+            DIAMOND_CP, min_cp, max_cp
+        )));
+    }
+    return Ok(());
+}
+
+pub fn gen_task_graph<R: Rng>(
+    stats: &FittedStats,
+    graph_type: GraphType,
+    min_cp: u32,
+    max_cp: u32,
+    rnd: &mut R,
+) -> Result<TaskDag> {
+    let level_distr_gen = &stats.level_distr_gen;
+    let level_gen = &stats.level_gen;
+
+    check_cp_range(graph_type, min_cp, max_cp)?;
+    // diamonds are three levels deep
+    let (cp, node_cnt) = match graph_type {
+        GraphType::Diamond => gen_size(stats, DIAMOND_CP, DIAMOND_CP, rnd)?,
+        _ => gen_size(stats, min_cp, max_cp, rnd)?,
+    };
+    // chains have no tasks besides critical path
+    let node_cnt = match graph_type {
+        GraphType::Chain => cp,
+        _ => node_cnt,
+    };
+    let mut result_dag = TaskDag::new();
+
+    let mut part = calc_part(node_cnt, cp);
+    level_distr_gen.adjust_part(cp, &mut part)?;
+
+    let mut node_level: Vec<u32> = vec![0; node_cnt as usize];
+    for i in 0..cp {
+        *node_level.get_mut(i as usize).unwrap() = i;
+    }
+
+    // Add empty nodes to graph
+    for i in 0..node_cnt {
+        result_dag.add_node(DagVertex {
+            task_name: format!("task_{}", i),
+            dependencies: Vec::new(),
+            instance_cnt: 0,
+            flops: 0.0,
+            cpu: DEFAULT_CPU,
+            mem: DEFAULT_MEM,
+            dispersion: Dispersion::default(),
+            patterns: BTreeMap::new(),
+        });
+    }
+
+    for i in 1..cp as usize {
+        node_level[i] = i as u32;
+        result_dag.add_task_endge(NodeIndex::new(i), NodeIndex::new(i - 1));
+    }
+    let asign_edge = match graph_type {
+        GraphType::Chain => asign_edge_for_chain::<R>,
+        GraphType::TreeIncr => asign_edge_for_incr::<R>,
+        GraphType::TreeDecr => asign_edge_for_decr::<R>,
+        GraphType::Diamond => asign_edge_for_diamond::<R>,
+        GraphType::ForkJoin => asign_edge_for_fork_join::<R>,
+        GraphType::LayeredBipartite => asign_edge_for_layered::<R>,
+        GraphType::SeriesParallel => asign_edge_for_sp::<R>,
+        GraphType::Other => asign_edge_for_other::<R>,
+    };
+    asign_edge(
+        node_cnt,
+        cp,
+        part,
+        &mut node_level,
+        level_gen,
+        &mut result_dag,
+        rnd,
+        level_distr_gen,
+    )?;
+    asign_weights(cp, part, &node_level, level_gen, &mut result_dag, rnd)?;
+    asign_patterns(&stats.patterns, &mut result_dag, rnd);
+    asign_edge_sizes(&stats.edge_data, &mut result_dag, rnd);
+    return Ok(result_dag);
+}
+
+// critical path and number of tasks of a new dag
+pub fn gen_size<R: Rng>(
+    stats: &FittedStats,
+    min_cp: u32,
+    max_cp: u32,
+    rnd: &mut R,
+) -> Result<(u32, u32)> {
+    let cp_gen_ranges = &stats.cp_ranges;
+    if !(min_cp..=max_cp).any(|cp| cp_gen_ranges.has_cp(cp)) {
+        return Err(Error::Config(format!(
+            "no fitted dags with critical path in {}..={}",
+            min_cp, max_cp
+        )));
+    }
+
+    loop {
+        let cp = rnd.gen_range(min_cp..=max_cp) as u32;
+
+        if let Some(node_cnt) = cp_gen_ranges.get_node_cnt(rnd, cp) {
+            return Ok((cp, node_cnt));
+        }
+    }
+}
+
+// instances and time of every task by its level, instances of task with parents are
+// relative to instances of its parents
+pub fn asign_weights<R: Rng>(
+    cp: u32,
+    part: u32,
+    node_level: &Vec<u32>,
+    level_gen: &LevelGenerator,
+    result_dag: &mut TaskDag,
+    rnd: &mut R,
+) -> Result<()> {
+    for i in 0..result_dag.node_count() {
+        let cur_node_ind = NodeIndex::new(i);
+        let node_lv = node_level[i];
+        let mut instance_cnt: f64;
+        let parents_vector = &result_dag.node_weight(cur_node_ind).unwrap().dependencies;
+        if parents_vector.len() != 0 {
+            instance_cnt =
+                level_gen.get_statistic(cp, part, node_lv, "instance_distr_perc", rnd)?;
+            let mut avg_parent_ins: f64 = 0.0;
+            for parent in parents_vector.iter() {
+                avg_parent_ins += result_dag
+                    .node_weight(NodeIndex::new(*parent as usize))
+                    .unwrap()
+                    .instance_cnt as f64;
+            }
+            avg_parent_ins /= parents_vector.len() as f64;
+            instance_cnt = avg_parent_ins as f64 * instance_cnt / 10000.0;
+        } else {
+            // real dags may have no sources on the level, they have them on level 0
+            instance_cnt =
+                match level_gen.get_statistic(cp, part, node_lv, "instance_distr_init", rnd) {
+                    Ok(instance_cnt) => instance_cnt,
+                    Err(_) => level_gen.get_statistic(cp, part, 0, "instance_distr_init", rnd)?,
+                };
+        }
+        let instance_cnt = instance_cnt.ceil() as u64;
+
+        let flops_sz = level_gen.get_statistic(cp, part, node_lv, "time_distrib", rnd)?;
+        // stats fitted before demands were learned leave the defaults
+        let cpu = if level_gen.has_statistic("cpu_distrib") {
+            level_gen.get_statistic(cp, part, node_lv, "cpu_distrib", rnd)?
+        } else {
+            DEFAULT_CPU
+        };
+        let mem = if level_gen.has_statistic("mem_distrib") {
+            level_gen.get_statistic(cp, part, node_lv, "mem_distrib", rnd)?
+        } else {
+            DEFAULT_MEM
+        };
+
+        // tasks with one instance and stats without spread keep all instances equal
+        let dispersion = if instance_cnt > 1 && level_gen.has_statistic("time_cv") {
+            Dispersion {
+                cv: level_gen
+                    .get_statistic_or(cp, part, node_lv, "time_cv", 0.0, rnd)
+                    .max(0.0),
+                straggler_prob: level_gen
+                    .get_statistic_or(cp, part, node_lv, "straggler_prob", 0.0, rnd)
+                    .clamp(0.0, 1.0),
+                straggler_magnitude: level_gen.get_statistic_or(
+                    cp,
+                    part,
+                    node_lv,
+                    "straggler_magnitude",
+                    0.0,
+                    rnd,
+                ),
+            }
+        } else {
+            Dispersion::default()
+        };
+
+        let asign_weight = result_dag.node_weight_mut(cur_node_ind).unwrap();
+        asign_weight.instance_cnt = instance_cnt.min(MAX_INST_CNT).max(1);
+        asign_weight.flops = flops_sz;
+        asign_weight.cpu = cpu;
+        asign_weight.mem = mem;
+        asign_weight.dispersion = dispersion;
+    }
+    return Ok(());
+}
@@ -1,94 +1,79 @@
+use crate::compare::{instance_times, MeasuredDag, Metric, ALL_METRICS};
+use crate::error::{read_dir_sorted, write_file, Error, Result};
+use crate::exchange::ExchangeFormat;
 use crate::pure_dag::*;
 use crate::task_dag::*;
-use std::fs;
-use std::fs::File;
-use std::io::Write;
-use std::path::Path;
 
-fn current_measure(file_path: &str, graph: &TaskDag, str_bufer: &mut String) {
-    if file_path.ends_with("sparity") {
-        str_bufer.push_str(&format!("{} ", graph.sparity()));
-    } else if file_path.ends_with("chain_ration") {
-        str_bufer.push_str(&format!("{} ", graph.chain_ratio()));
-    } else if file_path.ends_with("in_deg") {
-        for deg in graph.in_degree() {
-            str_bufer.push_str(&format!("{} ", deg));
-        }
-    } else if file_path.ends_with("out_deg") {
-        for deg in graph.out_degree() {
-            str_bufer.push_str(&format!("{} ", deg));
-        }
-    } else if file_path.ends_with("ins_ratio") {
-        for deg in graph.pairwise_ins_ration() {
-            str_bufer.push_str(&format!("{} ", deg));
-        }
-    } else if file_path.ends_with("time_ratio") {
-        for deg in graph.pairwise_flops_ration() {
-            str_bufer.push_str(&format!("{} ", deg));
-        }
-    } else if file_path.ends_with("wide_dependen") {
-    } else if file_path.ends_with("narrow_dependen") {
-    } else {
-        panic!("not implemented");
+// metric is chosen by suffix of output file
+fn file_metric(file_path: &str) -> Result<Metric> {
+    return match ALL_METRICS.iter().find(|x| file_path.ends_with(x.name())) {
+        Some(metric) => Ok(*metric),
+        None => Err(Error::Config(format!(
+            "statistic for {} is not implemented",
+            file_path
+        ))),
+    };
+}
+
+fn current_measure(metric: Metric, measured: &MeasuredDag, str_bufer: &mut String) -> Result<()> {
+    for value in metric.values(measured)? {
+        str_bufer.push_str(&format!("{} ", value));
     }
+    return Ok(());
 }
 
-pub fn stat_pure_dags(tt_input_dir: String, output_file: &str) {
+pub fn stat_pure_dags(tt_input_dir: String, output_file: &str) -> Result<()> {
     // Examples of calc stat
-    let paths = fs::read_dir(tt_input_dir).unwrap();
-
-    let path = Path::new(output_file);
-    let mut file = match File::create(&path) {
-        Err(why) => panic!("cant open file to write {}", why),
-        Ok(file) => file,
-    };
-
     let mut str_bufer = String::new();
     let mut graphs_count: u64 = 0;
-    for path in paths {
-        let path = path.unwrap().path().display().to_string();
-
-        let mut pure_dags = PureDags::get_from_file(path.as_str());
+    let metric = file_metric(output_file)?;
+    for path in read_dir_sorted(&tt_input_dir)? {
+        let pure_dags = PureDags::get_from_file(path.as_str())?;
         println!("Real work just starts");
 
-        for (_job_name, graph) in pure_dags.dags.iter_mut() {
-            let graph = <TaskDag as TaskDagFuncs>::from_pure_dag(&graph);
-            current_measure(output_file, &graph, &mut str_bufer);
+        for (job_name, graph) in pure_dags.dags.iter() {
+            let measured = <TaskDag as TaskDagFuncs>::from_pure_dag(&graph).and_then(|task_dag| {
+                let measured =
+                    MeasuredDag::new(&task_dag).with_instance_work(instance_times(graph));
+                current_measure(metric, &measured, &mut str_bufer)
+            });
+            if let Err(err) = measured {
+                eprintln!("skip {}", err.in_job(job_name));
+                continue;
+            }
             graphs_count += 1;
         }
     }
-    match file.write_all(str_bufer.as_bytes()) {
-        Err(why) => panic!("cant save serialization {}", why),
-        Ok(_) => {}
-    }
+    write_file(output_file, &str_bufer)?;
     println!("overal graphs: {}", graphs_count);
+    return Ok(());
 }
 
-pub fn stat_task_dags(tt_input_dir: String, output_file: &str) {
-    let paths = fs::read_dir(tt_input_dir).unwrap();
-
-    let path = Path::new(output_file);
-    let mut file = match File::create(&path) {
-        Err(why) => panic!("cant open file to write {}", why),
-        Ok(file) => file,
-    };
-
+// format is extension of files to read: json, stg or dax
+pub fn stat_task_dags(tt_input_dir: String, output_file: &str, format: &str) -> Result<()> {
     let mut str_bufer = String::new();
+    let metric = file_metric(output_file)?;
 
-    for path in paths {
-        let path = path.unwrap().path().display().to_string();
-
-        if path.ends_with("dot") {
+    for path in read_dir_sorted(&tt_input_dir)? {
+        if !path.ends_with(&format!(".{}", format)) {
             continue;
         }
 
-        let mut task_dag = TaskDag::new();
-        task_dag.load_from_file(path.as_str());
-
-        current_measure(output_file, &task_dag, &mut str_bufer);
-    }
-    match file.write_all(str_bufer.as_bytes()) {
-        Err(why) => panic!("cant save serialization {}", why),
-        Ok(_) => {}
+        let loaded = match format {
+            "json" => {
+                let mut task_dag = TaskDag::new();
+                task_dag.load_from_file(path.as_str()).map(|_| task_dag)
+            }
+            "stg" => TaskDag::load_from_stg(path.as_str()),
+            "dax" => TaskDag::load_from_dax(path.as_str()),
+            _ => return Err(Error::Config(format!("unknown dag format {}", format))),
+        };
+        let measured = loaded.and_then(|task_dag| {
+            current_measure(metric, &MeasuredDag::new(&task_dag), &mut str_bufer)
+        });
+        if let Err(err) = measured {
+            eprintln!("skip {}", err);
+        }
     }
+    return write_file(output_file, &str_bufer);
 }
@@ -1,184 +1,339 @@
+use crate::edge_data::DATA_SIZE_ESTIMATE;
+use crate::error::{read_dir_sorted, write_file, Result};
 use crate::instance::*;
+use crate::pipeline::{dag_name, generated_names};
 use crate::pure_dag::*;
+use crate::statistic::MetricSerial;
 use crate::task_dag::*;
-use rand::rngs::ThreadRng;
-use std::fs;
-use std::fs::File;
-use std::io::Write;
-use std::path::Path;
-const ranges: [(usize, usize); 6] = [(2, 4), (5, 7), (8, 10), (11, 14), (15, 18), (19, 24)];
-
-fn init_chars() -> (Vec<InstGrapgChar>, Vec<InstGrapgChar>) {
-    let mut init = InstGrapgChar {
-        depth: 0,
-        max_data: 0.0,
-        max_work: 0.0,
-        paralel: 0.0,
-        tasks_cnt: 0,
-        width: 0,
-    };
-    let mut hash_maxs = vec![init; ranges.len()];
-    init = InstGrapgChar {
-        depth: usize::MAX,
-        max_data: f64::MAX,
-        max_work: f64::MAX,
-        paralel: f64::MAX,
-        tasks_cnt: usize::MAX,
-        width: usize::MAX,
-    };
-    let mut hash_mins = vec![init.clone(); ranges.len()];
-    (hash_mins, hash_maxs)
+use rand::Rng;
+
+const DEPTH_RANGES: [(usize, usize); 6] = [(2, 4), (5, 7), (8, 10), (11, 14), (15, 18), (19, 24)];
+
+const CHAR_NAMES: [&str; 6] = [
+    "tasks_cnt",
+    "depth",
+    "width",
+    "paralel",
+    "max_work",
+    "max_data",
+];
+
+const SUMMARY_NAMES: [&str; 8] = ["min", "p10", "p25", "median", "mean", "p75", "p90", "max"];
+const SUMMARY_PERCENTILES: [f64; 5] = [0.1, 0.25, 0.5, 0.75, 0.9];
+
+fn char_values(stat: &InstGrapgChar) -> [f64; 6] {
+    return [
+        stat.tasks_cnt as f64,
+        stat.depth as f64,
+        stat.width as f64,
+        stat.paralel,
+        stat.max_work,
+        stat.max_data,
+    ];
 }
 
-fn current_measure(
-    graph: &InstanceDag,
-    hash_mins: &mut Vec<InstGrapgChar>,
-    hash_maxs: &mut Vec<InstGrapgChar>,
-) {
-    let stat = graph.get_all();
-    for (ind, range) in ranges.iter().enumerate() {
-        if range.0 <= stat.depth && stat.depth <= range.1 {
-            let mins = hash_mins.get_mut(ind).unwrap();
-            let maxs = hash_maxs.get_mut(ind).unwrap();
-
-            mins.tasks_cnt = mins.tasks_cnt.min(stat.tasks_cnt);
-            mins.width = mins.width.min(stat.width);
-            mins.paralel = mins.paralel.min(stat.paralel);
-            mins.max_work = mins.max_work.min(stat.max_work);
-            mins.max_data = mins.max_data.min(stat.max_data);
-
-            maxs.tasks_cnt = maxs.tasks_cnt.max(stat.tasks_cnt);
-            maxs.width = maxs.width.max(stat.width);
-            maxs.paralel = maxs.paralel.max(stat.paralel);
-            maxs.max_work = maxs.max_work.max(stat.max_work);
-            maxs.max_data = maxs.max_data.max(stat.max_data);
-        }
-    }
+// characteristics of instance dags for every depth range
+struct CharSeries {
+    // range -> characteristic -> values
+    values: Vec<Vec<Vec<f64>>>,
+    dags_cnt: Vec<usize>,
 }
 
-pub fn char_pure_dags(tt_input_dir: &String, graph_type: &str, output_file: &str) {
-    // Examples of calc stat
-    let paths = fs::read_dir(tt_input_dir).unwrap();
+impl CharSeries {
+    fn new() -> Self {
+        return CharSeries {
+            values: vec![vec![Vec::new(); CHAR_NAMES.len()]; DEPTH_RANGES.len()],
+            dags_cnt: vec![0; DEPTH_RANGES.len()],
+        };
+    }
 
-    let path = Path::new(output_file);
-    let mut file = match File::create(&path) {
-        Err(why) => panic!("cant open file to write {}", why),
-        Ok(file) => file,
-    };
-    let (mut hash_mins, mut hash_maxs) = init_chars();
+    fn add(&mut self, graph: &InstanceDag) -> Result<()> {
+        let stat = graph.get_all()?;
+        for (ind, range) in DEPTH_RANGES.iter().enumerate() {
+            if range.0 <= stat.depth && stat.depth <= range.1 {
+                self.dags_cnt[ind] += 1;
+                for (values, value) in self.values[ind].iter_mut().zip(char_values(&stat)) {
+                    values.push(value);
+                }
+            }
+        }
+        return Ok(());
+    }
+}
 
-    for (ind, hm) in hash_mins.iter_mut().enumerate() {
-        hm.depth = ranges[ind].0;
+// SUMMARY_NAMES of values, None if there are no values
+fn summary(values: &Vec<f64>) -> Option<Vec<f64>> {
+    if values.is_empty() {
+        return None;
     }
-    for (ind, hm) in hash_maxs.iter_mut().enumerate() {
-        hm.depth = ranges[ind].1;
+    let mut serial = MetricSerial::new();
+    for value in values.iter() {
+        serial.add(*value);
     }
+    let percentiles = serial.percentiles(&SUMMARY_PERCENTILES).ok()??;
+    let min = values.iter().copied().fold(f64::MAX, f64::min);
+    let max = values.iter().copied().fold(f64::MIN, f64::max);
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    return Some(vec![
+        min,
+        percentiles[0],
+        percentiles[1],
+        percentiles[2],
+        mean,
+        percentiles[3],
+        percentiles[4],
+        max,
+    ]);
+}
 
-    let mut rnd = rand::thread_rng();
-
-    let mut str_bufer = String::new();
-    for path in paths {
-        let path = path.unwrap().path().display().to_string();
+fn char_pure_dags<R: Rng>(
+    tt_input_dir: &str,
+    graph_type: &str,
+    ccr: Option<CcrTarget>,
+    rnd: &mut R,
+) -> Result<CharSeries> {
+    let mut result = CharSeries::new();
+    for path in read_dir_sorted(tt_input_dir)? {
         if !path.contains(graph_type) {
             continue;
         }
 
-        let mut pure_dags = PureDags::get_from_file(path.as_str());
+        let mut pure_dags = PureDags::get_from_file(path.as_str())?;
         println!("Real work just starts");
 
-        for (_job_name, graph) in pure_dags.dags.iter_mut() {
-            graph.sort_node_ids();
-
-            let graph = <TaskDag as TaskDagFuncs>::from_pure_dag(&graph);
-            current_measure(
-                &graph.convert_to_inst_dag(&mut rnd, 11.0),
-                &mut hash_mins,
-                &mut hash_maxs,
-            );
+        let mut job_names: Vec<String> = pure_dags.dags.keys().cloned().collect();
+        job_names.sort();
+        for job_name in job_names.iter() {
+            let graph = pure_dags.dags.get_mut(job_name).unwrap();
+            let graph = match graph
+                .sort_node_ids()
+                .and_then(|_| <TaskDag as TaskDagFuncs>::from_pure_dag(&graph))
+            {
+                Ok(graph) => graph,
+                Err(err) => {
+                    eprintln!("skip {}", err.in_job(job_name));
+                    continue;
+                }
+            };
+            if let Err(err) = result.add(&graph.convert_to_inst_dag(rnd, ccr)) {
+                eprintln!("skip {}", err.in_job(job_name));
+            }
         }
     }
+    return Ok(result);
+}
 
-    for (hmin, hmax) in hash_mins.iter_mut().zip(hash_maxs.iter_mut()) {
-        str_bufer += &format!(
-            "{}_real,{}-{},{}-{},{}-{},{}-{},{}-{},{}-{}\n",
-            graph_type,
-            hmin.tasks_cnt,
-            hmax.tasks_cnt,
-            hmin.depth,
-            hmax.depth,
-            hmin.width,
-            hmax.width,
-            hmin.paralel,
-            hmax.paralel,
-            hmin.max_work,
-            hmax.max_work,
-            hmin.max_data,
-            hmax.max_data
-        );
-    }
-    match file.write_all(str_bufer.as_bytes()) {
-        Err(why) => panic!("cant save serialization {}", why),
-        Ok(_) => {}
+// real samples in the same directory are skipped
+fn char_task_dags<R: Rng>(
+    tt_input_dir: &str,
+    ccr: Option<CcrTarget>,
+    rnd: &mut R,
+) -> Result<CharSeries> {
+    let mut result = CharSeries::new();
+    let generated = generated_names(tt_input_dir)?;
+    for path in read_dir_sorted(tt_input_dir)? {
+        if !path.ends_with(".json") || !generated.contains(&dag_name(&path)) {
+            continue;
+        }
+
+        let mut task_dag = TaskDag::new();
+        if let Err(err) = task_dag.load_from_file(path.as_str()) {
+            eprintln!("skip {}", err);
+            continue;
+        }
+        if let Err(err) = result.add(&task_dag.convert_to_inst_dag(rnd, ccr)) {
+            eprintln!("skip {}", err.in_job(&path));
+        }
     }
+    return Ok(result);
 }
 
-pub fn char_task_dags(tt_input_dir: String, graph_type: &str, output_file: &str) {
-    let paths = fs::read_dir(tt_input_dir).unwrap();
-    let mut rnd = rand::thread_rng();
-    let (mut hash_mins, mut hash_maxs) = init_chars();
-    for (ind, hm) in hash_mins.iter_mut().enumerate() {
-        hm.depth = ranges[ind].0;
-    }
-    for (ind, hm) in hash_maxs.iter_mut().enumerate() {
-        hm.depth = ranges[ind].1;
-    }
+fn md_cell(summary: &Option<Vec<f64>>) -> String {
+    return match summary {
+        Some(s) => format!(
+            "{:.2} | {:.2} | {:.2}-{:.2} | {:.2}-{:.2}",
+            s[4], s[3], s[1], s[6], s[0], s[7]
+        ),
+        None => String::from("- | - | - | -"),
+    };
+}
+
+// Instance dags of real jobs and of generated task dags, both expanded the same way, by depth
+// range. Writes output_file.csv with a row per set, range and characteristic and
+// output_file.md with real and generated side by side
+pub fn ins_chars<R: Rng>(
+    real_dir: &str,
+    tasks_dir: &str,
+    graph_type: &str,
+    ccr: Option<CcrTarget>,
+    output_file: &str,
+    rnd: &mut R,
+) -> Result<()> {
+    let real = char_pure_dags(real_dir, graph_type, ccr, rnd)?;
+    let generated = char_task_dags(tasks_dir, ccr, rnd)?;
 
-    let path = Path::new(output_file);
-    let mut file = match File::create(&path) {
-        Err(why) => panic!("cant open file to write {}", why),
-        Ok(file) => file,
+    let mut csv_bufer = format!(
+        "graph_type,set,depth_range,dags,characteristic,{}\n",
+        SUMMARY_NAMES.join(",")
+    );
+    let ccr_name = match ccr {
+        Some(ccr) => format!("ccr {} {}", ccr.definition.name(), ccr.value),
+        None => format!(
+            "learned data sizes, estimated as {} where trace has none",
+            DATA_SIZE_ESTIMATE
+        ),
     };
+    let mut md_bufer = format!("# {} instance dags, {}\n", graph_type, ccr_name);
+    for (ind, range) in DEPTH_RANGES.iter().enumerate() {
+        if real.dags_cnt[ind] == 0 && generated.dags_cnt[ind] == 0 {
+            continue;
+        }
+        md_bufer.push_str(&format!(
+            "\n## depth {}-{}: real {}, generated {}\n\n",
+            range.0, range.1, real.dags_cnt[ind], generated.dags_cnt[ind]
+        ));
+        md_bufer.push_str(
+            "| characteristic | real mean | real median | real p10-p90 | real min-max \
+             | gen mean | gen median | gen p10-p90 | gen min-max |\n",
+        );
+        md_bufer.push_str("|---|---|---|---|---|---|---|---|---|\n");
 
-    let mut str_bufer = String::new();
+        for (char_ind, char_name) in CHAR_NAMES.iter().enumerate() {
+            let real_summary = summary(&real.values[ind][char_ind]);
+            let gen_summary = summary(&generated.values[ind][char_ind]);
+            for (set, series, set_summary) in [
+                ("real", &real, &real_summary),
+                ("gen", &generated, &gen_summary),
+            ] {
+                if let Some(s) = set_summary {
+                    csv_bufer.push_str(&format!(
+                        "{},{},{}-{},{},{},{}\n",
+                        graph_type,
+                        set,
+                        range.0,
+                        range.1,
+                        series.dags_cnt[ind],
+                        char_name,
+                        s.iter()
+                            .map(|x| x.to_string())
+                            .collect::<Vec<_>>()
+                            .join(",")
+                    ));
+                }
+            }
+            md_bufer.push_str(&format!(
+                "| {} | {} | {} |\n",
+                char_name,
+                md_cell(&real_summary),
+                md_cell(&gen_summary)
+            ));
+        }
+    }
+    write_file(&format!("{}.csv", output_file), &csv_bufer)?;
+    return write_file(&format!("{}.md", output_file), &md_bufer);
+}
 
-    for path in paths {
-        let path = path.unwrap().path().display().to_string();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dispersion::Dispersion;
+    use crate::error::{read_file, recreate_dir};
+    use crate::generator::seeded_rng;
+    use crate::pipeline::MANIFEST_FILENAME;
+    use crate::wfcommons::REFERENCE_BANDWIDTH;
+    use petgraph::graph::{EdgeIndex, NodeIndex};
+    use std::collections::BTreeMap;
 
-        if path.ends_with("dot") {
-            continue;
+    // a -> b -> c with 1, 2 and 1 instances of 10 seconds, b reads 2 seconds of data from a
+    // at reference bandwidth, c reads nothing
+    fn chain() -> TaskDag {
+        let mut dag = TaskDag::new();
+        for (name, instance_cnt) in [("a", 1), ("b", 2), ("c", 1)] {
+            dag.add_node(DagVertex {
+                task_name: name.to_string(),
+                dependencies: Vec::new(),
+                instance_cnt: instance_cnt,
+                flops: 10.0,
+                cpu: DEFAULT_CPU,
+                mem: DEFAULT_MEM,
+                dispersion: Dispersion::default(),
+                patterns: BTreeMap::new(),
+            });
         }
-        println!("{}", path);
+        dag.add_task_endge(NodeIndex::new(1), NodeIndex::new(0));
+        dag.add_task_endge(NodeIndex::new(2), NodeIndex::new(1));
+        dag[EdgeIndex::new(0)] = 2 * REFERENCE_BANDWIDTH as u64;
+        dag[EdgeIndex::new(1)] = 0;
+        return dag;
+    }
 
-        let mut task_dag = TaskDag::new();
-        task_dag.load_from_file(path.as_str());
+    #[test]
+    fn chain_report_by_hand() {
+        let tmp = std::env::temp_dir().display().to_string();
+        let real_dir = format!("{}/cursach_chars_real", tmp);
+        let tasks_dir = format!("{}/cursach_chars_tasks", tmp);
+        recreate_dir(&real_dir).unwrap();
+        recreate_dir(&tasks_dir).unwrap();
+        chain()
+            .save_to_file(&format!("{}/gen_0.json", tasks_dir))
+            .unwrap();
+        // a real sample next to it is not counted as generated
+        let mut real = chain();
+        real.add_task_endge(NodeIndex::new(2), NodeIndex::new(0));
+        real.save_to_file(&format!("{}/real_0.json", tasks_dir))
+            .unwrap();
+        write_file(
+            &format!("{}/{}", tasks_dir, MANIFEST_FILENAME),
+            "gen_0,generated\nreal_0,real\n",
+        )
+        .unwrap();
 
-        current_measure(
-            &task_dag.convert_to_inst_dag(&mut rnd, 11.0),
-            &mut hash_mins,
-            &mut hash_maxs,
+        let output_file = format!("{}/cursach_chars", tmp);
+        let mut rnd = seeded_rng(3);
+        ins_chars(&real_dir, &tasks_dir, "other", None, &output_file, &mut rnd).unwrap();
+        let csv = read_file(&format!("{}.csv", output_file)).unwrap();
+        let md = read_file(&format!("{}.md", output_file)).unwrap();
+        for path in [
+            format!("{}.csv", output_file),
+            format!("{}.md", output_file),
+        ] {
+            std::fs::remove_file(path).unwrap();
+        }
+        std::fs::remove_dir_all(&real_dir).unwrap();
+        std::fs::remove_dir_all(&tasks_dir).unwrap();
+
+        // a_0 -> b_0, b_1 -> c_0: 4 instances on 3 levels, 2 wide, 40 seconds of work on
+        // a 31 second path, a sends a second of data to every b
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "graph_type,set,depth_range,dags,characteristic,min,p10,p25,median,mean,p75,p90,max"
         );
-    }
+        assert_eq!(lines.len(), 1 + CHAR_NAMES.len());
+        let expected = [4.0, 3.0, 2.0, 40.0 / 31.0, 10.0, REFERENCE_BANDWIDTH];
+        for (line, (char_name, value)) in lines[1..]
+            .iter()
+            .zip(CHAR_NAMES.iter().zip(expected.iter()))
+        {
+            let cells: Vec<&str> = line.split(',').collect();
+            assert_eq!(cells[..5], ["other", "gen", "2-4", "1", char_name]);
+            for cell in cells[5..].iter() {
+                let cell: f64 = cell.parse().unwrap();
+                assert!((cell - value).abs() < 1e-6, "{} is {}", char_name, cell);
+            }
+        }
 
-    for (hmin, hmax) in hash_mins.iter_mut().zip(hash_maxs.iter_mut()) {
-        str_bufer += &format!(
-            "{}_gen,{}-{},{}-{},{}-{},{}-{},{}-{},{}-{}\n",
-            graph_type,
-            hmin.tasks_cnt,
-            hmax.tasks_cnt,
-            hmin.depth,
-            hmax.depth,
-            hmin.width,
-            hmax.width,
-            hmin.paralel,
-            hmax.paralel,
-            hmin.max_work,
-            hmax.max_work,
-            hmin.max_data,
-            hmax.max_data
+        assert!(md.starts_with(&format!(
+            "# other instance dags, learned data sizes, estimated as {} where trace has none\n",
+            DATA_SIZE_ESTIMATE
+        )));
+        assert!(md.contains("\n## depth 2-4: real 0, generated 1\n"));
+        assert!(
+            md.contains("\n| tasks_cnt | - | - | - | - | 4.00 | 4.00 | 4.00-4.00 | 4.00-4.00 |\n")
         );
-    }
-    match file.write_all(str_bufer.as_bytes()) {
-        Err(why) => panic!("cant save serialization {}", why),
-        Ok(_) => {}
+        assert!(
+            md.contains("\n| paralel | - | - | - | - | 1.29 | 1.29 | 1.29-1.29 | 1.29-1.29 |\n")
+        );
+        assert!(!md.contains("## depth 5-7"));
     }
 }
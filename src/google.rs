@@ -0,0 +1,282 @@
+use crate::error::{Error, Result};
+use crate::pure_dag::{Instance, PureTaskInfo};
+use crate::source::{dag_from_parents, JobSink, TraceSource};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+// event types of Google cluster-data 2019
+const SCHEDULE: u64 = 3;
+const FAIL: u64 = 5;
+const FINISH: u64 = 6;
+const KILL: u64 = 7;
+const LOST: u64 = 8;
+
+// trace time is in microseconds, Alibaba one is in seconds
+const TIME_SCALE: u64 = 1000000;
+
+struct Collection {
+    parent: Option<u64>,
+    start_after: Vec<u64>,
+    finished: bool,
+    failed: bool,
+}
+
+#[derive(Default)]
+struct InstanceTimes {
+    schedule: Option<u64>,
+    finish: Option<u64>,
+    cpus: f64,
+    memory: f64,
+}
+
+// Google 2019 trace exported from BigQuery as json lines. Collections linked by
+// parent_collection_id or start_after_collection_ids form one job, only jobs where
+// all collections finished are kept
+pub struct GoogleSource {
+    pub collection_events_file: String,
+    pub instance_events_file: String,
+}
+
+// int64 are exported as strings
+fn get_u64(value: &Value, key: &str) -> Option<u64> {
+    return match value.get(key)? {
+        Value::Number(number) => number.as_u64(),
+        Value::String(string) => string.parse::<u64>().ok(),
+        _ => None,
+    };
+}
+
+fn get_f64(value: &Value, key: &str) -> Option<f64> {
+    return match value.get(key)? {
+        Value::Number(number) => number.as_f64(),
+        Value::String(string) => string.parse::<f64>().ok(),
+        _ => None,
+    };
+}
+
+// calls on_event for every parsed line, broken lines are reported and skipped
+fn read_events(filename: &str, mut on_event: impl FnMut(&Value)) -> Result<()> {
+    let file = File::open(filename).map_err(|why| Error::io(filename, why))?;
+    let mut lines: u64 = 0;
+    for line in BufReader::new(file).lines() {
+        lines += 1;
+        if lines % 10000000 == 0 {
+            println!("{}: lines {}", filename, lines);
+        }
+        let line = line.map_err(|why| Error::io(filename, why))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<Value>(&line) {
+            Ok(event) => on_event(&event),
+            Err(why) => eprintln!(
+                "skip {}: {}",
+                filename,
+                Error::Record {
+                    line: lines,
+                    reason: why.to_string(),
+                }
+            ),
+        }
+    }
+    return Ok(());
+}
+
+fn find_root(roots: &mut HashMap<u64, u64>, id: u64) -> u64 {
+    let mut root = id;
+    while roots[&root] != root {
+        root = roots[&root];
+    }
+    roots.insert(id, root);
+    return root;
+}
+
+impl GoogleSource {
+    fn read_collections(&self) -> Result<BTreeMap<u64, Collection>> {
+        let mut collections = BTreeMap::<u64, Collection>::new();
+        read_events(&self.collection_events_file, |event| {
+            let id = match get_u64(event, "collection_id") {
+                Some(id) => id,
+                None => return,
+            };
+            let collection = collections.entry(id).or_insert(Collection {
+                parent: None,
+                start_after: Vec::new(),
+                finished: false,
+                failed: false,
+            });
+            if let Some(parent) = get_u64(event, "parent_collection_id") {
+                if parent != 0 {
+                    collection.parent = Some(parent);
+                }
+            }
+            if let Some(Value::Array(ids)) = event.get("start_after_collection_ids") {
+                for id in ids.iter() {
+                    let id = match id {
+                        Value::Number(number) => number.as_u64(),
+                        Value::String(string) => string.parse::<u64>().ok(),
+                        _ => None,
+                    };
+                    if let Some(id) = id {
+                        if !collection.start_after.contains(&id) {
+                            collection.start_after.push(id);
+                        }
+                    }
+                }
+            }
+            match get_u64(event, "type") {
+                Some(FINISH) => collection.finished = true,
+                Some(FAIL) | Some(KILL) | Some(LOST) => collection.failed = true,
+                _ => {}
+            }
+        })?;
+        return Ok(collections);
+    }
+
+    // groups of linked collections, ids in every group are sorted
+    fn group_collections(collections: &BTreeMap<u64, Collection>) -> Vec<Vec<u64>> {
+        let mut roots: HashMap<u64, u64> = collections.keys().map(|id| (*id, *id)).collect();
+        for (id, collection) in collections.iter() {
+            let links = collection
+                .parent
+                .iter()
+                .chain(collection.start_after.iter());
+            for other in links {
+                if !collections.contains_key(other) {
+                    continue;
+                }
+                let a = find_root(&mut roots, *id);
+                let b = find_root(&mut roots, *other);
+                if a != b {
+                    roots.insert(a.max(b), a.min(b));
+                }
+            }
+        }
+
+        let mut groups = BTreeMap::<u64, Vec<u64>>::new();
+        for id in collections.keys() {
+            let root = find_root(&mut roots, *id);
+            groups.entry(root).or_insert(Vec::new()).push(*id);
+        }
+        return groups
+            .into_values()
+            .filter(|group| {
+                group.len() > 1
+                    && group
+                        .iter()
+                        .all(|id| collections[id].finished && !collections[id].failed)
+            })
+            .collect();
+    }
+
+    fn read_instances(
+        &self,
+        wanted: &HashMap<u64, usize>,
+    ) -> Result<HashMap<u64, BTreeMap<u64, InstanceTimes>>> {
+        let mut result = HashMap::<u64, BTreeMap<u64, InstanceTimes>>::new();
+        read_events(&self.instance_events_file, |event| {
+            let id = match get_u64(event, "collection_id") {
+                Some(id) if wanted.contains_key(&id) => id,
+                _ => return,
+            };
+            let index = get_u64(event, "instance_index").unwrap_or(0);
+            let times = result
+                .entry(id)
+                .or_insert(BTreeMap::new())
+                .entry(index)
+                .or_default();
+            let time = get_u64(event, "time");
+            match get_u64(event, "type") {
+                Some(SCHEDULE) => {
+                    times.schedule = time;
+                    if let Some(request) = event.get("resource_request") {
+                        times.cpus = get_f64(request, "cpus").unwrap_or(0.0);
+                        times.memory = get_f64(request, "memory").unwrap_or(0.0);
+                    }
+                }
+                Some(FINISH) => times.finish = time,
+                _ => {}
+            }
+        })?;
+        return Ok(result);
+    }
+}
+
+impl TraceSource for GoogleSource {
+    fn ingest_stream(&self, sink: &mut dyn JobSink) -> Result<()> {
+        let collections = self.read_collections()?;
+        let groups = GoogleSource::group_collections(&collections);
+        println!("Stayed jobs: {}", groups.len());
+
+        let mut wanted = HashMap::<u64, usize>::new();
+        for (group_ind, group) in groups.iter().enumerate() {
+            for id in group.iter() {
+                wanted.insert(*id, group_ind);
+            }
+        }
+        let mut instances = self.read_instances(&wanted)?;
+
+        for group in groups.iter() {
+            let position: HashMap<u64, usize> = group
+                .iter()
+                .enumerate()
+                .map(|(ind, id)| (*id, ind))
+                .collect();
+            let mut tasks = Vec::new();
+            let mut parents = Vec::new();
+            for id in group.iter() {
+                let collection = &collections[id];
+                let mut task_parents: Vec<(usize, u64)> = Vec::new();
+                for other in collection
+                    .parent
+                    .iter()
+                    .chain(collection.start_after.iter())
+                {
+                    if let Some(ind) = position.get(other) {
+                        if !task_parents.iter().any(|x| x.0 == *ind) {
+                            task_parents.push((*ind, 1));
+                        }
+                    }
+                }
+                parents.push(task_parents);
+
+                // normalized cpus and memory are turned into percents like in Alibaba trace
+                let task_instances: Vec<(u64, u64, f64, f64)> = instances
+                    .remove(id)
+                    .unwrap_or_default()
+                    .into_values()
+                    .filter_map(|times| match (times.schedule, times.finish) {
+                        (Some(start), Some(end)) if start <= end => Some((
+                            start / TIME_SCALE,
+                            end / TIME_SCALE,
+                            times.cpus * 100.0,
+                            times.memory * 100.0,
+                        )),
+                        _ => None,
+                    })
+                    .collect();
+                tasks.push(PureTaskInfo {
+                    name: String::new(),
+                    instance_cnt: task_instances.len() as u64,
+                    start_time: task_instances.iter().map(|x| x.0).min().unwrap_or(0),
+                    end_time: task_instances.iter().map(|x| x.1).max().unwrap_or(0),
+                    dependences: Vec::new(),
+                    instances: task_instances
+                        .iter()
+                        .map(|(start, end, cpu, memory)| Instance {
+                            time: end - start,
+                            cpu_avg: *cpu,
+                            cpu_diff_max: 0.0,
+                            mem_avg: *memory,
+                            mem_diff_max: 0.0,
+                        })
+                        .collect(),
+                });
+            }
+            sink.job(format!("g_{}", group[0]), dag_from_parents(tasks, &parents))?;
+        }
+        return sink.bucket_done(0);
+    }
+}
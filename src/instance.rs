@@ -1,16 +1,26 @@
-use petgraph::graph::Node;
 use petgraph::stable_graph::NodeIndex;
 
-use petgraph::{Directed, Graph};
-use std::fs::File;
-use std::io::Write;
+use crate::error::{write_file, Error, Result};
+use crate::task_dag::cores;
+use crate::wfcommons::{REFERENCE_BANDWIDTH, REFERENCE_MACHINE, REFERENCE_MACHINE_MEMORY};
+use petgraph::visit::EdgeRef;
+use petgraph::Direction::{Incoming, Outgoing};
+use petgraph::{algo::toposort, Directed, Graph};
+use serde_json::json;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub struct InstDagVertex {
     pub inst_name: String,
     pub dependencies: Vec<NodeIndex>,
+    // seconds on reference machine
     pub flops: f64,
+    // the same units as in DagVertex
+    pub cpu: f64,
+    pub mem: f64,
 }
 
+// edges are bytes child instance reads from parent instance
 pub type InstanceDag = Graph<InstDagVertex, f64, Directed>;
 
 pub trait AddEdge {
@@ -27,20 +37,67 @@ impl AddEdge for InstanceDag {
 }
 
 pub trait SaveToFormat {
-    fn save_to_dot(&self, filename: &str);
-    fn save_to_yaml(&self, filename: &str);
-    fn save_to_yaml_rev(&self, filename: &str);
+    fn save_to_dot(&self, filename: &str) -> Result<()>;
+    fn save_to_yaml(&self, filename: &str) -> Result<()>;
+    fn save_to_yaml_rev(&self, filename: &str) -> Result<()>;
+    fn save_to_wfformat(&self, filename: &str) -> Result<()>;
+}
+
+// days since epoch to (year, month, day), see http://howardhinnant.github.io/date_algorithms.html
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    return (year, month, day);
+}
+
+// in the order of first occurrence
+fn unique_nodes(nodes: impl Iterator<Item = NodeIndex>) -> Vec<NodeIndex> {
+    let mut result: Vec<NodeIndex> = Vec::new();
+    for node in nodes {
+        if !result.contains(&node) {
+            result.push(node);
+        }
+    }
+    return result;
+}
+
+fn utc_now() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs() as i64)
+        .unwrap_or(0);
+    let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+    let secs = secs.rem_euclid(86400);
+    return format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    );
 }
 
 impl SaveToFormat for InstanceDag {
-    fn save_to_dot(&self, filename: &str) {
-        let mut f = File::create(filename).unwrap();
+    fn save_to_dot(&self, filename: &str) -> Result<()> {
         let mut output = format!("digraph {{\n",);
 
         for node_ind in self.node_indices() {
             let node_info = self.node_weight(node_ind).unwrap();
             output.push_str(
-                format!("{} [size=\"{}\"];\n", node_info.inst_name, node_info.flops).as_str(),
+                format!(
+                    "{} [size=\"{}\", cpu=\"{}\", mem=\"{}\"];\n",
+                    node_info.inst_name, node_info.flops, node_info.cpu, node_info.mem
+                )
+                .as_str(),
             );
 
             for neighbour in self.neighbors(node_ind) {
@@ -57,19 +114,21 @@ impl SaveToFormat for InstanceDag {
             }
         }
         output.push_str("}\n");
-        f.write_all(&output.as_bytes()).unwrap();
+        return write_file(filename, &output);
     }
-    fn save_to_yaml(&self, filename: &str) {
-        let mut f = File::create(filename).unwrap();
+    fn save_to_yaml(&self, filename: &str) -> Result<()> {
         let mut output = format!("inputs:\n  - name: init\n    size: 0\ntasks:\n",);
 
         for node_ind in self.node_indices() {
             let node_info = self.node_weight(node_ind).unwrap();
             output.push_str(
                 format!(
-                    "  - name: {}\n    flops: {}\n    memory: 1\n",
+                    "  - name: {}\n    flops: {}\n    memory: {}\n    min_cores: {}\n    max_cores: {}\n",
                     node_info.inst_name,
-                    (node_info.flops.ceil() as u64).max(1)
+                    (node_info.flops.ceil() as u64).max(1),
+                    (node_info.mem.ceil() as u64).max(1),
+                    cores(node_info.cpu),
+                    cores(node_info.cpu)
                 )
                 .as_str(),
             );
@@ -106,20 +165,22 @@ impl SaveToFormat for InstanceDag {
                 output.push_str("    outputs:\n      - name: result\n        size: 1\n")
             }
         }
-        f.write_all(&output.as_bytes()).unwrap();
+        return write_file(filename, &output);
     }
 
-    fn save_to_yaml_rev(&self, filename: &str) {
-        let mut f = File::create(filename).unwrap();
+    fn save_to_yaml_rev(&self, filename: &str) -> Result<()> {
         let mut output = format!("inputs:\n  - name: init\n    size: 0\ntasks:\n",);
 
         for node_ind in self.node_indices().rev() {
             let node_info = self.node_weight(node_ind).unwrap();
             output.push_str(
                 format!(
-                    "  - name: {}\n    flops: {}\n    memory: 1\n",
+                    "  - name: {}\n    flops: {}\n    memory: {}\n    min_cores: {}\n    max_cores: {}\n",
                     node_info.inst_name,
-                    (node_info.flops.ceil() as u64).max(1)
+                    (node_info.flops.ceil() as u64).max(1),
+                    (node_info.mem.ceil() as u64).max(1),
+                    cores(node_info.cpu),
+                    cores(node_info.cpu)
                 )
                 .as_str(),
             );
@@ -156,12 +217,124 @@ impl SaveToFormat for InstanceDag {
                 output.push_str("    outputs:\n      - name: result\n        size: 1\n")
             }
         }
-        f.write_all(&output.as_bytes()).unwrap();
+        return write_file(filename, &output);
+    }
+
+    // Fields of WfCommons wfformat 1.5 that wfcommons::read_workflow reads. Flops are seconds
+    // on reference machine, mem is a share of its memory, every edge is a file named like in
+    // yaml with size of edge weight
+    fn save_to_wfformat(&self, filename: &str) -> Result<()> {
+        let order = toposort(self, None).map_err(|_| Error::Cycle)?;
+        let mut ends = vec![0.0; self.node_count()];
+        for node_ind in order.iter() {
+            let start = self
+                .node_weight(*node_ind)
+                .unwrap()
+                .dependencies
+                .iter()
+                .map(|x| ends[x.index()])
+                .fold(0.0, f64::max);
+            ends[node_ind.index()] = start + self.node_weight(*node_ind).unwrap().flops;
+        }
+        let makespan = ends.iter().copied().fold(0.0, f64::max);
+
+        let name_of = |ind: NodeIndex| self.node_weight(ind).unwrap().inst_name.clone();
+        let file_of =
+            |parent: NodeIndex, child: NodeIndex| format!("{}_{}", name_of(parent), name_of(child));
+
+        let mut tasks = Vec::new();
+        let mut files = Vec::new();
+        let mut executions = Vec::new();
+        for node_ind in self.node_indices() {
+            let node_info = self.node_weight(node_ind).unwrap();
+            // parallel edges are one file of their summed size
+            let children = unique_nodes(self.neighbors(node_ind));
+            let parents = unique_nodes(node_info.dependencies.iter().copied());
+            for child in children.iter() {
+                let size: f64 = self
+                    .edges_connecting(node_ind, *child)
+                    .map(|x| *x.weight())
+                    .sum();
+                files.push(json!({
+                    "id": file_of(node_ind, *child),
+                    "sizeInBytes": (size.ceil() as u64).max(1),
+                }));
+            }
+            tasks.push(json!({
+                "name": node_info.inst_name,
+                "id": node_info.inst_name,
+                "parents": parents.iter().map(|x| name_of(*x)).collect::<Vec<_>>(),
+                "children": children.iter().map(|x| name_of(*x)).collect::<Vec<_>>(),
+                "inputFiles": parents
+                    .iter()
+                    .map(|x| file_of(*x, node_ind))
+                    .collect::<Vec<_>>(),
+                "outputFiles": children
+                    .iter()
+                    .map(|x| file_of(node_ind, *x))
+                    .collect::<Vec<_>>(),
+            }));
+            executions.push(json!({
+                "id": node_info.inst_name,
+                "runtimeInSeconds": node_info.flops,
+                "coreCount": cores(node_info.cpu),
+                "avgCPU": node_info.cpu,
+                "memoryInBytes": (node_info.mem / 100.0 * REFERENCE_MACHINE_MEMORY as f64).ceil() as u64,
+                "machines": [REFERENCE_MACHINE],
+            }));
+        }
+
+        let created_at = utc_now();
+        let name = Path::new(filename)
+            .file_stem()
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let workflow = json!({
+            "name": name,
+            "description": "synthetic workflow generated from cluster trace statistics",
+            "createdAt": created_at,
+            "schemaVersion": "1.5",
+            "author": {"name": "cursach_content"},
+            "runtimeSystem": {
+                "name": "cursach_content",
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+            "workflow": {
+                "specification": {
+                    "tasks": tasks,
+                    "files": files,
+                },
+                "execution": {
+                    "makespanInSeconds": makespan,
+                    "executedAt": created_at,
+                    "tasks": executions,
+                    "machines": [{
+                        "nodeName": REFERENCE_MACHINE,
+                        "memoryInBytes": REFERENCE_MACHINE_MEMORY,
+                    }],
+                },
+            },
+        });
+        let j =
+            serde_json::to_string_pretty(&workflow).map_err(|why| Error::json(filename, why))?;
+        return write_file(filename, &j);
     }
 }
 
+// Times are for one processor per instance, communication always costs edge bytes sent
+// over REFERENCE_BANDWIDTH
+pub struct CriticalPath {
+    pub path: Vec<NodeIndex>,
+    pub length: f64,
+    // indexed by node index
+    pub earliest_start: Vec<f64>,
+    pub latest_start: Vec<f64>,
+    pub slack: Vec<f64>,
+}
+
 pub trait Characters {
-    fn get_all(&self) -> InstGrapgChar;
+    fn critical_path(&self) -> Result<CriticalPath>;
+    fn get_all(&self) -> Result<InstGrapgChar>;
 }
 
 fn dfs_level(v: NodeIndex, dag: &InstanceDag, levels: &mut Vec<usize>) {
@@ -172,20 +345,6 @@ fn dfs_level(v: NodeIndex, dag: &InstanceDag, levels: &mut Vec<usize>) {
         }
     }
 }
-fn dfs_cp(v: NodeIndex, dag: &InstanceDag, used: &mut Vec<f64>) -> f64 {
-    let mut result = 0.0;
-
-    for u in dag.neighbors(v) {
-        if used[v.index()] != 0.0 {
-            dfs_cp(u, dag, used);
-        }
-        let ne = used[v.index()];
-        if ne > result {
-            result = ne;
-        }
-    }
-    return result + dag.node_weight(v).unwrap().flops;
-}
 
 extern crate itertools;
 
@@ -201,18 +360,76 @@ pub struct InstGrapgChar {
 
 use itertools::Itertools;
 impl Characters for InstanceDag {
-    fn get_all(&self) -> InstGrapgChar {
+    fn critical_path(&self) -> Result<CriticalPath> {
+        let order = toposort(self, None).map_err(|_| Error::Cycle)?;
+        let node_cnt = self.node_count();
+        let flops = |ind: NodeIndex| self.node_weight(ind).unwrap().flops;
+        let comm = |weight: &f64| weight / REFERENCE_BANDWIDTH;
+
+        let mut earliest_start = vec![0.0; node_cnt];
+        let mut critical_parent: Vec<Option<NodeIndex>> = vec![None; node_cnt];
+        for node_ind in order.iter() {
+            for edge in self.edges_directed(*node_ind, Incoming) {
+                let ready = earliest_start[edge.source().index()]
+                    + flops(edge.source())
+                    + comm(edge.weight());
+                if ready > earliest_start[node_ind.index()] {
+                    earliest_start[node_ind.index()] = ready;
+                    critical_parent[node_ind.index()] = Some(edge.source());
+                }
+            }
+        }
+
+        let mut last: Option<NodeIndex> = None;
+        let mut length = 0.0;
+        for node_ind in order.iter() {
+            let finish = earliest_start[node_ind.index()] + flops(*node_ind);
+            if last.is_none() || finish > length {
+                length = finish;
+                last = Some(*node_ind);
+            }
+        }
+
+        let mut latest_start = vec![0.0; node_cnt];
+        for node_ind in order.iter().rev() {
+            let latest_finish = self
+                .edges_directed(*node_ind, Outgoing)
+                .map(|edge| latest_start[edge.target().index()] - comm(edge.weight()))
+                .fold(length, f64::min);
+            latest_start[node_ind.index()] = latest_finish - flops(*node_ind);
+        }
+        let slack = latest_start
+            .iter()
+            .zip(earliest_start.iter())
+            .map(|(latest, earliest)| latest - earliest)
+            .collect();
+
+        let mut path = Vec::new();
+        while let Some(node_ind) = last {
+            path.push(node_ind);
+            last = critical_parent[node_ind.index()];
+        }
+        path.reverse();
+
+        return Ok(CriticalPath {
+            path: path,
+            length: length,
+            earliest_start: earliest_start,
+            latest_start: latest_start,
+            slack: slack,
+        });
+    }
+
+    fn get_all(&self) -> Result<InstGrapgChar> {
         let tasks_cnt = self.node_count();
+        if tasks_cnt == 0 {
+            return Err(Error::Config(String::from("instance dag is empty")));
+        }
+        let critical_path = self.critical_path()?;
         let mut levels = vec![0; tasks_cnt];
-        let mut used = vec![0.0; tasks_cnt];
-        let mut cp_t = 0.0;
         for v in self.node_indices() {
             if self.node_weight(v).unwrap().dependencies.len() == 0 {
                 dfs_level(v, self, &mut levels);
-                let cp = dfs_cp(v, self, &mut used);
-                if cp > cp_t {
-                    cp_t = cp;
-                }
             }
         }
 
@@ -226,24 +443,180 @@ impl Characters for InstanceDag {
             .max()
             .unwrap();
 
-        let parallelism = self.node_weights().map(|x| x.flops).sum::<f64>() / cp_t;
-        let max_work = self
-            .node_weights()
-            .map(|x| x.flops)
-            .max_by(|a, b| a.partial_cmp(b).unwrap())
-            .unwrap();
-
-        let max_data = self
-            .edge_weights()
-            .max_by(|a, b| a.partial_cmp(b).unwrap())
-            .unwrap();
-        return InstGrapgChar {
+        let parallelism = if critical_path.length > 0.0 {
+            self.node_weights().map(|x| x.flops).sum::<f64>() / critical_path.length
+        } else {
+            0.0
+        };
+        let max_work = self.node_weights().map(|x| x.flops).fold(0.0, f64::max);
+        let max_data = self.edge_weights().copied().fold(0.0, f64::max);
+        return Ok(InstGrapgChar {
             tasks_cnt: tasks_cnt,
             depth: depth,
             width: width,
             paralel: parallelism,
             max_work: max_work,
-            max_data: *max_data,
+            max_data: max_data,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::read_file;
+    use crate::wfcommons::read_workflow;
+    use serde_json::Value;
+
+    // a -> b twice, a -> c, b -> c, c -> d
+    fn small_dag() -> InstanceDag {
+        let mut dag = InstanceDag::new();
+        for name in ["a", "b", "c", "d"] {
+            dag.add_node(InstDagVertex {
+                inst_name: name.to_string(),
+                dependencies: Vec::new(),
+                flops: 10.0,
+                cpu: 150.0,
+                mem: 2.0,
+            });
+        }
+        for (parent, child, weight) in [
+            (0, 1, 3.0),
+            (0, 1, 4.5),
+            (0, 2, 1.0),
+            (1, 2, 2.0),
+            (2, 3, 8.0),
+        ] {
+            dag.add_ins_edge(parent, child, weight);
+        }
+        return dag;
+    }
+
+    fn dag_of(flops: &[f64], edges: &[(usize, usize, f64)]) -> InstanceDag {
+        let mut dag = InstanceDag::new();
+        for (ind, flops) in flops.iter().enumerate() {
+            dag.add_node(InstDagVertex {
+                inst_name: format!("t{}", ind),
+                dependencies: Vec::new(),
+                flops: *flops,
+                cpu: 100.0,
+                mem: 1.0,
+            });
+        }
+        for (parent, child, weight) in edges.iter() {
+            dag.add_ins_edge(*parent, *child, *weight);
+        }
+        return dag;
+    }
+
+    fn export(dag: &InstanceDag, name: &str) -> Value {
+        let filename = format!("{}/{}.json", std::env::temp_dir().display(), name);
+        dag.save_to_wfformat(&filename).unwrap();
+        let result = serde_json::from_str(&read_file(&filename).unwrap()).unwrap();
+        std::fs::remove_file(&filename).unwrap();
+        return result;
+    }
+
+    #[test]
+    fn wfformat_reads_back() {
+        let filename = format!(
+            "{}/cursach_wfformat_back.json",
+            std::env::temp_dir().display()
+        );
+        small_dag().save_to_wfformat(&filename).unwrap();
+        let workflow = read_workflow(&filename).unwrap();
+        std::fs::remove_file(&filename).unwrap();
+
+        assert_eq!(workflow.node_count(), 4);
+        for node_info in workflow.node_weights() {
+            assert_eq!(node_info.instances.len(), 1);
+            assert_eq!(node_info.end_time - node_info.start_time, 10);
+            assert_eq!(node_info.instances[0].cpu_avg, 150.0);
+            assert!((node_info.instances[0].mem_avg - 2.0).abs() < 1e-6);
+        }
+        // tasks keep their order
+        let mut edges: Vec<(usize, usize, u64)> = workflow
+            .edge_references()
+            .map(|x| (x.source().index(), x.target().index(), *x.weight()))
+            .collect();
+        edges.sort();
+        assert_eq!(edges, vec![(0, 1, 8), (0, 2, 1), (1, 2, 2), (2, 3, 8)]);
+    }
+
+    #[test]
+    fn wfformat_parallel_edges_are_one_file() {
+        let workflow = export(&small_dag(), "cursach_wfformat_parallel");
+        let specification = &workflow["workflow"]["specification"];
+        let files = specification["files"].as_array().unwrap();
+        let mut ids: Vec<&str> = files.iter().map(|x| x["id"].as_str().unwrap()).collect();
+        ids.sort();
+        assert_eq!(ids, vec!["a_b", "a_c", "b_c", "c_d"]);
+        let a_b = files.iter().find(|x| x["id"] == "a_b").unwrap();
+        assert_eq!(a_b["sizeInBytes"], 8);
+
+        let tasks = specification["tasks"].as_array().unwrap();
+        let b = tasks.iter().find(|x| x["id"] == "b").unwrap();
+        assert_eq!(b["parents"], serde_json::json!(["a"]));
+        assert_eq!(b["inputFiles"], serde_json::json!(["a_b"]));
+        for task in tasks.iter() {
+            for file in task["inputFiles"].as_array().unwrap() {
+                assert!(ids.contains(&file.as_str().unwrap()));
+            }
+        }
+    }
+
+    #[test]
+    fn sent_bytes_take_bandwidth_time() {
+        // a -> b -> d is 4 seconds of work, a -> c -> d is 3.5 and a second to send to c
+        let dag = dag_of(
+            &[1.0, 2.0, 1.5, 1.0],
+            &[
+                (0, 1, 0.0),
+                (0, 2, REFERENCE_BANDWIDTH),
+                (1, 3, 0.0),
+                (2, 3, 0.0),
+            ],
+        );
+        let critical_path = dag.critical_path().unwrap();
+        assert_eq!(critical_path.path, [0, 2, 3].map(NodeIndex::new));
+        assert!((critical_path.length - 4.5).abs() < 1e-9);
+        assert!((critical_path.earliest_start[2] - 2.0).abs() < 1e-9);
+        assert!((critical_path.slack[1] - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn diamond_critical_path_by_hand() {
+        // s -> a, b -> t -> e, edges in seconds at reference bandwidth
+        let second = REFERENCE_BANDWIDTH;
+        let dag = dag_of(
+            &[2.0, 3.0, 1.0, 2.0, 1.0],
+            &[
+                (0, 1, second),
+                (0, 2, 2.0 * second),
+                (1, 3, second),
+                (2, 3, 0.5 * second),
+                (3, 4, 0.0),
+            ],
+        );
+        let critical_path = dag.critical_path().unwrap();
+        assert_eq!(critical_path.path, [0, 1, 3, 4].map(NodeIndex::new));
+        assert!((critical_path.length - 10.0).abs() < 1e-9);
+        let close = |left: &[f64], right: &[f64]| {
+            left.iter()
+                .zip(right.iter())
+                .all(|(x, y)| (x - y).abs() < 1e-9)
         };
+        assert!(close(
+            &critical_path.earliest_start,
+            &[0.0, 3.0, 4.0, 7.0, 9.0]
+        ));
+        assert!(close(
+            &critical_path.latest_start,
+            &[0.0, 3.0, 5.5, 7.0, 9.0]
+        ));
+        assert!(close(&critical_path.slack, &[0.0, 0.0, 1.5, 0.0, 0.0]));
+        for node_ind in critical_path.path.iter() {
+            assert!(critical_path.slack[node_ind.index()].abs() < 1e-9);
+        }
     }
 }
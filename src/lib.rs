@@ -0,0 +1,29 @@
+pub mod absorb;
+pub mod classify;
+pub mod compare;
+pub mod dependency;
+pub mod dispersion;
+pub mod distribution;
+pub mod edge_data;
+pub mod error;
+pub mod exchange;
+pub mod generator;
+pub mod get_dag_stat;
+pub mod get_gen_dag_stat;
+pub mod google;
+pub mod instance;
+pub mod motif;
+pub mod pipeline;
+pub mod pure_dag;
+pub mod schedule;
+pub mod series_parallel;
+pub mod simulation;
+pub mod source;
+pub mod statistic;
+pub mod structure;
+pub mod task_dag;
+pub mod trace_schema;
+pub mod wfcommons;
+
+pub use error::{Error, Result};
+pub use pipeline::Pipeline;
@@ -0,0 +1,355 @@
+use crate::dependency::asign_patterns;
+use crate::dispersion::Dispersion;
+use crate::edge_data::asign_edge_sizes;
+use crate::error::{Error, Result};
+use crate::generator::{asign_weights, gen_size};
+use crate::pure_dag::PureDag;
+use crate::statistic::*;
+use crate::task_dag::*;
+use petgraph::stable_graph::NodeIndex;
+use petgraph::Direction::{Incoming, Outgoing};
+use queues::*;
+use rand::prelude::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+// upper bounds of critical path buckets, longer paths go to the last bucket
+const MOTIF_CP_BUCKETS: [u32; 5] = [4, 7, 10, 14, 18];
+
+fn cp_bucket(cp: u32) -> u32 {
+    return MOTIF_CP_BUCKETS
+        .iter()
+        .position(|x| cp <= *x)
+        .unwrap_or(MOTIF_CP_BUCKETS.len()) as u32;
+}
+
+// How a stage gets its input: maps are parents reading input themselves, inner parents
+// are computed from other stages. Shared parents feed some other task too
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Motif {
+    pub maps: u32,
+    pub inner: u32,
+    pub shared: u32,
+}
+
+impl Motif {
+    pub fn name(&self) -> String {
+        let kind = match (self.maps, self.inner) {
+            (2, 0) => "join_of_two_maps",
+            _ if self.maps + self.inner == 1 => "pipe",
+            _ if self.maps + self.inner == 2 => "join",
+            _ => "shuffle_fan_in",
+        };
+        return format!("{}_{}m{}i{}s", kind, self.maps, self.inner, self.shared);
+    }
+}
+
+pub struct MotifStatistic {
+    // cp bucket -> motif -> tasks with it
+    stat: HashMap<u32, HashMap<Motif, u64>>,
+    // the most frequent motifs first
+    stat_result: HashMap<u32, Vec<(Motif, u64)>>,
+}
+
+impl SaveToFile for MotifStatistic {}
+
+impl StatBase for MotifStatistic {
+    fn form_stats(&mut self) {
+        self.stat_result.clear();
+        for (bucket, motifs) in self.stat.iter() {
+            let mut result: Vec<(Motif, u64)> = motifs.iter().map(|(x, y)| (*x, *y)).collect();
+            result.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+            self.stat_result.insert(*bucket, result);
+        }
+    }
+    fn get_string_obj(&self) -> String {
+        return serde_json::to_string(&self.stat_result).unwrap();
+    }
+    fn load_obj_from_string(&mut self, str: String) -> serde_json::Result<()> {
+        self.stat_result = serde_json::from_str(&str)?;
+        return Ok(());
+    }
+}
+
+impl MotifStatistic {
+    pub fn new() -> Self {
+        return MotifStatistic {
+            stat: HashMap::new(),
+            stat_result: HashMap::new(),
+        };
+    }
+
+    pub fn add(&mut self, critical_path: u32, graph: &PureDag) {
+        let bucket = cp_bucket(critical_path);
+        if !self.stat.contains_key(&bucket) {
+            self.stat.insert(bucket, HashMap::new());
+        }
+        let motifs = self.stat.get_mut(&bucket).unwrap();
+        for node_ind in graph.node_indices() {
+            let mut parents: Vec<NodeIndex> =
+                graph.neighbors_directed(node_ind, Incoming).collect();
+            parents.sort();
+            parents.dedup();
+            if parents.is_empty() {
+                continue;
+            }
+            let maps = parents
+                .iter()
+                .filter(|x| graph.neighbors_directed(**x, Incoming).next().is_none())
+                .count() as u32;
+            let shared = parents
+                .iter()
+                .filter(|x| graph.neighbors_directed(**x, Outgoing).count() > 1)
+                .count() as u32;
+            let motif = Motif {
+                maps: maps,
+                inner: parents.len() as u32 - maps,
+                shared: shared,
+            };
+            *motifs.entry(motif).or_insert(0) += 1;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.stat_result.is_empty();
+    }
+
+    // Task on the first level has only maps as parents, tasks above need an inner parent.
+    // Motifs come from the nearest cp bucket which has suitable ones
+    pub fn sample<R: Rng>(&self, cp: u32, level: u32, rnd: &mut R) -> Option<Motif> {
+        let fits = |x: &Motif| {
+            if level == 1 {
+                x.inner == 0
+            } else {
+                x.inner > 0
+            }
+        };
+        let bucket = cp_bucket(cp) as i64;
+        let mut buckets: Vec<u32> = self.stat_result.keys().copied().collect();
+        buckets.sort_by_key(|x| ((*x as i64 - bucket).abs(), *x));
+        for bucket in buckets {
+            let motifs: Vec<&(Motif, u64)> = self.stat_result[&bucket]
+                .iter()
+                .filter(|x| fits(&x.0))
+                .collect();
+            if let Ok(motif) = motifs.choose_weighted(rnd, |x| x.1) {
+                return Some(motif.0);
+            }
+        }
+        return None;
+    }
+}
+
+// Grows dag from the last task of critical path towards sources, every task gets
+// parents by a sampled motif. New parents are added while there are tasks left, shared
+// parents and parents over the limit are taken from tasks already on the needed level
+pub fn gen_motif_task_graph<R: Rng>(
+    stats: &FittedStats,
+    min_cp: u32,
+    max_cp: u32,
+    rnd: &mut R,
+) -> Result<TaskDag> {
+    if stats.motifs.is_empty() {
+        return Err(Error::NoStatistic {
+            name: String::from("motifs"),
+            cp: min_cp,
+            part: 0,
+        });
+    }
+    let (cp, node_cnt) = gen_size(stats, min_cp, max_cp, rnd)?;
+    let cp_len = cp as usize;
+
+    // critical path is a chain, its task i is on level i
+    let mut levels: Vec<u32> = (0..cp).collect();
+    let mut parents: Vec<Vec<usize>> = (0..cp_len)
+        .map(|x| if x == 0 { Vec::new() } else { vec![x - 1] })
+        .collect();
+    let mut by_level: Vec<Vec<usize>> = (0..cp_len).map(|x| vec![x]).collect();
+
+    let mut queue_to_assign: Queue<usize> = queue![];
+    for node in (1..cp_len).rev() {
+        queue_to_assign.add(node).unwrap();
+    }
+    while let Ok(node) = queue_to_assign.remove() {
+        let level = levels[node];
+        if level == 0 {
+            continue;
+        }
+        let motif = stats
+            .motifs
+            .sample(cp, level, rnd)
+            .ok_or(Error::NoStatistic {
+                name: String::from("motifs"),
+                cp: cp,
+                part: 0,
+            })?;
+        let (mut maps, mut inner, mut shared) = (motif.maps, motif.inner, motif.shared);
+        // parent on critical path is already there
+        if node < cp_len {
+            if level == 1 {
+                maps -= 1;
+            } else {
+                inner -= 1;
+            }
+        }
+        for (parent_cnt, parent_level) in [(inner, level - 1), (maps, 0)] {
+            for _ in 0..parent_cnt {
+                let reused: Vec<usize> = by_level[parent_level as usize]
+                    .iter()
+                    .filter(|x| !parents[node].contains(x))
+                    .copied()
+                    .collect();
+                let parent =
+                    if levels.len() < node_cnt as usize && (shared == 0 || reused.is_empty()) {
+                        let parent = levels.len();
+                        levels.push(parent_level);
+                        parents.push(Vec::new());
+                        by_level[parent_level as usize].push(parent);
+                        if parent_level > 0 {
+                            queue_to_assign.add(parent).unwrap();
+                        }
+                        parent
+                    } else {
+                        match reused.choose(rnd) {
+                            Some(parent) => {
+                                shared = shared.saturating_sub(1);
+                                *parent
+                            }
+                            None => continue,
+                        }
+                    };
+                parents[node].push(parent);
+            }
+        }
+    }
+
+    // parents go before children, as in dags from real data
+    let mut order: Vec<usize> = (0..levels.len()).collect();
+    order.sort_by_key(|x| (levels[*x], *x));
+    let mut new_index = vec![0; levels.len()];
+    for (ind, node) in order.iter().enumerate() {
+        new_index[*node] = ind;
+    }
+    let mut result_dag = TaskDag::new();
+    for i in 0..levels.len() {
+        result_dag.add_node(DagVertex {
+            task_name: format!("task_{}", i),
+            dependencies: Vec::new(),
+            instance_cnt: 0,
+            flops: 0.0,
+            cpu: DEFAULT_CPU,
+            mem: DEFAULT_MEM,
+            dispersion: Dispersion::default(),
+            patterns: BTreeMap::new(),
+        });
+    }
+    for node in order.iter() {
+        for parent in parents[*node].iter() {
+            result_dag.add_task_endge(
+                NodeIndex::new(new_index[*node]),
+                NodeIndex::new(new_index[*parent]),
+            );
+        }
+    }
+    let node_level: Vec<u32> = order.iter().map(|x| levels[*x]).collect();
+
+    let mut part = calc_part(levels.len() as u32, cp);
+    stats.level_distr_gen.adjust_part(cp, &mut part)?;
+    asign_weights(
+        cp,
+        part,
+        &node_level,
+        &stats.level_gen,
+        &mut result_dag,
+        rnd,
+    )?;
+    asign_patterns(&stats.patterns, &mut result_dag, rnd);
+    asign_edge_sizes(&stats.edge_data, &mut result_dag, rnd);
+    return Ok(result_dag);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::seeded_rng;
+    use crate::pure_dag::{Instance, PureTaskInfo};
+    use crate::source::dag_from_parents;
+    use crate::structure::StructureMetrics;
+
+    // task i runs on its level, parents are (task, bytes)
+    fn dag_of(levels: &[u64], parents: &[Vec<(usize, u64)>]) -> PureDag {
+        let tasks = levels
+            .iter()
+            .map(|level| PureTaskInfo {
+                name: String::new(),
+                instance_cnt: 2,
+                start_time: level * 100,
+                end_time: level * 100 + 50,
+                dependences: Vec::new(),
+                instances: (0..2)
+                    .map(|_| Instance {
+                        time: 50,
+                        cpu_avg: 100.0,
+                        cpu_diff_max: 0.0,
+                        mem_avg: 1.0,
+                        mem_diff_max: 0.0,
+                    })
+                    .collect(),
+            })
+            .collect();
+        return dag_from_parents(tasks, parents);
+    }
+
+    #[test]
+    fn motifs_of_hand_dag() {
+        // a, b -> c, a and c -> d: c joins two maps, a is shared, d has a map and an inner
+        let dag = dag_of(
+            &[0, 0, 1, 2],
+            &[vec![], vec![], vec![(0, 1), (1, 1)], vec![(0, 1), (2, 1)]],
+        );
+        let mut motifs = MotifStatistic::new();
+        motifs.add(3, &dag);
+        motifs.form_stats();
+        let join_of_maps = Motif {
+            maps: 2,
+            inner: 0,
+            shared: 1,
+        };
+        let join = Motif {
+            maps: 1,
+            inner: 1,
+            shared: 1,
+        };
+        assert_eq!(join_of_maps.name(), "join_of_two_maps_2m0i1s");
+        assert_eq!(join.name(), "join_1m1i1s");
+
+        let mut rnd = seeded_rng(1);
+        assert_eq!(motifs.sample(3, 1, &mut rnd), Some(join_of_maps));
+        assert_eq!(motifs.sample(3, 2, &mut rnd), Some(join));
+        // the only bucket is the nearest one for any critical path
+        assert_eq!(motifs.sample(20, 5, &mut rnd), Some(join));
+        assert_eq!(MotifStatistic::new().sample(3, 1, &mut rnd), None);
+    }
+
+    #[test]
+    fn chains_give_chains() {
+        let mut stats = FittedStats::new();
+        for _ in 0..10 {
+            let mut chain = dag_of(
+                &[0, 1, 2, 3],
+                &[vec![], vec![(0, 1)], vec![(1, 1)], vec![(2, 1)]],
+            );
+            stats.add_dag(&mut chain).unwrap();
+        }
+        stats.form_stats();
+        let mut rnd = seeded_rng(2);
+        for _ in 0..5 {
+            let dag = gen_motif_task_graph(&stats, 4, 4, &mut rnd).unwrap();
+            assert_eq!(dag.node_count(), 4);
+            assert_eq!(dag.edge_count(), 3);
+            assert_eq!(dag.levels().unwrap(), vec![0, 1, 2, 3]);
+            assert!(dag.node_weights().all(|x| x.dependencies.len() <= 1));
+        }
+    }
+}
@@ -0,0 +1,471 @@
+use crate::classify::*;
+use crate::error::{read_file, write_file, Error, Result};
+use crate::exchange::ExchangeFormat;
+use crate::generator::{check_cp_range, gen_task_graph, seeded_rng, Generator};
+use crate::instance::*;
+use crate::motif::gen_motif_task_graph;
+use crate::pure_dag::*;
+use crate::source::TraceSource;
+use crate::statistic::*;
+use crate::structure::{CcrDefinition, StructureMetrics};
+use crate::task_dag::*;
+use rand::prelude::SliceRandom;
+use rand::Rng;
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+pub const SAMPLE_CP_RANGES: [(u32, u32); 5] = [(2, 4), (5, 7), (8, 10), (11, 14), (19, 24)];
+
+// "name,kind" for every task dag exported to a directory, kind is real or generated
+pub const MANIFEST_FILENAME: &str = "manifest.csv";
+
+// seed is None for dags taken from real data
+pub struct NamedDag<T> {
+    pub name: String,
+    pub seed: Option<u64>,
+    pub dag: T,
+}
+
+// ingest trace -> classify -> fit statistics -> generate TaskDag -> expand to InstanceDag -> export
+pub struct Pipeline {
+    pub graph_type: GraphType,
+    // calibration target for edges of instance dags, learned data sizes are kept without it
+    pub ccr: Option<CcrTarget>,
+    pub generator: Generator,
+}
+
+impl Pipeline {
+    pub fn new(graph_type: GraphType, ccr: Option<CcrTarget>, generator: Generator) -> Self {
+        return Pipeline {
+            graph_type: graph_type,
+            ccr: ccr,
+            generator: generator,
+        };
+    }
+
+    pub fn ingest(&self, source: &dyn TraceSource) -> Result<PureDags> {
+        return source.ingest();
+    }
+
+    // keeps only jobs of pipeline's graph type
+    pub fn classify(&self, jobs: PureDags) -> PureDags {
+        return classify(jobs)
+            .remove(&self.graph_type)
+            .unwrap_or_else(PureDags::new);
+    }
+
+    pub fn fit(&self, dags: &mut PureDags) -> FittedStats {
+        let mut stats = FittedStats::new();
+        self.fit_into(&mut stats, dags);
+        stats.form_stats();
+        return stats;
+    }
+
+    // to accumulate statistics over several parts of dataset. Call form_stats after last part.
+    // Broken jobs are reported and removed from dags
+    pub fn fit_into(&self, stats: &mut FittedStats, dags: &mut PureDags) {
+        let mut broken_jobs = Vec::new();
+        for (job_name, graph) in dags.dags.iter_mut() {
+            if let Err(err) = stats.add_dag(graph) {
+                eprintln!("skip {}", err.in_job(job_name));
+                broken_jobs.push(job_name.clone());
+            }
+        }
+        for job_name in broken_jobs.iter() {
+            dags.dags.remove(job_name);
+        }
+    }
+
+    // dags should be already passed through fit (node ids sorted)
+    pub fn sample_real<R: Rng>(
+        &self,
+        dags: &PureDags,
+        sample_cnt: usize,
+        rnd: &mut R,
+    ) -> Vec<NamedDag<TaskDag>> {
+        let mut dags_to_sample = vec![Vec::<&String>::new(); SAMPLE_CP_RANGES.len()];
+        for (job_name, graph) in dags.dags.iter() {
+            let node_cnt = graph.node_count();
+            let mut depths = vec![0; node_cnt];
+            let mut levels = vec![0; node_cnt];
+            if let Err(err) = graph.calc_levels(&mut depths, &mut levels) {
+                eprintln!("skip {}", err.in_job(job_name));
+                continue;
+            }
+            let critical_path = depths.iter().max().copied().unwrap_or(0);
+
+            for (ind, cp_range) in SAMPLE_CP_RANGES.iter().enumerate() {
+                if cp_range.0 <= critical_path && critical_path <= cp_range.1 {
+                    dags_to_sample[ind].push(job_name);
+                }
+            }
+        }
+
+        let mut result = Vec::new();
+        for (cp_range, sample_ex) in SAMPLE_CP_RANGES.iter().zip(dags_to_sample.iter_mut()) {
+            sample_ex.sort();
+            sample_ex.shuffle(rnd);
+            for key in sample_ex[..sample_cnt.min(sample_ex.len())].iter() {
+                match <TaskDag as TaskDagFuncs>::from_pure_dag(&dags.dags[*key]) {
+                    Ok(dag) => result.push(NamedDag {
+                        name: format!("{}_{}_{}", cp_range.0, cp_range.1, key),
+                        seed: None,
+                        dag: dag,
+                    }),
+                    Err(err) => eprintln!("skip {}", err.in_job(key)),
+                }
+            }
+        }
+        return result;
+    }
+
+    // every dag gets its own seed derived from rnd, see regenerate. Samples that fail are
+    // skipped, a range no sample fits in is an error
+    pub fn generate<R: Rng>(
+        &self,
+        stats: &FittedStats,
+        min_cp: u32,
+        max_cp: u32,
+        sample_cnt: usize,
+        rnd: &mut R,
+    ) -> Result<Vec<NamedDag<TaskDag>>> {
+        if self.generator == Generator::Level {
+            check_cp_range(self.graph_type, min_cp, max_cp)?;
+        }
+        let mut result = Vec::new();
+        for job_gen in 0..sample_cnt {
+            let seed = rnd.gen::<u64>();
+            let name = format!("{}_{}_{}", min_cp, max_cp, job_gen);
+            match self.regenerate(stats, min_cp, max_cp, seed) {
+                Ok(dag) => result.push(NamedDag {
+                    name: name,
+                    seed: Some(seed),
+                    dag: dag,
+                }),
+                Err(err) => eprintln!("skip {}", err.in_job(&name)),
+            }
+        }
+        return Ok(result);
+    }
+
+    pub fn regenerate(
+        &self,
+        stats: &FittedStats,
+        min_cp: u32,
+        max_cp: u32,
+        seed: u64,
+    ) -> Result<TaskDag> {
+        let mut rnd = seeded_rng(seed);
+        return match self.generator {
+            Generator::Level => gen_task_graph(stats, self.graph_type, min_cp, max_cp, &mut rnd),
+            Generator::Motif => gen_motif_task_graph(stats, min_cp, max_cp, &mut rnd),
+        };
+    }
+
+    pub fn expand<R: Rng>(
+        &self,
+        task_dags: &[NamedDag<TaskDag>],
+        rnd: &mut R,
+    ) -> Vec<NamedDag<InstanceDag>> {
+        let mut result = Vec::new();
+        for task_dag in task_dags.iter() {
+            let seed = rnd.gen::<u64>();
+            let ccr_name = match self.ccr {
+                Some(ccr) => (ccr.value * 10.0).to_string(),
+                None => String::from("data"),
+            };
+            let name = format!("{}_{}", task_dag.name, ccr_name);
+            let dag = self.reexpand(&task_dag.dag, seed);
+            if let Some(ccr) = self.ccr {
+                let achieved = dag.ccr(ccr.definition);
+                if !ccr.is_reached(achieved) {
+                    eprintln!(
+                        "{}: ccr {} is out of reach, got {}",
+                        name, ccr.value, achieved
+                    );
+                }
+            }
+            result.push(NamedDag {
+                name: name,
+                seed: Some(seed),
+                dag: dag,
+            });
+        }
+        return result;
+    }
+
+    pub fn reexpand(&self, task_dag: &TaskDag, seed: u64) -> InstanceDag {
+        return task_dag.convert_to_inst_dag(&mut seeded_rng(seed), self.ccr);
+    }
+
+    pub fn export_tasks(&self, dir: &str, task_dags: &[NamedDag<TaskDag>]) -> Result<()> {
+        for task_dag in task_dags.iter() {
+            let name = &task_dag.name;
+            task_dag
+                .dag
+                .save_to_file(&format!("{}/{}.json", dir, name).to_string())?;
+            task_dag
+                .dag
+                .save_to_dot(&format!("{}/{}.dot", dir, name).to_string())?;
+            task_dag
+                .dag
+                .save_to_stg(&format!("{}/{}.stg", dir, name).to_string())?;
+            task_dag
+                .dag
+                .save_to_dax(&format!("{}/{}.dax", dir, name).to_string())?;
+            save_seed(dir, name, task_dag.seed)?;
+        }
+        return update_manifest(dir, task_dags);
+    }
+
+    pub fn export_instances(
+        &self,
+        dir: &str,
+        instance_dags: &[NamedDag<InstanceDag>],
+    ) -> Result<()> {
+        for instance_dag in instance_dags.iter() {
+            let name = &instance_dag.name;
+            let dag = &instance_dag.dag;
+            dag.save_to_dot(&format!("{}/{}.dot", dir, name).to_string())?;
+            dag.save_to_yaml(&format!("{}/{}.yaml", dir, name).to_string())?;
+            dag.save_to_yaml_rev(&format!("{}/{}.rev.yaml", dir, name).to_string())?;
+            dag.save_to_wfformat(&format!("{}/{}.json", dir, name).to_string())?;
+            dag.save_to_stg(&format!("{}/{}.stg", dir, name).to_string())?;
+            dag.save_to_dax(&format!("{}/{}.dax", dir, name).to_string())?;
+            save_seed(dir, name, instance_dag.seed)?;
+            self.save_ccr(dir, name, dag)?;
+        }
+        return Ok(());
+    }
+
+    // realised ccr of instance dag next to the target it was calibrated to
+    fn save_ccr(&self, dir: &str, name: &str, dag: &InstanceDag) -> Result<()> {
+        let mut content = String::new();
+        let definition = match self.ccr {
+            Some(ccr) => {
+                content += &format!("target {}\n", ccr.value);
+                ccr.definition
+            }
+            None => CcrDefinition::Average,
+        };
+        content += &format!("definition {}\n", definition.name());
+        content += &format!("achieved {}\n", dag.ccr(definition));
+        return write_file(&format!("{}/{}.ccr", dir, name), &content);
+    }
+}
+
+fn save_seed(dir: &str, name: &str, seed: Option<u64>) -> Result<()> {
+    if let Some(seed) = seed {
+        write_file(&format!("{}/{}.seed", dir, name), &format!("{}\n", seed))?;
+    }
+    return Ok(());
+}
+
+fn read_manifest(filename: &str) -> Result<BTreeMap<String, String>> {
+    let mut result = BTreeMap::new();
+    for (line_ind, line) in read_file(filename)?.lines().enumerate() {
+        match line.rsplit_once(',') {
+            Some((name, kind)) if kind == "real" || kind == "generated" => {
+                result.insert(name.to_string(), kind.to_string());
+            }
+            _ => {
+                return Err(Error::Config(format!(
+                    "{}:{}: expected name,real or name,generated",
+                    filename,
+                    line_ind + 1
+                )))
+            }
+        }
+    }
+    return Ok(result);
+}
+
+// dags exported earlier to the same directory are kept
+fn update_manifest(dir: &str, task_dags: &[NamedDag<TaskDag>]) -> Result<()> {
+    let filename = format!("{}/{}", dir, MANIFEST_FILENAME);
+    let mut kinds = if Path::new(&filename).exists() {
+        read_manifest(&filename)?
+    } else {
+        BTreeMap::new()
+    };
+    for task_dag in task_dags.iter() {
+        let kind = match task_dag.seed {
+            Some(_) => "generated",
+            None => "real",
+        };
+        kinds.insert(task_dag.name.clone(), kind.to_string());
+    }
+    let mut content = String::new();
+    for (name, kind) in kinds.iter() {
+        content.push_str(&format!("{},{}\n", name, kind));
+    }
+    return write_file(&filename, &content);
+}
+
+// names of generated task dags in a directory export_tasks wrote to
+pub fn generated_names(dir: &str) -> Result<HashSet<String>> {
+    return Ok(read_manifest(&format!("{}/{}", dir, MANIFEST_FILENAME))?
+        .into_iter()
+        .filter(|(_, kind)| kind == "generated")
+        .map(|(name, _)| name)
+        .collect());
+}
+
+// name of dag in directory, path is of any exported file
+pub fn dag_name(path: &str) -> String {
+    return Path::new(path)
+        .file_stem()
+        .map(|x| x.to_string_lossy().to_string())
+        .unwrap_or_default();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{read_dir_sorted, recreate_dir};
+    use crate::source::dag_from_parents;
+    use std::fs;
+
+    // layered jobs with critical path 5..7, every task feeds from one or two tasks above
+    fn layered_dags() -> PureDags {
+        let mut rnd = seeded_rng(7);
+        let mut result = PureDags::new();
+        for job in 0..30 {
+            let mut tasks = Vec::new();
+            let mut parents: Vec<Vec<(usize, u64)>> = Vec::new();
+            let mut prev_level: Vec<usize> = Vec::new();
+            for level in 0..(5 + job % 3) {
+                let mut cur_level = Vec::new();
+                for _ in 0..rnd.gen_range(1..4) {
+                    let mut task_parents = Vec::new();
+                    if !prev_level.is_empty() {
+                        task_parents.push((*prev_level.choose(&mut rnd).unwrap(), 1));
+                        let other = *prev_level.choose(&mut rnd).unwrap();
+                        if rnd.gen_bool(0.5) && task_parents[0].0 != other {
+                            task_parents.push((other, 1));
+                        }
+                    }
+                    let instance_cnt = rnd.gen_range(1..6);
+                    let start_time = level as u64 * 100;
+                    tasks.push(PureTaskInfo {
+                        name: String::new(),
+                        instance_cnt: instance_cnt,
+                        start_time: start_time,
+                        end_time: start_time + rnd.gen_range(10..90),
+                        dependences: Vec::new(),
+                        instances: (0..instance_cnt)
+                            .map(|_| Instance {
+                                time: rnd.gen_range(10..90),
+                                cpu_avg: rnd.gen_range(50.0..200.0),
+                                cpu_diff_max: 0.0,
+                                mem_avg: rnd.gen_range(0.1..2.0),
+                                mem_diff_max: 0.0,
+                            })
+                            .collect(),
+                    });
+                    parents.push(task_parents);
+                    cur_level.push(tasks.len() - 1);
+                }
+                prev_level = cur_level;
+            }
+            result
+                .dags
+                .insert(format!("j_{}", job), dag_from_parents(tasks, &parents));
+        }
+        return result;
+    }
+
+    // stats are fitted anew, maps in them must not change the output either. Export time
+    // of wfformat files is left out
+    fn export_run(pipeline: &Pipeline, dir: &str) -> Vec<(String, String)> {
+        recreate_dir(dir).unwrap();
+        let stats = pipeline.fit(&mut layered_dags());
+        let mut rnd = seeded_rng(42);
+        let task_dags = pipeline.generate(&stats, 5, 7, 5, &mut rnd).unwrap();
+        pipeline.export_tasks(dir, &task_dags).unwrap();
+        let instance_dags = pipeline.expand(&task_dags, &mut rnd);
+        pipeline.export_instances(dir, &instance_dags).unwrap();
+        let result = read_dir_sorted(dir)
+            .unwrap()
+            .iter()
+            .map(|x| {
+                let name = Path::new(x).file_name().unwrap().to_string_lossy();
+                let content = read_file(x)
+                    .unwrap()
+                    .lines()
+                    .filter(|x| !x.contains("\"createdAt\"") && !x.contains("\"executedAt\""))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                (name.to_string(), content)
+            })
+            .collect();
+        fs::remove_dir_all(dir).unwrap();
+        return result;
+    }
+
+    #[test]
+    fn same_seed_gives_identical_files() {
+        let ccr = Some(CcrTarget {
+            value: 0.5,
+            definition: CcrDefinition::Average,
+        });
+        for generator in [Generator::Level, Generator::Motif] {
+            let pipeline = Pipeline::new(GraphType::Other, ccr, generator);
+            let tmp = std::env::temp_dir().display().to_string();
+            let first = export_run(&pipeline, &format!("{}/cursach_seed_a", tmp));
+            let second = export_run(&pipeline, &format!("{}/cursach_seed_b", tmp));
+            assert!(first.len() > 5 * 8);
+            assert!(first == second, "{} output differs", generator.name());
+        }
+    }
+
+    #[test]
+    fn manifest_tells_generated_from_real() {
+        let pipeline = Pipeline::new(GraphType::Other, None, Generator::Level);
+        let dir = format!("{}/cursach_manifest", std::env::temp_dir().display());
+        recreate_dir(&dir).unwrap();
+        let mut dags = layered_dags();
+        let stats = pipeline.fit(&mut dags);
+        let mut rnd = seeded_rng(5);
+        let real = pipeline.sample_real(&dags, 2, &mut rnd);
+        pipeline.export_tasks(&dir, &real).unwrap();
+        let generated = pipeline.generate(&stats, 5, 7, 3, &mut rnd).unwrap();
+        pipeline.export_tasks(&dir, &generated).unwrap();
+        // a stale seed does not make a real sample generated
+        write_file(&format!("{}/{}.seed", dir, real[0].name), "1\n").unwrap();
+
+        let names = generated_names(&dir).unwrap();
+        assert_eq!(names.len(), 3);
+        assert!(generated.iter().all(|x| names.contains(&x.name)));
+        assert!(real.iter().all(|x| !names.contains(&x.name)));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn classify_without_jobs_of_type() {
+        let pipeline = Pipeline::new(GraphType::Diamond, None, Generator::Level);
+        assert!(pipeline.classify(layered_dags()).dags.is_empty());
+    }
+
+    #[test]
+    fn failed_samples_are_skipped() {
+        let pipeline = Pipeline::new(GraphType::Other, None, Generator::Level);
+        let stats = pipeline.fit(&mut layered_dags());
+        let mut rnd = seeded_rng(3);
+        // no fitted dag has critical path over 7
+        assert!(pipeline
+            .generate(&stats, 8, 10, 3, &mut rnd)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            pipeline.generate(&stats, 5, 7, 3, &mut rnd).unwrap().len(),
+            3
+        );
+
+        let diamond = Pipeline::new(GraphType::Diamond, None, Generator::Level);
+        assert!(matches!(
+            diamond.generate(&stats, 5, 7, 3, &mut rnd),
+            Err(Error::Config(_))
+        ));
+    }
+}
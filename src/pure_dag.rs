@@ -1,15 +1,22 @@
 use petgraph::visit::EdgeRef;
 use petgraph::{graph::Graph, stable_graph::NodeIndex, Directed};
 
+use crate::error::{read_file, write_file, Error, Result};
 use rand::seq::SliceRandom;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fs::File, io::Read, io::Write, path::Path}; // 0.7.2
+use std::collections::HashMap;
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Instance {
     pub time: u64,
     pub cpu_avg: f64,
     pub cpu_diff_max: f64,
+    // percents of machine memory, dags absorbed before it was kept have zeros
+    #[serde(default)]
+    pub mem_avg: f64,
+    #[serde(default)]
+    pub mem_diff_max: f64,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -24,32 +31,62 @@ pub struct PureTaskInfo {
 
 pub type PureDag = Graph<PureTaskInfo, u64, Directed>;
 
+// taskN -> N - 1, checked to be a valid node position
+pub fn task_position(task_name: &str, node_cnt: usize) -> Result<usize> {
+    let number = match task_name.strip_prefix("task") {
+        Some(number) => number.parse::<usize>().unwrap_or(0),
+        None => 0,
+    };
+    if number == 0 || number > node_cnt {
+        return Err(Error::TaskName {
+            task: task_name.to_string(),
+        });
+    }
+    return Ok(number - 1);
+}
+
+// positions of nodes ordered by taskN names
+pub fn true_node_order(names: Vec<&str>) -> Result<(Vec<NodeIndex>, HashMap<NodeIndex, usize>)> {
+    let node_cnt = names.len();
+    let mut true_order = vec![None; node_cnt];
+    let mut map_orders = HashMap::<NodeIndex, usize>::new();
+    for (ind, task_name) in names.into_iter().enumerate() {
+        let number = task_position(task_name, node_cnt)?;
+        if true_order[number].is_some() {
+            return Err(Error::Task {
+                task: task_name.to_string(),
+                reason: String::from("name is duplicated"),
+            });
+        }
+        true_order[number] = Some(NodeIndex::new(ind));
+        map_orders.insert(NodeIndex::new(ind), number);
+    }
+    return Ok((
+        true_order.into_iter().map(|x| x.unwrap()).collect(),
+        map_orders,
+    ));
+}
+
 pub trait SortNodeIndex {
-    fn sort_node_ids(&mut self);
+    fn sort_node_ids(&mut self) -> Result<()>;
 }
 
 impl SortNodeIndex for PureDag {
-    fn sort_node_ids(&mut self) {
+    fn sort_node_ids(&mut self) -> Result<()> {
         let mut result = PureDag::new();
 
-        let mut true_order = vec![NodeIndex::new(0); self.node_count()];
+        let (true_order, map_orders) =
+            true_node_order(self.node_weights().map(|x| x.name.as_str()).collect())?;
 
-        let mut map_orders = HashMap::<NodeIndex, usize>::new();
-        for node_ind in self.node_indices() {
-            let node_info = self.node_weight(node_ind).unwrap();
-            let task_name: &str = node_info.name.as_str();
-            let number = &task_name[4..task_name.len()];
-            let number = match number.parse::<usize>() {
-                Ok(number) => number,
-                Err(_) => 0,
-            };
-
-            true_order[number - 1] = node_ind;
-            map_orders.insert(node_ind, number - 1);
-        }
         for node_ind in true_order {
             let node_info = self.node_weight(node_ind).unwrap();
 
+            if node_info.dependences.iter().any(|x| *x == 0) {
+                return Err(Error::Task {
+                    task: node_info.name.clone(),
+                    reason: String::from("dependence on task0"),
+                });
+            }
             let last_node = result.add_node(node_info.clone());
             result.node_weight_mut(last_node).unwrap().dependences =
                 node_info.dependences.iter().map(|x| x - 1).collect();
@@ -63,6 +100,7 @@ impl SortNodeIndex for PureDag {
             );
         }
         *self = result;
+        return Ok(());
     }
 }
 
@@ -80,53 +118,37 @@ impl PureDags {
         self.dags.insert(key, dag);
     }
 
-    pub fn get_from_file(filename: &str) -> Self {
-        let path = Path::new(filename);
-        let mut file = match File::open(&path) {
-            Err(why) => panic!("cant open file to write {}", why),
-            Ok(file) => file,
-        };
-        let mut contents = String::new();
-        match file.read_to_string(&mut contents) {
-            Err(why) => panic!("couldn't read: {}", why),
-            Ok(_) => {}
+    // jobs that fail to parse are reported and skipped
+    pub fn get_from_file(filename: &str) -> Result<Self> {
+        let contents = read_file(filename)?;
+        let raw_dags: HashMap<String, serde_json::Value> =
+            serde_json::from_str(&contents).map_err(|why| Error::json(filename, why))?;
+        let mut result = PureDags::new();
+        for (job_name, raw_dag) in raw_dags.into_iter() {
+            match serde_json::from_value::<PureDag>(raw_dag) {
+                Ok(dag) => result.insert(job_name, dag),
+                Err(why) => eprintln!("skip {}", Error::json(filename, why).in_job(&job_name)),
+            }
         }
-        return PureDags {
-            dags: serde_json::from_str(&contents).unwrap(),
-        };
+        return Ok(result);
     }
-    pub fn save_to_file(&self, filename: &str) {
-        let j = serde_json::to_string(&self.dags).unwrap();
-
-        let pull_file_path = format!("../by_graph_type/{}.json", filename);
-        let path = Path::new(pull_file_path.as_str());
-        let mut file = match File::create(&path) {
-            Err(why) => panic!("cant open file to write {}", why),
-            Ok(file) => file,
-        };
-
-        match file.write_all(j.as_bytes()) {
-            Err(why) => panic!("cant save serialization {}", why),
-            Ok(_) => {}
-        };
+    pub fn save_to_file(&self, filename: &str) -> Result<()> {
+        let j = serde_json::to_string(&self.dags).map_err(|why| Error::json(filename, why))?;
+        return write_file(filename, &j);
     }
 
-    pub fn samples(&self, n: usize) -> HashMap<String, PureDag> {
+    pub fn samples<R: Rng>(&self, n: usize, rnd: &mut R) -> HashMap<String, PureDag> {
         let mut ranges: Vec<&String> = self.dags.keys().collect();
-        let mut rnd = rand::thread_rng();
-        ranges.shuffle(&mut rnd);
+        ranges.sort();
+        ranges.shuffle(rnd);
         let mut result: HashMap<String, PureDag> = HashMap::new();
-        for key in ranges[..n].iter() {
+        for key in ranges[..n.min(ranges.len())].iter() {
             result.insert((*key).clone(), self.dags[*key].clone());
         }
         return result;
     }
 }
 
-pub enum Error {
-    DFS(&'static str),
-}
-
 pub trait DoTraverse {
     fn dfs(
         &self,
@@ -144,7 +166,7 @@ pub trait DoTraverse {
     );
 
     fn dfs_to_calc_final_level(&self, u: NodeIndex, used: &mut Vec<u32>, levels: &mut Vec<u32>);
-    fn calc_levels(&self, depths: &mut Vec<u32>, levels: &mut Vec<u32>) -> Option<Error>;
+    fn calc_levels(&self, depths: &mut Vec<u32>, levels: &mut Vec<u32>) -> Result<()>;
 }
 
 impl DoTraverse for PureDag {
@@ -218,7 +240,7 @@ impl DoTraverse for PureDag {
         }
     }
 
-    fn calc_levels(&self, depths: &mut Vec<u32>, levels: &mut Vec<u32>) -> Option<Error> {
+    fn calc_levels(&self, depths: &mut Vec<u32>, levels: &mut Vec<u32>) -> Result<()> {
         let node_cnt = self.node_count();
         let mut used = vec![0; node_cnt];
         let mut dfs_used = vec![0; node_cnt];
@@ -232,16 +254,15 @@ impl DoTraverse for PureDag {
                 // calc depths
                 let mut tmp = true;
                 if self.dfs(ind, depths, &mut dfs_used, &mut tmp) != 0 {
-                    return Some(Error::DFS("Found cycle in graph"));
+                    return Err(Error::Cycle);
                 }
                 // calculate lower bound of levels
                 self.dfs_to_calc_first_level(ind, &mut used, levels)
             }
         }
-        assert!(
-            used.iter().sum::<u32>() == 0,
-            "levels weren't calculated for all nodes"
-        );
+        if used.iter().sum::<u32>() != 0 {
+            return Err(Error::Levels);
+        }
         let mut used = vec![0; node_cnt];
         for ind in self.node_indices() {
             let depend_len = self.node_weight(ind).unwrap().dependences.len();
@@ -250,7 +271,7 @@ impl DoTraverse for PureDag {
                 self.dfs_to_calc_final_level(ind, &mut used, levels)
             }
         }
-        return None;
+        return Ok(());
     }
 }
 
@@ -259,7 +280,7 @@ pub trait AbsorbStat {
         &self,
         cp: usize,
         levels: &Vec<u32>,
-    ) -> (Vec<Vec<u32>>, Vec<Vec<u32>>, Vec<Vec<u32>>);
+    ) -> (Vec<Vec<u32>>, Vec<Vec<f64>>, Vec<Vec<f64>>);
     fn get_links_per_type(&self, levels: &Vec<u32>) -> (Vec<u32>, Vec<u32>);
 }
 
@@ -268,22 +289,20 @@ impl AbsorbStat for PureDag {
         &self,
         cp: usize,
         levels: &Vec<u32>,
-    ) -> (Vec<Vec<u32>>, Vec<Vec<u32>>, Vec<Vec<u32>>) {
+    ) -> (Vec<Vec<u32>>, Vec<Vec<f64>>, Vec<Vec<f64>>) {
         let mut result_time = vec![Vec::new(); cp];
         let mut result_cpu = vec![Vec::new(); cp];
-        let mut result_cpu_diff_max = vec![Vec::new(); cp];
+        let mut result_mem = vec![Vec::new(); cp];
 
         for node_ind in self.node_indices() {
             let node_info = self.node_weight(node_ind).unwrap();
             for instance in node_info.instances.iter() {
                 result_time[levels[node_ind.index() as usize] as usize].push(instance.time as u32);
-                result_cpu[levels[node_ind.index() as usize] as usize]
-                    .push(instance.cpu_avg as u32);
-                result_cpu_diff_max[levels[node_ind.index() as usize] as usize]
-                    .push(instance.cpu_diff_max as u32);
+                result_cpu[levels[node_ind.index() as usize] as usize].push(instance.cpu_avg);
+                result_mem[levels[node_ind.index() as usize] as usize].push(instance.mem_avg);
             }
         }
-        return (result_time, result_cpu, result_cpu_diff_max);
+        return (result_time, result_cpu, result_mem);
     }
 
     fn get_links_per_type(&self, levels: &Vec<u32>) -> (Vec<u32>, Vec<u32>) {
@@ -0,0 +1,485 @@
+use crate::error::{read_file, Error, Result};
+use crate::instance::InstanceDag;
+use crate::wfcommons::REFERENCE_BANDWIDTH;
+use petgraph::algo::toposort;
+use petgraph::stable_graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+use petgraph::Direction::{Incoming, Outgoing};
+use serde::{Deserialize, Serialize};
+
+// Processors with different speeds connected by links of the same bandwidth in bytes per second.
+// Task takes flops / speed, edge takes latency + weight / bandwidth between different processors
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Platform {
+    pub speeds: Vec<f64>,
+    pub bandwidth: f64,
+    #[serde(default)]
+    pub latency: f64,
+}
+
+impl Platform {
+    pub fn new(speeds: Vec<f64>, bandwidth: f64) -> Self {
+        return Platform {
+            speeds: speeds,
+            bandwidth: bandwidth,
+            latency: 0.0,
+        };
+    }
+
+    // with speed 1 and reference bandwidth costs are the ones ccr is measured by
+    pub fn reference() -> Self {
+        return Platform::new(vec![1.0, 1.0, 2.0, 2.0], REFERENCE_BANDWIDTH);
+    }
+
+    pub fn load_from_file(filename: &str) -> Result<Self> {
+        let contents = read_file(filename)?;
+        let platform: Platform =
+            serde_json::from_str(&contents).map_err(|why| Error::json(filename, why))?;
+        if platform.speeds.is_empty()
+            || platform.speeds.iter().any(|x| *x <= 0.0)
+            || platform.bandwidth <= 0.0
+            || platform.latency < 0.0
+        {
+            return Err(Error::Config(format!(
+                "{}: platform needs processors with positive speeds and positive bandwidth",
+                filename
+            )));
+        }
+        return Ok(platform);
+    }
+
+    fn comp(&self, flops: f64, processor: usize) -> f64 {
+        return flops / self.speeds[processor];
+    }
+
+    fn comm(&self, data: f64, from: usize, to: usize) -> f64 {
+        if from == to {
+            return 0.0;
+        }
+        return self.latency + data / self.bandwidth;
+    }
+
+    fn avg_comp(&self, flops: f64) -> f64 {
+        return (0..self.speeds.len())
+            .map(|p| self.comp(flops, p))
+            .sum::<f64>()
+            / self.speeds.len() as f64;
+    }
+
+    fn avg_comm(&self, data: f64) -> f64 {
+        if self.speeds.len() == 1 {
+            return 0.0;
+        }
+        return self.latency + data / self.bandwidth;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    Heft,
+    Cpop,
+    Peft,
+}
+
+pub const ALL_ALGORITHMS: [Algorithm; 3] = [Algorithm::Heft, Algorithm::Cpop, Algorithm::Peft];
+
+impl Algorithm {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Algorithm::Heft => "heft",
+            Algorithm::Cpop => "cpop",
+            Algorithm::Peft => "peft",
+        };
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        return ALL_ALGORITHMS.iter().find(|x| x.name() == name).copied();
+    }
+}
+
+#[derive(Clone)]
+pub struct ScheduledTask {
+    pub processor: usize,
+    pub start: f64,
+    pub end: f64,
+}
+
+pub struct Schedule {
+    pub algorithm: Algorithm,
+    // indexed by node index of InstanceDag
+    pub tasks: Vec<ScheduledTask>,
+    pub makespan: f64,
+}
+
+impl Schedule {
+    // schedule length ratio: makespan to critical path where every task runs on the fastest processor
+    pub fn slr(&self, dag: &InstanceDag, platform: &Platform) -> f64 {
+        let fastest = platform.speeds.iter().copied().fold(0.0, f64::max);
+        let order = toposort(dag, None).unwrap_or_default();
+        let mut path = vec![0.0; dag.node_count()];
+        for node in order.iter() {
+            let start = dag
+                .neighbors_directed(*node, Incoming)
+                .map(|x| path[x.index()])
+                .fold(0.0, f64::max);
+            path[node.index()] = start + dag.node_weight(*node).unwrap().flops / fastest;
+        }
+        let critical_path = path.iter().copied().fold(0.0, f64::max);
+        if critical_path == 0.0 {
+            return 1.0;
+        }
+        return self.makespan / critical_path;
+    }
+
+    // sequential time on the fastest processor to makespan
+    pub fn speedup(&self, dag: &InstanceDag, platform: &Platform) -> f64 {
+        let fastest = platform.speeds.iter().copied().fold(0.0, f64::max);
+        let sequential = dag.node_weights().map(|x| x.flops).sum::<f64>() / fastest;
+        if self.makespan == 0.0 {
+            return 1.0;
+        }
+        return sequential / self.makespan;
+    }
+}
+
+struct Placement<'a> {
+    dag: &'a InstanceDag,
+    platform: &'a Platform,
+    placed: Vec<Option<ScheduledTask>>,
+    // busy intervals of every processor sorted by start
+    busy: Vec<Vec<(f64, f64)>>,
+}
+
+impl<'a> Placement<'a> {
+    fn new(dag: &'a InstanceDag, platform: &'a Platform) -> Self {
+        return Placement {
+            dag: dag,
+            platform: platform,
+            placed: vec![None; dag.node_count()],
+            busy: vec![Vec::new(); platform.speeds.len()],
+        };
+    }
+
+    // insertion based: task could go into a gap between already placed tasks
+    fn earliest(&self, node: NodeIndex, processor: usize) -> (f64, f64) {
+        let mut ready: f64 = 0.0;
+        for edge in self.dag.edges_directed(node, Incoming) {
+            let parent = self.placed[edge.source().index()].as_ref().unwrap();
+            ready = ready.max(
+                parent.end
+                    + self
+                        .platform
+                        .comm(*edge.weight(), parent.processor, processor),
+            );
+        }
+        let duration = self
+            .platform
+            .comp(self.dag.node_weight(node).unwrap().flops, processor);
+        let mut start = ready;
+        for (busy_start, busy_end) in self.busy[processor].iter() {
+            if start + duration <= *busy_start {
+                break;
+            }
+            start = start.max(*busy_end);
+        }
+        return (start, start + duration);
+    }
+
+    // processor with minimal finish time plus extra cost of the processor
+    fn best(&self, node: NodeIndex, extra: impl Fn(usize) -> f64) -> (usize, f64, f64) {
+        let mut result = (0, f64::INFINITY, f64::INFINITY);
+        let mut best_score = f64::INFINITY;
+        for processor in 0..self.platform.speeds.len() {
+            let (start, end) = self.earliest(node, processor);
+            let score = end + extra(processor);
+            if score < best_score {
+                best_score = score;
+                result = (processor, start, end);
+            }
+        }
+        return result;
+    }
+
+    fn place(&mut self, node: NodeIndex, processor: usize, start: f64, end: f64) {
+        self.placed[node.index()] = Some(ScheduledTask {
+            processor: processor,
+            start: start,
+            end: end,
+        });
+        let busy = &mut self.busy[processor];
+        let pos = busy.partition_point(|x| x.0 < start);
+        busy.insert(pos, (start, end));
+    }
+
+    fn finish(self, algorithm: Algorithm) -> Schedule {
+        let tasks: Vec<ScheduledTask> = self.placed.into_iter().map(|x| x.unwrap()).collect();
+        let makespan = tasks.iter().map(|x| x.end).fold(0.0, f64::max);
+        return Schedule {
+            algorithm: algorithm,
+            tasks: tasks,
+            makespan: makespan,
+        };
+    }
+}
+
+fn upward_rank(dag: &InstanceDag, platform: &Platform, order: &[NodeIndex]) -> Vec<f64> {
+    let mut result = vec![0.0; dag.node_count()];
+    for node in order.iter().rev() {
+        let tail = dag
+            .edges_directed(*node, Outgoing)
+            .map(|edge| platform.avg_comm(*edge.weight()) + result[edge.target().index()])
+            .fold(0.0, f64::max);
+        result[node.index()] = platform.avg_comp(dag.node_weight(*node).unwrap().flops) + tail;
+    }
+    return result;
+}
+
+fn downward_rank(dag: &InstanceDag, platform: &Platform, order: &[NodeIndex]) -> Vec<f64> {
+    let mut result = vec![0.0; dag.node_count()];
+    for node in order.iter() {
+        result[node.index()] = dag
+            .edges_directed(*node, Incoming)
+            .map(|edge| {
+                let parent = edge.source();
+                result[parent.index()]
+                    + platform.avg_comp(dag.node_weight(parent).unwrap().flops)
+                    + platform.avg_comm(*edge.weight())
+            })
+            .fold(0.0, f64::max);
+    }
+    return result;
+}
+
+// optimistic cost table of PEFT: time to the exit from task placed on processor
+fn optimistic_costs(dag: &InstanceDag, platform: &Platform, order: &[NodeIndex]) -> Vec<Vec<f64>> {
+    let proc_cnt = platform.speeds.len();
+    let mut result = vec![vec![0.0; proc_cnt]; dag.node_count()];
+    for node in order.iter().rev() {
+        for processor in 0..proc_cnt {
+            let mut cost: f64 = 0.0;
+            for edge in dag.edges_directed(*node, Outgoing) {
+                let child = edge.target();
+                let child_flops = dag.node_weight(child).unwrap().flops;
+                let best = (0..proc_cnt)
+                    .map(|child_proc| {
+                        let comm = if child_proc == processor {
+                            0.0
+                        } else {
+                            platform.avg_comm(*edge.weight())
+                        };
+                        result[child.index()][child_proc]
+                            + platform.comp(child_flops, child_proc)
+                            + comm
+                    })
+                    .fold(f64::INFINITY, f64::min);
+                cost = cost.max(best);
+            }
+            result[node.index()][processor] = cost;
+        }
+    }
+    return result;
+}
+
+// Picks ready task with the highest priority until all are placed, ties go to earlier task
+fn by_ready_list(dag: &InstanceDag, priority: &[f64], mut place: impl FnMut(NodeIndex)) {
+    let mut parents_left: Vec<usize> = dag
+        .node_indices()
+        .map(|x| dag.edges_directed(x, Incoming).count())
+        .collect();
+    let mut ready: Vec<NodeIndex> = dag
+        .node_indices()
+        .filter(|x| parents_left[x.index()] == 0)
+        .collect();
+    while !ready.is_empty() {
+        let mut pos = 0;
+        for (ind, node) in ready.iter().enumerate() {
+            let chosen = ready[pos];
+            if priority[node.index()] > priority[chosen.index()]
+                || (priority[node.index()] == priority[chosen.index()]
+                    && node.index() < chosen.index())
+            {
+                pos = ind;
+            }
+        }
+        let node = ready.swap_remove(pos);
+        place(node);
+        for edge in dag.edges_directed(node, Outgoing) {
+            let child = edge.target().index();
+            parents_left[child] -= 1;
+            if parents_left[child] == 0 {
+                ready.push(edge.target());
+            }
+        }
+    }
+}
+
+pub fn schedule(dag: &InstanceDag, platform: &Platform, algorithm: Algorithm) -> Result<Schedule> {
+    let order = toposort(dag, None).map_err(|_| Error::Cycle)?;
+    let mut placement = Placement::new(dag, platform);
+
+    match algorithm {
+        Algorithm::Heft => {
+            let rank = upward_rank(dag, platform, &order);
+            by_ready_list(dag, &rank, |node| {
+                let (processor, start, end) = placement.best(node, |_| 0.0);
+                placement.place(node, processor, start, end);
+            });
+        }
+        Algorithm::Cpop => {
+            let up = upward_rank(dag, platform, &order);
+            let down = downward_rank(dag, platform, &order);
+            let priority: Vec<f64> = up.iter().zip(down.iter()).map(|(a, b)| a + b).collect();
+            let critical = priority.iter().copied().fold(0.0, f64::max);
+            let eps = 1e-9 * critical.max(1.0);
+            let on_critical: Vec<bool> = priority.iter().map(|x| critical - x <= eps).collect();
+
+            // the processor which runs whole critical path the fastest
+            let cp_flops: f64 = dag
+                .node_indices()
+                .filter(|x| on_critical[x.index()])
+                .map(|x| dag.node_weight(x).unwrap().flops)
+                .sum();
+            let cp_processor = (0..platform.speeds.len())
+                .min_by(|a, b| {
+                    platform
+                        .comp(cp_flops, *a)
+                        .partial_cmp(&platform.comp(cp_flops, *b))
+                        .unwrap()
+                })
+                .unwrap();
+
+            by_ready_list(dag, &priority, |node| {
+                if on_critical[node.index()] {
+                    let (start, end) = placement.earliest(node, cp_processor);
+                    placement.place(node, cp_processor, start, end);
+                } else {
+                    let (processor, start, end) = placement.best(node, |_| 0.0);
+                    placement.place(node, processor, start, end);
+                }
+            });
+        }
+        Algorithm::Peft => {
+            let oct = optimistic_costs(dag, platform, &order);
+            let rank: Vec<f64> = oct
+                .iter()
+                .map(|x| x.iter().sum::<f64>() / x.len() as f64)
+                .collect();
+            by_ready_list(dag, &rank, |node| {
+                let (processor, start, end) =
+                    placement.best(node, |processor| oct[node.index()][processor]);
+                placement.place(node, processor, start, end);
+            });
+        }
+    }
+    return Ok(placement.finish(algorithm));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::seeded_rng;
+    use crate::instance::{AddEdge, InstDagVertex};
+    use rand::Rng;
+
+    fn dag_of(flops: &[f64], edges: &[(usize, usize, f64)]) -> InstanceDag {
+        let mut dag = InstanceDag::new();
+        for (ind, flops) in flops.iter().enumerate() {
+            dag.add_node(InstDagVertex {
+                inst_name: format!("t{}", ind),
+                dependencies: Vec::new(),
+                flops: *flops,
+                cpu: 100.0,
+                mem: 1.0,
+            });
+        }
+        for (parent, child, weight) in edges.iter() {
+            dag.add_ins_edge(*parent, *child, *weight);
+        }
+        return dag;
+    }
+
+    // no two tasks overlap on a processor, children start after data of parents came
+    fn assert_valid(dag: &InstanceDag, platform: &Platform, result: &Schedule) {
+        for a in 0..result.tasks.len() {
+            for b in (a + 1)..result.tasks.len() {
+                let (x, y) = (&result.tasks[a], &result.tasks[b]);
+                if x.processor == y.processor {
+                    assert!(x.end <= y.start + 1e-9 || y.end <= x.start + 1e-9);
+                }
+            }
+        }
+        for edge in dag.edge_references() {
+            let parent = &result.tasks[edge.source().index()];
+            let child = &result.tasks[edge.target().index()];
+            let comm = platform.comm(*edge.weight(), parent.processor, child.processor);
+            assert!(parent.end + comm <= child.start + 1e-9);
+        }
+        let makespan = result.tasks.iter().map(|x| x.end).fold(0.0, f64::max);
+        assert_eq!(result.makespan, makespan);
+    }
+
+    // a -> b, a -> c, b -> d, c -> d on two equal processors, every edge costs 1
+    #[test]
+    fn diamond_by_hand() {
+        let dag = dag_of(
+            &[2.0, 4.0, 4.0, 2.0],
+            &[(0, 1, 1.0), (0, 2, 1.0), (1, 3, 1.0), (2, 3, 1.0)],
+        );
+        let platform = Platform::new(vec![1.0, 1.0], 1.0);
+        let processors = |result: &Schedule| -> Vec<usize> {
+            return result.tasks.iter().map(|x| x.processor).collect();
+        };
+
+        // b and c go to different processors, d waits for data of b
+        let heft = schedule(&dag, &platform, Algorithm::Heft).unwrap();
+        assert_eq!(heft.makespan, 9.0);
+        assert_eq!(processors(&heft), vec![0, 0, 1, 1]);
+        assert_eq!(heft.slr(&dag, &platform), 9.0 / 8.0);
+        assert_eq!(heft.speedup(&dag, &platform), 12.0 / 9.0);
+        // every task is on the critical path, so all of them go to one processor
+        let cpop = schedule(&dag, &platform, Algorithm::Cpop).unwrap();
+        assert_eq!(cpop.makespan, 12.0);
+        assert_eq!(processors(&cpop), vec![0, 0, 0, 0]);
+        let peft = schedule(&dag, &platform, Algorithm::Peft).unwrap();
+        assert_eq!(peft.makespan, 9.0);
+        assert_eq!(processors(&peft), vec![0, 0, 1, 1]);
+        for result in [heft, cpop, peft] {
+            assert_valid(&dag, &platform, &result);
+        }
+    }
+
+    #[test]
+    fn insertion_fills_gaps() {
+        let dag = dag_of(&[1.0, 1.0, 2.0, 4.0], &[]);
+        let platform = Platform::new(vec![1.0], 1.0);
+        let mut placement = Placement::new(&dag, &platform);
+        placement.place(NodeIndex::new(1), 0, 4.0, 5.0);
+        placement.place(NodeIndex::new(0), 0, 0.0, 1.0);
+        assert_eq!(placement.earliest(NodeIndex::new(2), 0), (1.0, 3.0));
+        assert_eq!(placement.earliest(NodeIndex::new(3), 0), (5.0, 9.0));
+    }
+
+    #[test]
+    fn random_dags_are_valid() {
+        let mut rnd = seeded_rng(11);
+        for _ in 0..20 {
+            let node_cnt = 25;
+            let flops: Vec<f64> = (0..node_cnt).map(|_| rnd.gen_range(1.0..10.0)).collect();
+            let mut edges = Vec::new();
+            for child in 1..node_cnt {
+                for parent in 0..child {
+                    if rnd.gen_bool(0.15) {
+                        edges.push((parent, child, rnd.gen_range(0.0..5.0)));
+                    }
+                }
+            }
+            let dag = dag_of(&flops, &edges);
+            let platform = Platform::new(vec![1.0, 1.5, 2.0], 1.0);
+            for algorithm in ALL_ALGORITHMS.iter() {
+                let result = schedule(&dag, &platform, *algorithm).unwrap();
+                assert_valid(&dag, &platform, &result);
+                assert!(result.slr(&dag, &platform) >= 1.0 - 1e-9);
+            }
+        }
+    }
+}
@@ -0,0 +1,445 @@
+use crate::error::{read_dir_sorted, write_file, Error, Result};
+use crate::pure_dag::*;
+use petgraph::algo::toposort;
+use petgraph::visit::EdgeRef;
+use petgraph::{Directed, Graph};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Decomposition of vertex series-parallel dag. Leaf is node index in the dag. In series
+// every sink of a part precedes every source of the next part, parallel parts are
+// independent. Series has no series parts and parallel has no parallel parts
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum SpTree {
+    Leaf(usize),
+    Series(Vec<SpTree>),
+    Parallel(Vec<SpTree>),
+}
+
+impl SpTree {
+    pub fn leaves(&self) -> usize {
+        return match self {
+            SpTree::Leaf(_) => 1,
+            SpTree::Series(parts) | SpTree::Parallel(parts) => {
+                parts.iter().map(|x| x.leaves()).sum()
+            }
+        };
+    }
+
+    // series and parallel nodes on the longest way from root to a leaf
+    pub fn nesting_depth(&self) -> usize {
+        return match self {
+            SpTree::Leaf(_) => 0,
+            SpTree::Series(parts) | SpTree::Parallel(parts) => {
+                1 + parts.iter().map(|x| x.nesting_depth()).max().unwrap_or(0)
+            }
+        };
+    }
+
+    // number of parts of every parallel node
+    pub fn branching(&self) -> Vec<usize> {
+        let mut result = Vec::new();
+        self.collect_branching(&mut result);
+        return result;
+    }
+
+    fn collect_branching(&self, result: &mut Vec<usize>) {
+        match self {
+            SpTree::Leaf(_) => {}
+            SpTree::Series(parts) => parts.iter().for_each(|x| x.collect_branching(result)),
+            SpTree::Parallel(parts) => {
+                result.push(parts.len());
+                parts.iter().for_each(|x| x.collect_branching(result));
+            }
+        }
+    }
+
+    pub fn sources(&self) -> Vec<usize> {
+        return match self {
+            SpTree::Leaf(ind) => vec![*ind],
+            SpTree::Series(parts) => parts.first().map(|x| x.sources()).unwrap_or_default(),
+            SpTree::Parallel(parts) => parts.iter().flat_map(|x| x.sources()).collect(),
+        };
+    }
+
+    pub fn sinks(&self) -> Vec<usize> {
+        return match self {
+            SpTree::Leaf(ind) => vec![*ind],
+            SpTree::Series(parts) => parts.last().map(|x| x.sinks()).unwrap_or_default(),
+            SpTree::Parallel(parts) => parts.iter().flat_map(|x| x.sinks()).collect(),
+        };
+    }
+
+    // edges of transitive reduction of the dag, so a sampled tree could be turned into a dag
+    pub fn edges(&self) -> Vec<(usize, usize)> {
+        let mut result = Vec::new();
+        self.collect_edges(&mut result);
+        return result;
+    }
+
+    fn collect_edges(&self, result: &mut Vec<(usize, usize)>) {
+        match self {
+            SpTree::Leaf(_) => {}
+            SpTree::Series(parts) => {
+                for part in parts.iter() {
+                    part.collect_edges(result);
+                }
+                for pair in parts.windows(2) {
+                    for from in pair[0].sinks() {
+                        for to in pair[1].sources() {
+                            result.push((from, to));
+                        }
+                    }
+                }
+            }
+            SpTree::Parallel(parts) => parts.iter().for_each(|x| x.collect_edges(result)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SpDecomposition {
+    pub tree: SpTree,
+    // edges (source, target) dropped to get series-parallel dag, empty if it was one
+    pub removed: Vec<(usize, usize)>,
+}
+
+fn has(row: &Vec<u64>, ind: usize) -> bool {
+    return row[ind / 64] & (1 << (ind % 64)) != 0;
+}
+
+fn set(row: &mut Vec<u64>, ind: usize) {
+    row[ind / 64] |= 1 << (ind % 64);
+}
+
+// descendants and ancestors of every vertex as bitsets, order is topological
+fn reachability(
+    node_cnt: usize,
+    order: &Vec<usize>,
+    edges: &Vec<(usize, usize)>,
+) -> (Vec<Vec<u64>>, Vec<Vec<u64>>) {
+    let words = (node_cnt + 63) / 64;
+    let mut children = vec![Vec::new(); node_cnt];
+    let mut parents = vec![Vec::new(); node_cnt];
+    for (from, to) in edges.iter() {
+        children[*from].push(*to);
+        parents[*to].push(*from);
+    }
+    let mut descendants = vec![vec![0u64; words]; node_cnt];
+    for node in order.iter().rev() {
+        let mut row = vec![0u64; words];
+        for child in children[*node].iter() {
+            for (word, child_word) in row.iter_mut().zip(descendants[*child].iter()) {
+                *word |= child_word;
+            }
+            set(&mut row, *child);
+        }
+        descendants[*node] = row;
+    }
+    let mut ancestors = vec![vec![0u64; words]; node_cnt];
+    for node in order.iter() {
+        let mut row = vec![0u64; words];
+        for parent in parents[*node].iter() {
+            for (word, parent_word) in row.iter_mut().zip(ancestors[*parent].iter()) {
+                *word |= parent_word;
+            }
+            set(&mut row, *parent);
+        }
+        ancestors[*node] = row;
+    }
+    return (descendants, ancestors);
+}
+
+// connected components of nodes where linked tells if two nodes are adjacent
+fn components<F: Fn(usize, usize) -> bool>(nodes: &Vec<usize>, linked: F) -> Vec<Vec<usize>> {
+    let mut used = vec![false; nodes.len()];
+    let mut result = Vec::new();
+    for start in 0..nodes.len() {
+        if used[start] {
+            continue;
+        }
+        used[start] = true;
+        let mut component = vec![nodes[start]];
+        let mut queue = vec![start];
+        while let Some(cur) = queue.pop() {
+            for next in 0..nodes.len() {
+                if !used[next] && linked(nodes[cur], nodes[next]) {
+                    used[next] = true;
+                    component.push(nodes[next]);
+                    queue.push(next);
+                }
+            }
+        }
+        component.sort();
+        result.push(component);
+    }
+    return result;
+}
+
+// Parallel parts are components of comparability graph, series parts are components of
+// incomparability graph. If both graphs are connected nodes have an N inside
+fn split(nodes: Vec<usize>, descendants: &Vec<Vec<u64>>) -> Option<SpTree> {
+    if nodes.len() == 1 {
+        return Some(SpTree::Leaf(nodes[0]));
+    }
+    let comparable = |a: usize, b: usize| has(&descendants[a], b) || has(&descendants[b], a);
+    let parts = components(&nodes, comparable);
+    if parts.len() > 1 {
+        let parts = parts
+            .into_iter()
+            .map(|x| split(x, descendants))
+            .collect::<Option<Vec<SpTree>>>()?;
+        return Some(SpTree::Parallel(parts));
+    }
+    let mut parts = components(&nodes, |a, b| a != b && !comparable(a, b));
+    if parts.len() > 1 {
+        // every node of one part is comparable with every node of another
+        parts.sort_by(|a, b| {
+            if has(&descendants[a[0]], b[0]) {
+                std::cmp::Ordering::Less
+            } else {
+                std::cmp::Ordering::Greater
+            }
+        });
+        let parts = parts
+            .into_iter()
+            .map(|x| split(x, descendants))
+            .collect::<Option<Vec<SpTree>>>()?;
+        return Some(SpTree::Series(parts));
+    }
+    return None;
+}
+
+fn sp_tree(node_cnt: usize, order: &Vec<usize>, edges: &Vec<(usize, usize)>) -> Option<SpTree> {
+    if node_cnt == 0 {
+        return Some(SpTree::Parallel(Vec::new()));
+    }
+    let (descendants, _) = reachability(node_cnt, order, edges);
+    return split((0..node_cnt).collect(), &descendants);
+}
+
+// edges u -> v where v is not reachable from another child of u
+fn reduction(edges: &Vec<(usize, usize)>, descendants: &Vec<Vec<u64>>) -> Vec<(usize, usize)> {
+    return edges
+        .iter()
+        .filter(|(from, to)| {
+            !edges.iter().any(|(other_from, other_to)| {
+                other_from == from && other_to != to && has(&descendants[*other_to], *to)
+            })
+        })
+        .copied()
+        .collect();
+}
+
+// Edge of transitive reduction that starts an N: a < c, b < c, b < d and a, d are
+// incomparable, a with b and c with d too. Edge goes from b towards c
+fn find_n_edge(
+    node_cnt: usize,
+    order: &Vec<usize>,
+    edges: &Vec<(usize, usize)>,
+) -> Option<(usize, usize)> {
+    let (descendants, ancestors) = reachability(node_cnt, order, edges);
+    for (b, child) in reduction(edges, &descendants) {
+        let targets = (0..node_cnt).filter(|x| *x == child || has(&descendants[child], *x));
+        for c in targets {
+            let ds: Vec<usize> = (0..node_cnt)
+                .filter(|x| {
+                    has(&descendants[b], *x)
+                        && *x != c
+                        && !has(&descendants[c], *x)
+                        && !has(&ancestors[c], *x)
+                })
+                .collect();
+            if ds.is_empty() {
+                continue;
+            }
+            let found = (0..node_cnt)
+                .filter(|x| {
+                    has(&ancestors[c], *x)
+                        && *x != b
+                        && !has(&ancestors[b], *x)
+                        && !has(&descendants[b], *x)
+                })
+                .any(|a| ds.iter().any(|d| !has(&descendants[a], *d)));
+            if found {
+                return Some((b, child));
+            }
+        }
+    }
+    return None;
+}
+
+// Decomposition tree if the dag is vertex series-parallel
+pub fn decompose<N, E>(graph: &Graph<N, E, Directed>) -> Result<Option<SpTree>> {
+    let order: Vec<usize> = toposort(graph, None)
+        .map_err(|_| Error::Cycle)?
+        .iter()
+        .map(|x| x.index())
+        .collect();
+    let edges = graph
+        .edge_references()
+        .map(|x| (x.source().index(), x.target().index()))
+        .collect();
+    return Ok(sp_tree(graph.node_count(), &order, &edges));
+}
+
+pub fn is_series_parallel<N, E>(graph: &Graph<N, E, Directed>) -> bool {
+    return matches!(decompose(graph), Ok(Some(_)));
+}
+
+// Removes edges of transitive reduction breaking N subgraphs until the dag is
+// series-parallel, then puts back every edge which does not break it again. Removed
+// set is minimal by inclusion, not the smallest one
+pub fn make_series_parallel<N, E>(graph: &Graph<N, E, Directed>) -> Result<SpDecomposition> {
+    let node_cnt = graph.node_count();
+    let order: Vec<usize> = toposort(graph, None)
+        .map_err(|_| Error::Cycle)?
+        .iter()
+        .map(|x| x.index())
+        .collect();
+    let mut edges: Vec<(usize, usize)> = graph
+        .edge_references()
+        .map(|x| (x.source().index(), x.target().index()))
+        .collect();
+    edges.sort();
+    edges.dedup();
+
+    let mut removed = Vec::new();
+    while sp_tree(node_cnt, &order, &edges).is_none() {
+        let edge = find_n_edge(node_cnt, &order, &edges).ok_or_else(|| {
+            Error::Config(String::from(
+                "dag is not series-parallel but has no N inside",
+            ))
+        })?;
+        edges.retain(|x| *x != edge);
+        removed.push(edge);
+    }
+    let mut ind = removed.len();
+    while ind > 0 {
+        ind -= 1;
+        edges.push(removed[ind]);
+        if sp_tree(node_cnt, &order, &edges).is_some() {
+            removed.remove(ind);
+        } else {
+            edges.pop();
+        }
+    }
+    removed.sort();
+    return Ok(SpDecomposition {
+        tree: sp_tree(node_cnt, &order, &edges).unwrap(),
+        removed: removed,
+    });
+}
+
+// Decompositions of real dags of graph_type: output_file.json maps job to its
+// decomposition, output_file.csv has a row per job
+pub fn save_sp_trees(real_dir: &str, graph_type: &str, output_file: &str) -> Result<()> {
+    let mut trees = BTreeMap::<String, SpDecomposition>::new();
+    let mut csv_bufer =
+        String::from("job,tasks,edges,removed_edges,nesting_depth,parallel_nodes,max_branching\n");
+    for path in read_dir_sorted(real_dir)? {
+        if !path.contains(graph_type) {
+            continue;
+        }
+        let pure_dags = PureDags::get_from_file(path.as_str())?;
+        let mut job_names: Vec<&String> = pure_dags.dags.keys().collect();
+        job_names.sort();
+        for job_name in job_names {
+            let graph = &pure_dags.dags[job_name];
+            let decomposition = match make_series_parallel(graph) {
+                Ok(decomposition) => decomposition,
+                Err(err) => {
+                    eprintln!("skip {}", err.in_job(job_name));
+                    continue;
+                }
+            };
+            let branching = decomposition.tree.branching();
+            csv_bufer.push_str(&format!(
+                "{},{},{},{},{},{},{}\n",
+                job_name,
+                graph.node_count(),
+                graph.edge_count(),
+                decomposition.removed.len(),
+                decomposition.tree.nesting_depth(),
+                branching.len(),
+                branching.iter().max().copied().unwrap_or(0)
+            ));
+            trees.insert(job_name.clone(), decomposition);
+        }
+    }
+    let json = serde_json::to_string(&trees).map_err(|why| Error::json(output_file, why))?;
+    write_file(&format!("{}.json", output_file), &json)?;
+    return write_file(&format!("{}.csv", output_file), &csv_bufer);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dag_of(node_cnt: usize, edges: &[(usize, usize)]) -> Graph<(), (), Directed> {
+        let mut dag = Graph::new();
+        let nodes: Vec<_> = (0..node_cnt).map(|_| dag.add_node(())).collect();
+        for (from, to) in edges.iter() {
+            dag.add_edge(nodes[*from], nodes[*to], ());
+        }
+        return dag;
+    }
+
+    #[test]
+    fn diamond_is_series_of_parallel() {
+        let dag = dag_of(4, &[(0, 1), (0, 2), (1, 3), (2, 3)]);
+        let tree = decompose(&dag).unwrap().unwrap();
+        assert_eq!(
+            tree,
+            SpTree::Series(vec![
+                SpTree::Leaf(0),
+                SpTree::Parallel(vec![SpTree::Leaf(1), SpTree::Leaf(2)]),
+                SpTree::Leaf(3),
+            ])
+        );
+        assert_eq!(tree.leaves(), 4);
+        assert_eq!(tree.nesting_depth(), 2);
+        assert_eq!(tree.branching(), vec![2]);
+        let mut edges = tree.edges();
+        edges.sort();
+        assert_eq!(edges, vec![(0, 1), (0, 2), (1, 3), (2, 3)]);
+
+        let decomposition = make_series_parallel(&dag).unwrap();
+        assert!(decomposition.removed.is_empty());
+        assert_eq!(decomposition.tree, tree);
+    }
+
+    #[test]
+    fn n_loses_one_edge() {
+        // 0 -> 2 <- 1 -> 3, dropping 1 -> 2 leaves two chains
+        let dag = dag_of(4, &[(0, 2), (1, 2), (1, 3)]);
+        assert!(!is_series_parallel(&dag));
+        let decomposition = make_series_parallel(&dag).unwrap();
+        assert_eq!(decomposition.removed, vec![(1, 2)]);
+        assert_eq!(
+            decomposition.tree,
+            SpTree::Parallel(vec![
+                SpTree::Series(vec![SpTree::Leaf(0), SpTree::Leaf(2)]),
+                SpTree::Series(vec![SpTree::Leaf(1), SpTree::Leaf(3)]),
+            ])
+        );
+    }
+
+    #[test]
+    fn removed_edges_are_minimal() {
+        // N 0 -> 2 <- 1 -> 3 with 4 under everything, 0 -> 4 is implied and kept
+        let dag = dag_of(5, &[(0, 2), (1, 2), (1, 3), (2, 4), (3, 4), (0, 4)]);
+        let decomposition = make_series_parallel(&dag).unwrap();
+        assert_eq!(decomposition.removed.len(), 1);
+        // putting any removed edge back breaks the decomposition again
+        let mut edges: Vec<(usize, usize)> = dag
+            .edge_references()
+            .map(|x| (x.source().index(), x.target().index()))
+            .filter(|x| !decomposition.removed.contains(x))
+            .collect();
+        let kept = dag_of(5, &edges);
+        assert_eq!(decompose(&kept).unwrap(), Some(decomposition.tree.clone()));
+        edges.push(decomposition.removed[0]);
+        assert!(!is_series_parallel(&dag_of(5, &edges)));
+    }
+}
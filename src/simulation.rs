@@ -0,0 +1,502 @@
+use crate::error::{read_file, write_file, Error, Result};
+use crate::instance::InstanceDag;
+use crate::pure_dag::{Instance, PureDag};
+use crate::task_dag::cores;
+use crate::wfcommons::REFERENCE_BANDWIDTH;
+use petgraph::stable_graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+use petgraph::Direction::{Incoming, Outgoing};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Machine {
+    pub cores: u32,
+    pub memory: f64,
+    #[serde(default = "default_speed")]
+    pub speed: f64,
+}
+
+fn default_speed() -> f64 {
+    return 1.0;
+}
+
+// Every instance takes whole cores for its cpu and its mem on its machine, memory of
+// machines is in the same percents of reference machine memory as mem of instances.
+// Data between instances on different machines takes latency + size / bandwidth, bandwidth
+// is in bytes per second, links are not shared
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Cluster {
+    pub machines: Vec<Machine>,
+    pub bandwidth: f64,
+    #[serde(default)]
+    pub latency: f64,
+}
+
+impl Cluster {
+    pub fn uniform(machine_cnt: usize, cores: u32, memory: f64) -> Self {
+        return Cluster {
+            machines: vec![
+                Machine {
+                    cores: cores,
+                    memory: memory,
+                    speed: 1.0,
+                };
+                machine_cnt
+            ],
+            bandwidth: REFERENCE_BANDWIDTH,
+            latency: 0.0,
+        };
+    }
+
+    pub fn load_from_file(filename: &str) -> Result<Self> {
+        let contents = read_file(filename)?;
+        let cluster: Cluster =
+            serde_json::from_str(&contents).map_err(|why| Error::json(filename, why))?;
+        if cluster.machines.is_empty()
+            || cluster
+                .machines
+                .iter()
+                .any(|x| x.cores == 0 || x.speed <= 0.0 || x.memory <= 0.0)
+            || cluster.bandwidth <= 0.0
+        {
+            return Err(Error::Config(format!(
+                "{}: every machine needs cores, positive speed and positive memory",
+                filename
+            )));
+        }
+        return Ok(cluster);
+    }
+
+    fn transfer(&self, data: f64, from: usize, to: usize) -> f64 {
+        if from == to {
+            return 0.0;
+        }
+        return self.latency + data / self.bandwidth;
+    }
+}
+
+#[derive(Clone)]
+pub struct SimInstance {
+    pub machine: usize,
+    pub start: f64,
+    pub end: f64,
+    // held on machine from placement to end
+    pub cores: u32,
+    pub memory: f64,
+}
+
+// cores and memory instance takes on any machine
+pub fn demand(dag: &InstanceDag, node: NodeIndex) -> (u32, f64) {
+    let node_info = dag.node_weight(node).unwrap();
+    return (cores(node_info.cpu) as u32, node_info.mem);
+}
+
+#[derive(Clone)]
+pub struct UtilSample {
+    pub time: f64,
+    pub busy_cores: Vec<u32>,
+    pub used_memory: Vec<f64>,
+}
+
+// what policy could see to make a decision
+pub struct SimState<'a> {
+    pub dag: &'a InstanceDag,
+    pub cluster: &'a Cluster,
+    pub now: f64,
+    // in order instances became ready
+    pub ready: &'a Vec<NodeIndex>,
+    pub free_cores: &'a Vec<u32>,
+    pub free_memory: &'a Vec<f64>,
+    pub placed: &'a Vec<Option<SimInstance>>,
+}
+
+impl<'a> SimState<'a> {
+    pub fn fits(&self, node: NodeIndex, machine: usize) -> bool {
+        let (cores, memory) = demand(self.dag, node);
+        return self.free_cores[machine] >= cores && self.free_memory[machine] >= memory;
+    }
+
+    // time when all input data of instance is on machine
+    pub fn data_ready(&self, node: NodeIndex, machine: usize) -> f64 {
+        let mut result = self.now;
+        for edge in self.dag.edges_directed(node, Incoming) {
+            let parent = self.placed[edge.source().index()].as_ref().unwrap();
+            result = result.max(
+                parent.end
+                    + self
+                        .cluster
+                        .transfer(*edge.weight(), parent.machine, machine),
+            );
+        }
+        return result;
+    }
+}
+
+// Decides which ready instance goes to which machine. Called until it returns None,
+// then simulation waits for the next instance to finish
+pub trait Policy {
+    fn name(&self) -> &'static str;
+    fn pick(&mut self, state: &SimState) -> Option<(NodeIndex, usize)>;
+}
+
+// oldest ready instance to the first machine with free resources for it
+pub struct FirstFit;
+
+impl Policy for FirstFit {
+    fn name(&self) -> &'static str {
+        return "first_fit";
+    }
+    fn pick(&mut self, state: &SimState) -> Option<(NodeIndex, usize)> {
+        let node = *state.ready.first()?;
+        let machine = (0..state.cluster.machines.len()).find(|x| state.fits(node, *x))?;
+        return Some((node, machine));
+    }
+}
+
+// oldest ready instance to the machine where it could start the earliest, so children
+// tend to stay with their parents' data
+pub struct Locality;
+
+impl Policy for Locality {
+    fn name(&self) -> &'static str {
+        return "locality";
+    }
+    fn pick(&mut self, state: &SimState) -> Option<(NodeIndex, usize)> {
+        let node = *state.ready.first()?;
+        let machine = (0..state.cluster.machines.len())
+            .filter(|x| state.fits(node, *x))
+            .min_by(|a, b| {
+                state
+                    .data_ready(node, *a)
+                    .partial_cmp(&state.data_ready(node, *b))
+                    .unwrap()
+            })?;
+        return Some((node, machine));
+    }
+}
+
+pub fn policy_from_name(name: &str) -> Option<Box<dyn Policy>> {
+    return match name {
+        "first_fit" => Some(Box::new(FirstFit)),
+        "locality" => Some(Box::new(Locality)),
+        _ => None,
+    };
+}
+
+struct Finish {
+    time: f64,
+    node: NodeIndex,
+}
+
+impl PartialEq for Finish {
+    fn eq(&self, other: &Self) -> bool {
+        return self.cmp(other) == Ordering::Equal;
+    }
+}
+
+impl Eq for Finish {}
+
+impl PartialOrd for Finish {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+
+// reversed for min heap, equal times are ordered by node to be deterministic
+impl Ord for Finish {
+    fn cmp(&self, other: &Self) -> Ordering {
+        return other
+            .time
+            .partial_cmp(&self.time)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| other.node.cmp(&self.node));
+    }
+}
+
+pub struct SimResult {
+    pub policy: String,
+    // indexed by node index of InstanceDag
+    pub instances: Vec<SimInstance>,
+    pub trace: Vec<UtilSample>,
+    pub makespan: f64,
+}
+
+pub fn simulate(
+    dag: &InstanceDag,
+    cluster: &Cluster,
+    policy: &mut dyn Policy,
+) -> Result<SimResult> {
+    let node_cnt = dag.node_count();
+    // an instance no machine could hold would never start
+    for node in dag.node_indices() {
+        let (cores, memory) = demand(dag, node);
+        if !cluster
+            .machines
+            .iter()
+            .any(|x| x.cores >= cores && x.memory >= memory)
+        {
+            return Err(Error::Config(format!(
+                "instance {} needs {} cores and {} memory, no machine has them",
+                dag.node_weight(node).unwrap().inst_name,
+                cores,
+                memory
+            )));
+        }
+    }
+    let mut parents_left: Vec<usize> = dag
+        .node_indices()
+        .map(|x| dag.edges_directed(x, Incoming).count())
+        .collect();
+    let mut ready: Vec<NodeIndex> = dag
+        .node_indices()
+        .filter(|x| parents_left[x.index()] == 0)
+        .collect();
+    let mut free_cores: Vec<u32> = cluster.machines.iter().map(|x| x.cores).collect();
+    let mut free_memory: Vec<f64> = cluster.machines.iter().map(|x| x.memory).collect();
+    let mut placed: Vec<Option<SimInstance>> = vec![None; node_cnt];
+    let mut events = BinaryHeap::<Finish>::new();
+    let mut trace = Vec::<UtilSample>::new();
+    let mut now = 0.0;
+    let mut finished = 0;
+
+    loop {
+        loop {
+            let state = SimState {
+                dag: dag,
+                cluster: cluster,
+                now: now,
+                ready: &ready,
+                free_cores: &free_cores,
+                free_memory: &free_memory,
+                placed: &placed,
+            };
+            let (node, machine) = match policy.pick(&state) {
+                Some(choice) => choice,
+                None => break,
+            };
+            let pos = match ready.iter().position(|x| *x == node) {
+                Some(pos) => pos,
+                None => {
+                    return Err(Error::Config(format!(
+                        "policy {} picked instance which is not ready",
+                        policy.name()
+                    )))
+                }
+            };
+            if !state.fits(node, machine) {
+                return Err(Error::Config(format!(
+                    "policy {} picked machine {} without free resources",
+                    policy.name(),
+                    machine
+                )));
+            }
+            // resources are held while input data is transferred
+            let (cores, memory) = demand(dag, node);
+            let start = state.data_ready(node, machine);
+            let end =
+                start + dag.node_weight(node).unwrap().flops / cluster.machines[machine].speed;
+            ready.remove(pos);
+            free_cores[machine] -= cores;
+            free_memory[machine] -= memory;
+            placed[node.index()] = Some(SimInstance {
+                machine: machine,
+                start: start,
+                end: end,
+                cores: cores,
+                memory: memory,
+            });
+            events.push(Finish {
+                time: end,
+                node: node,
+            });
+        }
+        trace.push(UtilSample {
+            time: now,
+            busy_cores: cluster
+                .machines
+                .iter()
+                .zip(free_cores.iter())
+                .map(|(machine, free)| machine.cores - free)
+                .collect(),
+            used_memory: cluster
+                .machines
+                .iter()
+                .zip(free_memory.iter())
+                .map(|(machine, free)| machine.memory - free)
+                .collect(),
+        });
+
+        let event = match events.pop() {
+            Some(event) => event,
+            None => break,
+        };
+        now = event.time;
+        let instance = placed[event.node.index()].as_ref().unwrap();
+        free_cores[instance.machine] += instance.cores;
+        free_memory[instance.machine] += instance.memory;
+        finished += 1;
+        for edge in dag.edges_directed(event.node, Outgoing) {
+            let child = edge.target().index();
+            parents_left[child] -= 1;
+            if parents_left[child] == 0 {
+                ready.push(edge.target());
+            }
+        }
+    }
+
+    if finished != node_cnt {
+        return Err(Error::Config(format!(
+            "policy {} left {} instances unscheduled",
+            policy.name(),
+            node_cnt - finished
+        )));
+    }
+    let instances: Vec<SimInstance> = placed.into_iter().map(|x| x.unwrap()).collect();
+    let makespan = instances.iter().map(|x| x.end).fold(0.0, f64::max);
+    return Ok(SimResult {
+        policy: policy.name().to_string(),
+        instances: instances,
+        trace: trace,
+        makespan: makespan,
+    });
+}
+
+impl SimResult {
+    pub fn save_timeline(&self, dag: &InstanceDag, filename: &str) -> Result<()> {
+        let mut output = String::from("instance,machine,start,end\n");
+        for node in dag.node_indices() {
+            let instance = &self.instances[node.index()];
+            output.push_str(&format!(
+                "{},{},{},{}\n",
+                dag.node_weight(node).unwrap().inst_name,
+                instance.machine,
+                instance.start,
+                instance.end
+            ));
+        }
+        return write_file(filename, &output);
+    }
+
+    pub fn save_utilisation(&self, filename: &str) -> Result<()> {
+        let mut output = String::from("time,machine,busy_cores,used_memory\n");
+        for sample in self.trace.iter() {
+            for (machine, busy) in sample.busy_cores.iter().enumerate() {
+                output.push_str(&format!(
+                    "{},{},{},{}\n",
+                    sample.time, machine, busy, sample.used_memory[machine]
+                ));
+            }
+        }
+        return write_file(filename, &output);
+    }
+
+    pub fn summary(&self) -> TimelineSummary {
+        let total = self.instances.iter().map(|x| x.end - x.start).sum::<f64>();
+        let core_time = self
+            .instances
+            .iter()
+            .map(|x| (x.end - x.start) * x.cores as f64)
+            .sum::<f64>();
+        return TimelineSummary::new(self.instances.len(), self.makespan, total, core_time);
+    }
+}
+
+// numbers comparable between simulated and real timelines of a job
+pub struct TimelineSummary {
+    pub instances: usize,
+    pub makespan: f64,
+    pub mean_instance_time: f64,
+    // cores busy on average during makespan
+    pub busy_cores: f64,
+}
+
+impl TimelineSummary {
+    fn new(instances: usize, makespan: f64, total_time: f64, core_time: f64) -> Self {
+        return TimelineSummary {
+            instances: instances,
+            makespan: makespan,
+            mean_instance_time: if instances == 0 {
+                0.0
+            } else {
+                total_time / instances as f64
+            },
+            busy_cores: if makespan == 0.0 {
+                0.0
+            } else {
+                core_time / makespan
+            },
+        };
+    }
+}
+
+// real job from trace, cpu_avg is in percents of one core
+pub fn real_summary(dag: &PureDag) -> TimelineSummary {
+    let instances: Vec<&Instance> = dag
+        .node_weights()
+        .flat_map(|x| x.instances.iter())
+        .collect();
+    let start = dag.node_weights().map(|x| x.start_time).min().unwrap_or(0);
+    let end = dag.node_weights().map(|x| x.end_time).max().unwrap_or(0);
+    let total_time = instances.iter().map(|x| x.time as f64).sum::<f64>();
+    let core_time = instances
+        .iter()
+        .map(|x| x.time as f64 * x.cpu_avg / 100.0)
+        .sum::<f64>();
+    return TimelineSummary::new(
+        instances.len(),
+        end.saturating_sub(start) as f64,
+        total_time,
+        core_time,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instance::InstDagVertex;
+
+    fn independent(demands: &[(f64, f64)]) -> InstanceDag {
+        let mut dag = InstanceDag::new();
+        for (i, (cpu, mem)) in demands.iter().enumerate() {
+            dag.add_node(InstDagVertex {
+                inst_name: format!("i{}", i),
+                dependencies: Vec::new(),
+                flops: 1.0,
+                cpu: *cpu,
+                mem: *mem,
+            });
+        }
+        return dag;
+    }
+
+    #[test]
+    fn instances_hold_their_demands() {
+        let cluster = Cluster::uniform(1, 4, 100.0);
+        // both fit by cores, not by memory
+        let dag = independent(&[(100.0, 60.0), (100.0, 60.0)]);
+        let result = simulate(&dag, &cluster, &mut FirstFit).unwrap();
+        assert_eq!(result.makespan, 2.0);
+        assert_eq!(result.trace[0].used_memory, vec![60.0]);
+
+        // both fit by memory, not by cores
+        let dag = independent(&[(300.0, 10.0), (150.0, 10.0)]);
+        let result = simulate(&dag, &cluster, &mut FirstFit).unwrap();
+        assert_eq!(result.makespan, 2.0);
+        assert_eq!(result.trace[0].busy_cores, vec![3]);
+        assert_eq!(result.summary().busy_cores, 2.5);
+
+        let dag = independent(&[(100.0, 60.0), (100.0, 30.0)]);
+        let result = simulate(&dag, &cluster, &mut FirstFit).unwrap();
+        assert_eq!(result.makespan, 1.0);
+        assert_eq!(result.trace[0].used_memory, vec![90.0]);
+    }
+
+    #[test]
+    fn instance_larger_than_machines_is_an_error() {
+        let cluster = Cluster::uniform(2, 4, 100.0);
+        assert!(simulate(&independent(&[(500.0, 1.0)]), &cluster, &mut FirstFit).is_err());
+        assert!(simulate(&independent(&[(100.0, 150.0)]), &cluster, &mut FirstFit).is_err());
+    }
+}
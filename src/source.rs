@@ -0,0 +1,46 @@
+use crate::error::Result;
+use crate::pure_dag::{PureDag, PureDags, PureTaskInfo};
+use petgraph::stable_graph::NodeIndex;
+
+// receives jobs one at a time, jobs of one bucket go in a row
+pub trait JobSink {
+    fn job(&mut self, job_name: String, dag: PureDag) -> Result<()>;
+    fn bucket_done(&mut self, _bucket: usize) -> Result<()> {
+        return Ok(());
+    }
+}
+
+impl JobSink for PureDags {
+    fn job(&mut self, job_name: String, dag: PureDag) -> Result<()> {
+        self.insert(job_name, dag);
+        return Ok(());
+    }
+}
+
+// Trace of some cluster or workflow archive turned into PureDag jobs
+pub trait TraceSource {
+    fn ingest_stream(&self, sink: &mut dyn JobSink) -> Result<()>;
+
+    fn ingest(&self) -> Result<PureDags> {
+        let mut result = PureDags::new();
+        self.ingest_stream(&mut result)?;
+        return Ok(result);
+    }
+}
+
+// Node i gets name task{i+1} like in Alibaba trace, parents are (position, edge weight).
+// Name and dependences of tasks are overwritten
+pub fn dag_from_parents(tasks: Vec<PureTaskInfo>, parents: &[Vec<(usize, u64)>]) -> PureDag {
+    let mut result = PureDag::new();
+    for (ind, mut task) in tasks.into_iter().enumerate() {
+        task.name = format!("task{}", ind + 1);
+        task.dependences = parents[ind].iter().map(|x| x.0 as u32 + 1).collect();
+        result.add_node(task);
+    }
+    for (child, child_parents) in parents.iter().enumerate() {
+        for (parent, weight) in child_parents.iter() {
+            result.add_edge(NodeIndex::new(*parent), NodeIndex::new(child), *weight);
+        }
+    }
+    return result;
+}
@@ -1,49 +1,36 @@
-use crate::pure_dag::PureDag;
+use crate::dependency::PatternStatistic;
+use crate::dispersion::Dispersion;
+use crate::distribution::QuantileSketch;
+use crate::edge_data::EdgeDataStatistic;
+use crate::error::{read_file, write_file, Error, Result};
+use crate::motif::MotifStatistic;
+use crate::pure_dag::{AbsorbStat, DoTraverse, PureDag, SortNodeIndex};
+use crate::task_dag::{TaskDag, TaskDagFuncs, MAX_INST_CNT};
+use petgraph::stable_graph::NodeIndex;
 use rand::distributions::{Distribution, Uniform};
 
 use inc_stats;
-use rand::rngs::ThreadRng;
 use rand::Rng;
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::{Read, Write};
 use std::path::Path;
 
 pub trait StatBase {
     fn form_stats(&mut self);
     fn get_string_obj(&self) -> String;
-    fn load_obj_from_string(&mut self, str: String);
+    fn load_obj_from_string(&mut self, str: String) -> serde_json::Result<()>;
 }
 
 pub trait SaveToFile: StatBase {
-    fn save_to_file(&mut self, file_name: &str) {
+    fn save_to_file(&mut self, file_name: &str) -> Result<()> {
         self.form_stats();
         let j = self.get_string_obj();
-
-        let path = Path::new(file_name);
-        let mut file = match File::create(&path) {
-            Err(why) => panic!("cant open file to write {}", why),
-            Ok(file) => file,
-        };
-
-        match file.write_all(j.as_bytes()) {
-            Err(why) => panic!("cant save serialization {}", why),
-            Ok(_) => {}
-        }
+        return write_file(file_name, &j);
     }
-    fn load_from_file(&mut self, file_name: &str) {
-        let path = Path::new(file_name);
-        let mut file = match File::open(&path) {
-            Err(why) => panic!("cant open file to write {}", why),
-            Ok(file) => file,
-        };
-        let mut contents = String::new();
-        match file.read_to_string(&mut contents) {
-            Err(why) => panic!("couldn't read: {}", why),
-            Ok(_) => {}
-        }
-
-        self.load_obj_from_string(contents);
+    fn load_from_file(&mut self, file_name: &str) -> Result<()> {
+        let contents = read_file(file_name)?;
+        return self
+            .load_obj_from_string(contents)
+            .map_err(|why| Error::json(file_name, why));
     }
 }
 
@@ -72,7 +59,11 @@ impl CpStatistic {
         stats.add(node_cnt as f64);
     }
 
-    pub fn get_node_cnt(&self, rnd: &mut ThreadRng, cp: u32) -> Option<u32> {
+    pub fn has_cp(&self, cp: u32) -> bool {
+        return self.stat_result.contains_key(&cp);
+    }
+
+    pub fn get_node_cnt<R: Rng>(&self, rnd: &mut R, cp: u32) -> Option<u32> {
         let cp_range = match self.stat_result.get(&cp) {
             Some(par) => par,
             None => {
@@ -95,8 +86,9 @@ impl StatBase for CpStatistic {
     fn get_string_obj(&self) -> String {
         return serde_json::to_string(&self.stat_result).unwrap();
     }
-    fn load_obj_from_string(&mut self, str: String) {
-        self.stat_result = serde_json::from_str(&str).unwrap();
+    fn load_obj_from_string(&mut self, str: String) -> serde_json::Result<()> {
+        self.stat_result = serde_json::from_str(&str)?;
+        return Ok(());
     }
 }
 
@@ -129,8 +121,9 @@ impl StatBase for StructStatistic {
     fn get_string_obj(&self) -> String {
         return serde_json::to_string(&self.stat_result).unwrap();
     }
-    fn load_obj_from_string(&mut self, str: String) {
-        self.stat_result = serde_json::from_str(&str).unwrap();
+    fn load_obj_from_string(&mut self, str: String) -> serde_json::Result<()> {
+        self.stat_result = serde_json::from_str(&str)?;
+        return Ok(());
     }
 }
 
@@ -156,7 +149,7 @@ impl StructStatistic {
         }
     }
 
-    pub fn gen_level(&self, rnd: &mut ThreadRng, cp: u32, part: u32) -> Option<u32> {
+    pub fn gen_level<R: Rng>(&self, rnd: &mut R, cp: u32, part: u32) -> Option<u32> {
         let cumulative = match self.stat_result.get(&cp) {
             Some(ref par) => match par.get(&part) {
                 Some(cum_distr) => cum_distr,
@@ -173,39 +166,46 @@ impl StructStatistic {
         // all other assign accourding to distribution
         let tmp = uniform_rnd.sample(rnd);
         let level = match cumulative.binary_search(&tmp) {
-            Result::Err(ind) => ind,
-            Result::Ok(ind) => ind,
+            Err(ind) => ind,
+            Ok(ind) => ind,
         };
         return Some(level as u32);
     }
 
-    pub fn adjust_part(&self, cp: u32, part: &mut u32) {
+    pub fn adjust_part(&self, cp: u32, part: &mut u32) -> Result<()> {
         match self.stat_result.get(&cp) {
-            Some(ref par) => {
+            Some(ref par) if par.len() != 0 => {
                 let mut keys = par.keys().map(|&x| x).collect::<Vec<u32>>();
                 keys.sort();
                 match keys.binary_search(part) {
                     Ok(_) => {}
                     Err(ind) => {
-                        *part = keys[ind];
+                        *part = keys[ind.min(keys.len() - 1)];
                     }
                 }
             }
-            None => {
-                panic!("smh get cp with empty stat: {}", cp);
+            _ => {
+                return Err(Error::NoStatistic {
+                    name: String::from("level_distribute"),
+                    cp: cp,
+                    part: *part,
+                });
             }
         };
+        return Ok(());
     }
 }
 
 pub type MetricSerial = inc_stats::Percentiles<f64>;
 
 type StatSeries = HashMap<u32, HashMap<u32, Vec<MetricSerial>>>;
-type StatSaved = HashMap<u32, HashMap<u32, Vec<Vec<f64>>>>;
+type StatSaved = HashMap<u32, HashMap<u32, Vec<QuantileSketch>>>;
+// level statistics saved before sketches, percentiles are BASIC_PERCENTILES
+type StatSavedPercentiles = HashMap<u32, HashMap<u32, Vec<Vec<f64>>>>;
 
 trait MultiStatIndexes<T> {
     fn get_mut_by(&mut self, cp: u32, part: u32) -> &mut Vec<T>;
-    fn get_by(&self, cp: u32, part: u32) -> &Vec<T>;
+    fn get_by(&self, cp: u32, part: u32) -> Option<&Vec<T>>;
 }
 
 impl MultiStatIndexes<MetricSerial> for StatSeries {
@@ -221,14 +221,12 @@ impl MultiStatIndexes<MetricSerial> for StatSeries {
         part_values.resize_with(cp as usize, || MetricSerial::new());
         return part_values;
     }
-    fn get_by(&self, cp: u32, part: u32) -> &Vec<MetricSerial> {
-        let cp_values = self.get(&cp).unwrap();
-        let part_values = cp_values.get(&part).unwrap();
-        return part_values;
+    fn get_by(&self, cp: u32, part: u32) -> Option<&Vec<MetricSerial>> {
+        return self.get(&cp)?.get(&part);
     }
 }
-impl MultiStatIndexes<Vec<f64>> for StatSaved {
-    fn get_mut_by(&mut self, cp: u32, part: u32) -> &mut Vec<Vec<f64>> {
+impl MultiStatIndexes<QuantileSketch> for StatSaved {
+    fn get_mut_by(&mut self, cp: u32, part: u32) -> &mut Vec<QuantileSketch> {
         if !self.contains_key(&cp) {
             self.insert(cp, HashMap::new());
         }
@@ -237,20 +235,28 @@ impl MultiStatIndexes<Vec<f64>> for StatSaved {
             cp_values.insert(part, Vec::new());
         }
         let part_values = cp_values.get_mut(&part).unwrap();
-        part_values.resize_with(cp as usize, || Vec::<f64>::new());
+        part_values.resize_with(cp as usize, || QuantileSketch::empty());
         return part_values;
     }
-    fn get_by(&self, cp: u32, part: u32) -> &Vec<Vec<f64>> {
-        let cp_values = self.get(&cp).unwrap();
-        let part_values = cp_values.get(&part).unwrap();
-        return part_values;
+    fn get_by(&self, cp: u32, part: u32) -> Option<&Vec<QuantileSketch>> {
+        return self.get(&cp)?.get(&part);
     }
 }
 
+// statistics which get lognormal, Pareto or Weibull fit if LevelGenerator is parametric
+pub const FITTED_STATISTICS: [&str; 4] = [
+    "time_distrib",
+    "instance_distr_perc",
+    "cpu_distrib",
+    "mem_distrib",
+];
+
 pub struct LevelGenerator {
     // cp, part -> [level] -> some statistic to calc Percentiles
     stat: HashMap<String, StatSeries>,
     stat_result: HashMap<String, StatSaved>,
+    // fit families to FITTED_STATISTICS in form_stats, the best one by AIC is sampled
+    pub parametric: bool,
 }
 
 impl LevelGenerator {
@@ -259,11 +265,19 @@ impl LevelGenerator {
         return LevelGenerator {
             stat: HashMap::new(),
             stat_result: HashMap::new(),
+            parametric: false,
         };
     }
-    pub fn add_statistic<F>(&mut self, cp: u32, part: u32, name: &str, graph: &PureDag, stat_gen: F)
-    where
-        F: FnOnce(&PureDag) -> Vec<Vec<u32>>,
+    pub fn add_statistic<F, T>(
+        &mut self,
+        cp: u32,
+        part: u32,
+        name: &str,
+        graph: &PureDag,
+        stat_gen: F,
+    ) where
+        F: FnOnce(&PureDag) -> Vec<Vec<T>>,
+        T: Into<f64> + Copy,
     {
         if !self.stat.contains_key(name) {
             self.stat.insert(name.to_string(), StatSeries::new());
@@ -272,41 +286,54 @@ impl LevelGenerator {
         let stat_values = stat_gen(&graph);
         for (upd_val, level_val) in upd_values.iter_mut().zip(stat_values.iter()) {
             for val in level_val {
-                upd_val.add(*val as f64);
+                upd_val.add((*val).into());
             }
         }
     }
 
-    pub fn get_statistic(
+    pub fn has_statistic(&self, name: &str) -> bool {
+        return self.stat_result.contains_key(name);
+    }
+
+    pub fn get_statistic<R: Rng>(
         &self,
         cp: u32,
         part: u32,
         level: u32,
         name: &str,
-        rnd: &mut ThreadRng,
-    ) -> f64 {
-        let upd_values = self
+        rnd: &mut R,
+    ) -> Result<f64> {
+        let sketch = match self
             .stat_result
             .get(name)
-            .unwrap()
-            .get_by(cp, part)
-            .get(level as usize)
-            .unwrap();
-        let upd_values = &upd_values[1..=3];
-        let ps = BASIC_PERCENTILES.map(|x| x * 10.0);
-        let rnd_n = rnd.gen_range(0.0..10.0);
-        if rnd_n < ps[0] {
-            return upd_values[0];
-        }
-        if rnd_n <= ps[1] {
-            return upd_values[0]
-                + (upd_values[1] - upd_values[0]) / (ps[1] - ps[0]) * (rnd_n - ps[0]);
-        }
-        if rnd_n < ps[2] {
-            return upd_values[1]
-                + (upd_values[2] - upd_values[1]) / (ps[2] - ps[1]) * (rnd_n - ps[1]);
-        }
-        return upd_values[2];
+            .and_then(|stat| stat.get_by(cp, part))
+            .and_then(|stat| stat.get(level as usize))
+        {
+            Some(sketch) if sketch.count > 0 => sketch,
+            _ => {
+                return Err(Error::NoStatistic {
+                    name: name.to_string(),
+                    cp: cp,
+                    part: part,
+                })
+            }
+        };
+        return Ok(sketch.sample(rnd));
+    }
+
+    // for statistics a level may have no values of, like spread of tasks without stragglers
+    pub fn get_statistic_or<R: Rng>(
+        &self,
+        cp: u32,
+        part: u32,
+        level: u32,
+        name: &str,
+        default: f64,
+        rnd: &mut R,
+    ) -> f64 {
+        return self
+            .get_statistic(cp, part, level, name, rnd)
+            .unwrap_or(default);
     }
 }
 
@@ -324,15 +351,20 @@ impl StatBase for LevelGenerator {
                     let mut result_level = Vec::new();
                     for level_stat in part_values.iter() {
                         let result_stat = if level_stat.count() == 0 {
-                            vec![0.0; 5]
+                            QuantileSketch::empty()
                         } else {
-                            level_stat
-                                .percentiles(&BASIC_PERCENTILES)
-                                .unwrap()
-                                .unwrap()
-                                .iter()
-                                .map(|&x| x)
-                                .collect::<Vec<f64>>()
+                            let mut sketch = QuantileSketch {
+                                count: level_stat.count() as u64,
+                                quantiles: level_stat
+                                    .percentiles(&QuantileSketch::probabilities(level_stat.count()))
+                                    .unwrap()
+                                    .unwrap(),
+                                fit: None,
+                            };
+                            if self.parametric && FITTED_STATISTICS.contains(&name.as_str()) {
+                                sketch.fit_best();
+                            }
+                            sketch
                         };
                         result_level.push(result_stat);
                     }
@@ -347,9 +379,351 @@ impl StatBase for LevelGenerator {
     fn get_string_obj(&self) -> String {
         return serde_json::to_string(&self.stat_result).unwrap();
     }
-    fn load_obj_from_string(&mut self, str: String) {
-        self.stat_result = serde_json::from_str(&str).unwrap();
+    fn load_obj_from_string(&mut self, str: String) -> serde_json::Result<()> {
+        let saved: HashMap<String, StatSavedPercentiles> = match serde_json::from_str(&str) {
+            Ok(saved) => saved,
+            Err(_) => {
+                self.stat_result = serde_json::from_str(&str)?;
+                return Ok(());
+            }
+        };
+        self.stat_result.clear();
+        for (name, stat_values) in saved.into_iter() {
+            let mut result_cp = HashMap::new();
+            for (cp, cp_values) in stat_values.into_iter() {
+                let mut result_part = HashMap::new();
+                for (part, part_values) in cp_values.into_iter() {
+                    let result_level = part_values
+                        .iter()
+                        .map(|x| QuantileSketch::from_percentiles(&BASIC_PERCENTILES, x))
+                        .collect::<Vec<QuantileSketch>>();
+                    result_part.insert(part, result_level);
+                }
+                result_cp.insert(cp, result_part);
+            }
+            self.stat_result.insert(name, result_cp);
+        }
+        return Ok(());
     }
 }
 
 impl SaveToFile for LevelGenerator {}
+
+pub const CP_RANGES_FILENAME: &str = "cp_ranges.json";
+pub const LEVEL_DISTRIB_FILENAME: &str = "level_distribute.json";
+pub const LEVEL_GENERAOTR_FILENAME: &str = "level_generator.json";
+pub const MOTIFS_FILENAME: &str = "motifs.json";
+pub const EDGE_DATA_FILENAME: &str = "edge_data.json";
+pub const PATTERNS_FILENAME: &str = "patterns.json";
+
+pub fn calc_part(node_cnt: u32, critical_path: u32) -> u32 {
+    return node_cnt / critical_path;
+}
+
+pub struct FittedStats {
+    pub cp_ranges: CpStatistic,
+    pub level_distr_gen: StructStatistic,
+    pub level_gen: LevelGenerator,
+    pub motifs: MotifStatistic,
+    pub edge_data: EdgeDataStatistic,
+    pub patterns: PatternStatistic,
+}
+
+impl FittedStats {
+    pub fn new() -> Self {
+        return FittedStats {
+            cp_ranges: CpStatistic::new(),
+            level_distr_gen: StructStatistic::new(),
+            level_gen: LevelGenerator::new(),
+            motifs: MotifStatistic::new(),
+            edge_data: EdgeDataStatistic::new(),
+            patterns: PatternStatistic::new(),
+        };
+    }
+
+    // returns critical path of added graph, graph isn't added on error
+    pub fn add_dag(&mut self, graph: &mut PureDag) -> Result<u32> {
+        graph.sort_node_ids()?;
+        let node_cnt = graph.node_count();
+        let mut depths = vec![0; node_cnt];
+        let mut levels = vec![0; node_cnt];
+
+        graph.calc_levels(&mut depths, &mut levels)?;
+
+        let critical_path = match depths.iter().max() {
+            Some(critical_path) if *critical_path > 0 => *critical_path,
+            _ => return Err(Error::Levels),
+        };
+        // data sizes and dependency patterns are measured on task edges
+        let task_dag = TaskDag::from_pure_dag(graph)?;
+
+        self.cp_ranges.add(&critical_path, node_cnt as u32);
+
+        let part = calc_part(node_cnt as u32, critical_path);
+        self.level_distr_gen.add(critical_path, part, &levels);
+        self.motifs.add(critical_path, graph);
+        self.edge_data.add(&task_dag);
+        self.patterns.add(&task_dag);
+
+        // many massive statistics/ Yes, bad api again, but better
+
+        let level_gen = &mut self.level_gen;
+        level_gen.add_statistic(
+            critical_path,
+            part,
+            "childs_distribution",
+            graph,
+            |graph: &PureDag| -> Vec<Vec<u32>> {
+                let mut result = vec![Vec::new(); critical_path as usize];
+                for node_ind in graph.node_indices() {
+                    let child_cnt = graph.neighbors(node_ind).count();
+                    result[levels[node_ind.index() as usize] as usize].push(child_cnt as u32);
+                }
+                return result;
+            },
+        );
+        level_gen.add_statistic(
+            critical_path,
+            part,
+            "dependances_distribution",
+            graph,
+            |graph: &PureDag| -> Vec<Vec<u32>> {
+                let mut result = vec![Vec::new(); critical_path as usize];
+                for node_ind in graph.node_indices() {
+                    let dep_cnt = graph.node_weight(node_ind).unwrap().dependences.len();
+                    result[levels[node_ind.index() as usize] as usize].push(dep_cnt as u32);
+                }
+                return result;
+            },
+        );
+        level_gen.add_statistic(
+            critical_path,
+            part,
+            "instance_distr_init",
+            graph,
+            |graph: &PureDag| -> Vec<Vec<u32>> {
+                let mut result = vec![Vec::new(); critical_path as usize];
+                for node_ind in graph.node_indices() {
+                    let node_info = graph.node_weight(node_ind).unwrap();
+                    let node_level = levels[node_ind.index() as usize];
+                    if node_info.dependences.len() == 0 {
+                        result[node_level as usize]
+                            .push(node_info.instance_cnt.min(MAX_INST_CNT) as u32);
+                    }
+                }
+                return result;
+            },
+        );
+        level_gen.add_statistic(
+            critical_path,
+            part,
+            "instance_distr_perc",
+            graph,
+            |graph: &PureDag| -> Vec<Vec<u32>> {
+                let mut result = vec![Vec::new(); critical_path as usize];
+                for node_ind in graph.node_indices() {
+                    let node_info = graph.node_weight(node_ind).unwrap();
+                    let node_level = levels[node_ind.index() as usize];
+
+                    if node_info.dependences.len() != 0 {
+                        let mut depence_ins_avg = 0;
+                        for parent in node_info.dependences.iter() {
+                            depence_ins_avg += graph
+                                .node_weight(NodeIndex::new(*parent as usize))
+                                .unwrap()
+                                .instance_cnt
+                                .min(MAX_INST_CNT);
+                        }
+                        depence_ins_avg /= node_info.dependences.len() as u64;
+                        if depence_ins_avg == 0 {
+                            continue;
+                        }
+                        result[node_level as usize].push(
+                            (node_info.instance_cnt.min(MAX_INST_CNT) * 10000 / depence_ins_avg)
+                                as u32,
+                        );
+                    }
+                }
+                return result;
+            },
+        );
+
+        let (result_time, result_cpu, result_mem) =
+            graph.get_inst_inf(critical_path as usize, &levels);
+
+        level_gen.add_statistic(
+            critical_path,
+            part,
+            "heavy_distr",
+            graph,
+            |graph: &PureDag| -> Vec<Vec<u32>> {
+                let mut result = vec![Vec::new(); critical_path as usize];
+                for node_ind in graph.node_indices() {
+                    let node_info = graph.node_weight(node_ind).unwrap();
+                    let node_level = levels[node_ind.index() as usize];
+                    let ins_cnt = node_info.instance_cnt.min(MAX_INST_CNT) as f64;
+                    let time_amnt = (node_info.end_time - node_info.start_time) as f64;
+                    let heavy_score = 2.0 * ins_cnt * time_amnt / (ins_cnt + time_amnt);
+                    result[node_level as usize].push(heavy_score as u32);
+                }
+                return result;
+            },
+        );
+
+        level_gen.add_statistic(
+            critical_path,
+            part,
+            "time_distrib",
+            graph,
+            move |_: &PureDag| -> Vec<Vec<u32>> {
+                return result_time;
+            },
+        );
+
+        // demands of every instance: cpu in percents of one core, mem in percents of machine
+        level_gen.add_statistic(
+            critical_path,
+            part,
+            "cpu_distrib",
+            graph,
+            move |_: &PureDag| -> Vec<Vec<f64>> {
+                return result_cpu;
+            },
+        );
+
+        level_gen.add_statistic(
+            critical_path,
+            part,
+            "mem_distrib",
+            graph,
+            move |_: &PureDag| -> Vec<Vec<f64>> {
+                return result_mem;
+            },
+        );
+
+        // spread of instances inside tasks, only tasks with stragglers give magnitude
+        let mut result_cv = vec![Vec::new(); critical_path as usize];
+        let mut result_straggler_prob = vec![Vec::new(); critical_path as usize];
+        let mut result_straggler_magnitude = vec![Vec::new(); critical_path as usize];
+        for node_ind in graph.node_indices() {
+            let times: Vec<f64> = graph
+                .node_weight(node_ind)
+                .unwrap()
+                .instances
+                .iter()
+                .map(|x| x.time as f64)
+                .collect();
+            if let Some(dispersion) = Dispersion::from_times(&times) {
+                let node_level = levels[node_ind.index()] as usize;
+                result_cv[node_level].push(dispersion.cv);
+                result_straggler_prob[node_level].push(dispersion.straggler_prob);
+                if dispersion.straggler_prob > 0.0 {
+                    result_straggler_magnitude[node_level].push(dispersion.straggler_magnitude);
+                }
+            }
+        }
+        for (name, values) in [
+            ("time_cv", result_cv),
+            ("straggler_prob", result_straggler_prob),
+            ("straggler_magnitude", result_straggler_magnitude),
+        ] {
+            level_gen.add_statistic(
+                critical_path,
+                part,
+                name,
+                graph,
+                move |_: &PureDag| -> Vec<Vec<f64>> {
+                    return values;
+                },
+            );
+        }
+        return Ok(critical_path);
+    }
+
+    pub fn form_stats(&mut self) {
+        self.cp_ranges.form_stats();
+        self.level_distr_gen.form_stats();
+        self.level_gen.form_stats();
+        self.motifs.form_stats();
+        self.edge_data.form_stats();
+        self.patterns.form_stats();
+    }
+
+    pub fn save_to_dir(&mut self, dir: &str) -> Result<()> {
+        let stat_name = |filename: &str| -> String { format!("{}/{}", dir, filename) };
+        self.cp_ranges
+            .save_to_file(&stat_name(CP_RANGES_FILENAME).to_string())?;
+        self.level_distr_gen
+            .save_to_file(&stat_name(LEVEL_DISTRIB_FILENAME).to_string())?;
+        self.level_gen
+            .save_to_file(&stat_name(LEVEL_GENERAOTR_FILENAME).to_string())?;
+        self.motifs
+            .save_to_file(&stat_name(MOTIFS_FILENAME).to_string())?;
+        self.edge_data
+            .save_to_file(&stat_name(EDGE_DATA_FILENAME).to_string())?;
+        self.patterns
+            .save_to_file(&stat_name(PATTERNS_FILENAME).to_string())?;
+        return Ok(());
+    }
+
+    pub fn load_from_dir(dir: &str) -> Result<Self> {
+        let mut result = FittedStats::new();
+        let stat_name = |filename: &str| -> String { format!("{}/{}", dir, filename) };
+        result
+            .cp_ranges
+            .load_from_file(&stat_name(CP_RANGES_FILENAME).to_string())?;
+        result
+            .level_distr_gen
+            .load_from_file(&stat_name(LEVEL_DISTRIB_FILENAME).to_string())?;
+        result
+            .level_gen
+            .load_from_file(&stat_name(LEVEL_GENERAOTR_FILENAME).to_string())?;
+        // stats fitted before motifs were mined work for level generator
+        if Path::new(&stat_name(MOTIFS_FILENAME)).exists() {
+            result
+                .motifs
+                .load_from_file(&stat_name(MOTIFS_FILENAME).to_string())?;
+        }
+        // without data sizes generated task edges keep weight 1
+        if Path::new(&stat_name(EDGE_DATA_FILENAME)).exists() {
+            result
+                .edge_data
+                .load_from_file(&stat_name(EDGE_DATA_FILENAME).to_string())?;
+        }
+        // without patterns they are inferred from instance counts
+        if Path::new(&stat_name(PATTERNS_FILENAME)).exists() {
+            result
+                .patterns
+                .load_from_file(&stat_name(PATTERNS_FILENAME).to_string())?;
+        }
+        return Ok(result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::seeded_rng;
+
+    #[test]
+    fn empty_level_has_no_statistic() {
+        let mut level_gen = LevelGenerator::new();
+        level_gen.add_statistic(2, 1, "time_distrib", &PureDag::new(), |_| {
+            vec![vec![3.0, 5.0], Vec::new()]
+        });
+        level_gen.form_stats();
+        let mut rnd = seeded_rng(1);
+        let value = level_gen
+            .get_statistic(2, 1, 0, "time_distrib", &mut rnd)
+            .unwrap();
+        assert!((3.0..=5.0).contains(&value));
+        assert!(matches!(
+            level_gen.get_statistic(2, 1, 1, "time_distrib", &mut rnd),
+            Err(Error::NoStatistic { .. })
+        ));
+        assert_eq!(
+            level_gen.get_statistic_or(2, 1, 1, "time_distrib", 7.0, &mut rnd),
+            7.0
+        );
+    }
+}
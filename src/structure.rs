@@ -0,0 +1,338 @@
+use crate::error::{Error, Result};
+use crate::instance::InstDagVertex;
+use crate::pure_dag::PureTaskInfo;
+use crate::task_dag::DagVertex;
+use crate::wfcommons::REFERENCE_BANDWIDTH;
+use petgraph::algo::toposort;
+use petgraph::stable_graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+use petgraph::Direction::{Incoming, Outgoing};
+use petgraph::{Directed, Graph};
+
+// computation cost of vertex or communication cost of edge
+pub trait Cost {
+    fn cost(&self) -> f64;
+}
+
+impl Cost for DagVertex {
+    fn cost(&self) -> f64 {
+        return self.flops;
+    }
+}
+
+impl Cost for InstDagVertex {
+    fn cost(&self) -> f64 {
+        return self.flops;
+    }
+}
+
+// pure dags have only real instance times
+impl Cost for PureTaskInfo {
+    fn cost(&self) -> f64 {
+        if self.instances.is_empty() {
+            return 0.0;
+        }
+        return self.instances.iter().map(|x| x.time as f64).sum::<f64>()
+            / self.instances.len() as f64;
+    }
+}
+
+// task dag edges are data sizes in bytes
+impl Cost for u64 {
+    fn cost(&self) -> f64 {
+        return *self as f64;
+    }
+}
+
+impl Cost for f64 {
+    fn cost(&self) -> f64 {
+        return *self;
+    }
+}
+
+// Both are communication to computation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CcrDefinition {
+    // average edge cost to average vertex cost
+    Average,
+    // all edge costs to all vertex costs
+    Total,
+}
+
+pub const ALL_CCR_DEFINITIONS: [CcrDefinition; 2] = [CcrDefinition::Average, CcrDefinition::Total];
+
+impl CcrDefinition {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            CcrDefinition::Average => "average",
+            CcrDefinition::Total => "total",
+        };
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        return ALL_CCR_DEFINITIONS
+            .iter()
+            .find(|x| x.name() == name)
+            .copied();
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DagShape {
+    // by CcrDefinition::Average
+    pub ccr: f64,
+    // alpha of Topcuoglu generator: sqrt(nodes) / height
+    pub parallelism: f64,
+    // narrowest level to widest one, like regular of daggen
+    pub regularity: f64,
+}
+
+// Level of vertex is the longest path from a source in edges, sources are on level 0
+pub trait StructureMetrics {
+    fn levels(&self) -> Result<Vec<usize>>;
+    fn width_profile(&self) -> Result<Vec<usize>>;
+    // in vertices
+    fn longest_path(&self) -> Result<usize>;
+    // in vertices, over all paths from a source to a sink
+    fn average_path_length(&self) -> Result<f64>;
+    fn sources(&self) -> usize;
+    fn sinks(&self) -> usize;
+    fn transitive_reduction_edges(&self) -> Result<usize>;
+    // fraction of edges going over more than one level
+    fn jump_edge_ratio(&self) -> Result<f64>;
+    fn fan_in_gini(&self) -> f64;
+    fn fan_out_gini(&self) -> f64;
+    fn ccr(&self, definition: CcrDefinition) -> f64;
+    fn shape(&self) -> Result<DagShape>;
+}
+
+fn gini(values: Vec<usize>) -> f64 {
+    let mut values = values;
+    values.sort();
+    let n = values.len() as f64;
+    let sum = values.iter().sum::<usize>() as f64;
+    if sum == 0.0 {
+        return 0.0;
+    }
+    let weighted = values
+        .iter()
+        .enumerate()
+        .map(|(ind, x)| (2.0 * (ind + 1) as f64 - n - 1.0) * *x as f64)
+        .sum::<f64>();
+    return weighted / (n * sum);
+}
+
+impl<N: Cost, E: Cost> StructureMetrics for Graph<N, E, Directed> {
+    fn levels(&self) -> Result<Vec<usize>> {
+        let order = toposort(self, None).map_err(|_| Error::Cycle)?;
+        let mut levels = vec![0; self.node_count()];
+        for node_ind in order.iter() {
+            for child in self.neighbors_directed(*node_ind, Outgoing) {
+                levels[child.index()] = levels[child.index()].max(levels[node_ind.index()] + 1);
+            }
+        }
+        return Ok(levels);
+    }
+
+    fn width_profile(&self) -> Result<Vec<usize>> {
+        let levels = self.levels()?;
+        let mut result = vec![0; levels.iter().max().map(|x| x + 1).unwrap_or(0)];
+        for level in levels {
+            result[level] += 1;
+        }
+        return Ok(result);
+    }
+
+    fn longest_path(&self) -> Result<usize> {
+        return Ok(self.width_profile()?.len());
+    }
+
+    fn average_path_length(&self) -> Result<f64> {
+        let order = toposort(self, None).map_err(|_| Error::Cycle)?;
+        // number of paths from sources to vertex and sum of their lengths
+        let mut paths = vec![0.0; self.node_count()];
+        let mut lengths = vec![0.0; self.node_count()];
+        let mut all_paths = 0.0;
+        let mut all_lengths = 0.0;
+        for node_ind in order.iter() {
+            let ind = node_ind.index();
+            for parent in self.neighbors_directed(*node_ind, Incoming) {
+                paths[ind] += paths[parent.index()];
+                lengths[ind] += lengths[parent.index()];
+            }
+            if paths[ind] == 0.0 {
+                paths[ind] = 1.0;
+            }
+            lengths[ind] += paths[ind];
+            if self
+                .neighbors_directed(*node_ind, Outgoing)
+                .next()
+                .is_none()
+            {
+                all_paths += paths[ind];
+                all_lengths += lengths[ind];
+            }
+        }
+        if all_paths == 0.0 {
+            return Ok(0.0);
+        }
+        return Ok(all_lengths / all_paths);
+    }
+
+    fn sources(&self) -> usize {
+        return self
+            .node_indices()
+            .filter(|x| self.neighbors_directed(*x, Incoming).next().is_none())
+            .count();
+    }
+
+    fn sinks(&self) -> usize {
+        return self
+            .node_indices()
+            .filter(|x| self.neighbors_directed(*x, Outgoing).next().is_none())
+            .count();
+    }
+
+    // edge u -> v stays if v is not reachable from another child of u
+    fn transitive_reduction_edges(&self) -> Result<usize> {
+        let order = toposort(self, None).map_err(|_| Error::Cycle)?;
+        let words = (self.node_count() + 63) / 64;
+        let mut reachable = vec![vec![0u64; words]; self.node_count()];
+        let mut result = 0;
+        for node_ind in order.iter().rev() {
+            let children: Vec<NodeIndex> = self.neighbors_directed(*node_ind, Outgoing).collect();
+            let mut row = vec![0u64; words];
+            for child in children.iter() {
+                for (word, child_word) in row.iter_mut().zip(reachable[child.index()].iter()) {
+                    *word |= child_word;
+                }
+            }
+            // row has descendants of children, so it has v only if v is reached indirectly
+            let mut kept = children.clone();
+            kept.sort();
+            kept.dedup();
+            result += kept
+                .iter()
+                .filter(|x| row[x.index() / 64] & (1 << (x.index() % 64)) == 0)
+                .count();
+            for child in children.iter() {
+                row[child.index() / 64] |= 1 << (child.index() % 64);
+            }
+            reachable[node_ind.index()] = row;
+        }
+        return Ok(result);
+    }
+
+    fn jump_edge_ratio(&self) -> Result<f64> {
+        if self.edge_count() == 0 {
+            return Ok(0.0);
+        }
+        let levels = self.levels()?;
+        let jumps = self
+            .edge_references()
+            .filter(|x| levels[x.target().index()] > levels[x.source().index()] + 1)
+            .count();
+        return Ok(jumps as f64 / self.edge_count() as f64);
+    }
+
+    fn fan_in_gini(&self) -> f64 {
+        return gini(
+            self.node_indices()
+                .map(|x| self.neighbors_directed(x, Incoming).count())
+                .collect(),
+        );
+    }
+
+    fn fan_out_gini(&self) -> f64 {
+        return gini(
+            self.node_indices()
+                .map(|x| self.neighbors_directed(x, Outgoing).count())
+                .collect(),
+        );
+    }
+
+    // edges are bytes, they are sent with reference bandwidth to be in seconds like vertices
+    fn ccr(&self, definition: CcrDefinition) -> f64 {
+        let comp = self.node_weights().map(|x| x.cost()).sum::<f64>();
+        let comm = self.edge_weights().map(|x| x.cost()).sum::<f64>() / REFERENCE_BANDWIDTH;
+        let (comp, comm) = match definition {
+            CcrDefinition::Average => (
+                comp / self.node_count().max(1) as f64,
+                comm / self.edge_count().max(1) as f64,
+            ),
+            CcrDefinition::Total => (comp, comm),
+        };
+        if comp == 0.0 {
+            return 0.0;
+        }
+        return comm / comp;
+    }
+
+    fn shape(&self) -> Result<DagShape> {
+        let widths = self.width_profile()?;
+        let node_cnt = self.node_count() as f64;
+        let max_width = widths.iter().max().copied().unwrap_or(0);
+        let min_width = widths.iter().min().copied().unwrap_or(0);
+        return Ok(DagShape {
+            ccr: self.ccr(CcrDefinition::Average),
+            parallelism: if widths.is_empty() {
+                0.0
+            } else {
+                node_cnt.sqrt() / widths.len() as f64
+            },
+            regularity: if max_width == 0 {
+                0.0
+            } else {
+                min_width as f64 / max_width as f64
+            },
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0 -> 1, 2 -> 3 -> 4 and the jump 0 -> 3, every edge is half a second at reference bandwidth
+    fn diamond_with_jump() -> Graph<f64, f64, Directed> {
+        let mut dag = Graph::new();
+        let nodes: Vec<NodeIndex> = [1.0, 2.0, 2.0, 1.0, 1.0]
+            .iter()
+            .map(|x| dag.add_node(*x))
+            .collect();
+        for (parent, child) in [(0, 1), (0, 2), (1, 3), (2, 3), (0, 3), (3, 4)] {
+            dag.add_edge(nodes[parent], nodes[child], 0.5 * REFERENCE_BANDWIDTH);
+        }
+        return dag;
+    }
+
+    #[test]
+    fn metrics_by_hand() {
+        let dag = diamond_with_jump();
+        assert_eq!(dag.levels().unwrap(), vec![0, 1, 1, 2, 3]);
+        assert_eq!(dag.width_profile().unwrap(), vec![1, 2, 1, 1]);
+        assert_eq!(dag.longest_path().unwrap(), 4);
+        // 0-1-3-4, 0-2-3-4 and 0-3-4
+        assert!((dag.average_path_length().unwrap() - 11.0 / 3.0).abs() < 1e-9);
+        assert_eq!((dag.sources(), dag.sinks()), (1, 1));
+        // 0 -> 3 is implied by 0 -> 1 -> 3
+        assert_eq!(dag.transitive_reduction_edges().unwrap(), 5);
+        assert!((dag.jump_edge_ratio().unwrap() - 1.0 / 6.0).abs() < 1e-9);
+        // fan in 0, 1, 1, 3, 1 and fan out 3, 1, 1, 1, 0: 12 / (5 * 6)
+        assert!((dag.fan_in_gini() - 0.4).abs() < 1e-9);
+        assert!((dag.fan_out_gini() - 0.4).abs() < 1e-9);
+        // 3 seconds of sending to 7 of work, per edge 0.5 to 1.4 per vertex
+        assert!((dag.ccr(CcrDefinition::Total) - 3.0 / 7.0).abs() < 1e-9);
+        assert!((dag.ccr(CcrDefinition::Average) - 5.0 / 14.0).abs() < 1e-9);
+        let shape = dag.shape().unwrap();
+        assert!((shape.parallelism - 5.0f64.sqrt() / 4.0).abs() < 1e-9);
+        assert!((shape.regularity - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn gini_of_even_and_skewed() {
+        assert_eq!(gini(vec![2, 2, 2]), 0.0);
+        assert_eq!(gini(vec![0, 0, 0]), 0.0);
+        assert!((gini(vec![0, 0, 0, 4]) - 0.75).abs() < 1e-9);
+    }
+}
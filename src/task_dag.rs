@@ -1,17 +1,18 @@
+use crate::dependency::{dependency_patterns, fit_pattern, DependencyPattern};
+use crate::dispersion::Dispersion;
+use crate::edge_data::data_size;
+use crate::error::{read_file, write_file, Error, Result};
 use crate::instance::{AddEdge, InstDagVertex, InstanceDag};
-use rand::rngs::ThreadRng;
 use rand::Rng;
 
-use crate::pure_dag::PureDag;
+use crate::pure_dag::{true_node_order, PureDag};
+use crate::structure::{CcrDefinition, StructureMetrics};
 use petgraph::stable_graph::NodeIndex;
 
 use petgraph::visit::EdgeRef;
 use petgraph::{Directed, Graph};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::{Read, Write};
-use std::path::Path;
+use std::collections::BTreeMap;
 
 #[derive(Serialize, Deserialize)]
 pub struct DagVertex {
@@ -19,177 +20,311 @@ pub struct DagVertex {
     pub dependencies: Vec<u32>,
     pub instance_cnt: u64,
     pub flops: f64,
+    // demand of every instance: cpu in percents of one core, mem in percents of machine memory
+    #[serde(default = "default_cpu")]
+    pub cpu: f64,
+    #[serde(default = "default_mem")]
+    pub mem: f64,
+    // flops are work of a typical instance, others are spread around it
+    #[serde(default)]
+    pub dispersion: Dispersion,
+    // parent -> how its instances feed instances of this task, inferred if missing
+    #[serde(default)]
+    pub patterns: BTreeMap<u32, DependencyPattern>,
 }
 
+// one core and the memory every task had before demands were learned
+pub const DEFAULT_CPU: f64 = 100.0;
+pub const DEFAULT_MEM: f64 = 1.0;
+
+fn default_cpu() -> f64 {
+    return DEFAULT_CPU;
+}
+
+fn default_mem() -> f64 {
+    return DEFAULT_MEM;
+}
+
+// whole cores for formats without fractions, at least one
+pub fn cores(cpu: f64) -> u64 {
+    return ((cpu / 100.0).ceil() as u64).max(1);
+}
+
+// edges are bytes child task reads from parent task
 pub type TaskDag = Graph<DagVertex, u64, Directed>;
 
 pub const MAX_INST_CNT: u64 = 20;
 
+// instance dag has at most this number of instances per task
+pub const INSTANCE_CAP: u64 = 40;
+
+// exporters write every edge as at least this
+pub const MIN_EDGE_WEIGHT: f64 = 1.0;
+// relative error of realised ccr that calibration accepts
+pub const CCR_TOLERANCE: f64 = 0.01;
+const CALIBRATION_STEPS: usize = 64;
+
+#[derive(Clone, Copy, Debug)]
+pub struct CcrTarget {
+    pub value: f64,
+    pub definition: CcrDefinition,
+}
+
+impl CcrTarget {
+    pub fn is_reached(&self, ccr: f64) -> bool {
+        return (ccr - self.value).abs() <= CCR_TOLERANCE * self.value;
+    }
+}
+
+// All edges are scaled by one factor and clamped to MIN_EDGE_WEIGHT. Without clamping ccr
+// is linear in the factor, clamping only raises it, so the factor is lowered by bisection
+// until ccr is in tolerance. Targets below ccr of all clamped edges end up there
+fn calibrate_ccr(instance_dag: &mut InstanceDag, target: CcrTarget) {
+    let initial = instance_dag.ccr(target.definition);
+    if initial <= 0.0 || target.value <= 0.0 {
+        return;
+    }
+    let weights: Vec<f64> = instance_dag.edge_weights().copied().collect();
+    let mut ccr_with = |factor: f64| -> f64 {
+        for (weight, base) in instance_dag.edge_weights_mut().zip(weights.iter()) {
+            *weight = (base * factor).max(MIN_EDGE_WEIGHT);
+        }
+        return instance_dag.ccr(target.definition);
+    };
+    let mut low = 0.0;
+    let mut high = target.value / initial;
+    let mut factor = high;
+    for _ in 0..CALIBRATION_STEPS {
+        let ccr = ccr_with(factor);
+        if target.is_reached(ccr) {
+            break;
+        }
+        if ccr > target.value {
+            high = factor;
+        } else {
+            low = factor;
+        }
+        factor = (low + high) / 2.0;
+    }
+}
+
 pub trait TaskDagFuncs {
-    fn from_pure_dag(pure_dag: &PureDag) -> TaskDag;
-    fn convert_to_inst_dag(&self, rnd: &mut ThreadRng, ccr: f64) -> InstanceDag;
-    fn save_to_file(&self, file_name: &str);
-    fn load_from_file(&mut self, file_name: &str);
+    fn from_pure_dag(pure_dag: &PureDag) -> Result<TaskDag>;
+    fn convert_to_inst_dag<R: Rng>(&self, rnd: &mut R, ccr: Option<CcrTarget>) -> InstanceDag;
+    fn save_to_file(&self, file_name: &str) -> Result<()>;
+    fn load_from_file(&mut self, file_name: &str) -> Result<()>;
+    fn check_dependencies(&self) -> Result<()>;
     fn add_task_endge(&mut self, child_ind: NodeIndex, parent_ind: NodeIndex);
 }
 
 impl TaskDagFuncs for TaskDag {
-    fn from_pure_dag(pure_dag: &PureDag) -> TaskDag {
+    fn from_pure_dag(pure_dag: &PureDag) -> Result<TaskDag> {
         let mut result = TaskDag::new();
 
-        let mut true_order = vec![NodeIndex::new(0); pure_dag.node_count()];
-
-        let mut map_orders = HashMap::<NodeIndex, usize>::new();
-        for node_ind in pure_dag.node_indices() {
-            let node_info = pure_dag.node_weight(node_ind).unwrap();
-            let task_name: &str = node_info.name.as_str();
-            let number = &task_name[4..task_name.len()];
-            let number = match number.parse::<usize>() {
-                Ok(number) => number,
-                Err(_) => 0,
+        let (true_order, map_orders) =
+            true_node_order(pure_dag.node_weights().map(|x| x.name.as_str()).collect())?;
+        for node_ind in true_order.iter() {
+            let node_info = pure_dag.node_weight(*node_ind).unwrap();
+            let instances = node_info.instances.len() as f64;
+            let (cpu, mem) = if node_info.instances.is_empty() {
+                (DEFAULT_CPU, DEFAULT_MEM)
+            } else {
+                (
+                    node_info.instances.iter().map(|x| x.cpu_avg).sum::<f64>() / instances,
+                    node_info.instances.iter().map(|x| x.mem_avg).sum::<f64>() / instances,
+                )
             };
 
-            true_order[number - 1] = node_ind;
-            map_orders.insert(node_ind, number - 1);
-        }
-        for node_ind in true_order {
-            let node_info = pure_dag.node_weight(node_ind).unwrap();
-
             result.add_node(DagVertex {
                 task_name: node_info.name.clone(),
                 dependencies: node_info.dependences.clone(),
                 instance_cnt: node_info.instance_cnt.min(MAX_INST_CNT),
                 flops: (node_info.end_time - node_info.start_time) as f64,
+                cpu: cpu,
+                mem: mem,
+                dispersion: Dispersion::from_times(
+                    &node_info
+                        .instances
+                        .iter()
+                        .map(|x| x.time as f64)
+                        .collect::<Vec<f64>>(),
+                )
+                .unwrap_or_default(),
+                patterns: BTreeMap::new(),
             });
         }
 
         for edge in pure_dag.edge_references() {
-            result.add_edge(
-                NodeIndex::new(map_orders[&edge.source()]),
-                NodeIndex::new(map_orders[&edge.target()]),
-                *edge.weight(),
-            );
+            let parent = NodeIndex::new(map_orders[&edge.source()]);
+            let size = data_size(*edge.weight(), result.node_weight(parent).unwrap());
+            result.add_edge(parent, NodeIndex::new(map_orders[&edge.target()]), size);
         }
-        return result;
+        result.check_dependencies()?;
+
+        for node_ind in result.node_indices() {
+            let node_info = result.node_weight(node_ind).unwrap();
+            let child_start = pure_dag
+                .node_weight(true_order[node_ind.index()])
+                .unwrap()
+                .start_time;
+            let parents: Vec<NodeIndex> = node_info
+                .dependencies
+                .iter()
+                .map(|x| NodeIndex::new(*x as usize))
+                .collect();
+            let parent_cnts: Vec<u64> = parents
+                .iter()
+                .map(|x| {
+                    result
+                        .node_weight(*x)
+                        .unwrap()
+                        .instance_cnt
+                        .min(INSTANCE_CAP)
+                })
+                .collect();
+            let sizes: Vec<f64> = parents
+                .iter()
+                .map(|x| match result.find_edge(*x, node_ind) {
+                    Some(edge) => result[edge] as f64,
+                    None => 1.0,
+                })
+                .collect();
+            let input = sizes.iter().sum::<f64>();
+            let mut patterns = BTreeMap::new();
+            for (ind, parent) in parents.iter().enumerate() {
+                let parent_end = pure_dag
+                    .node_weight(true_order[parent.index()])
+                    .unwrap()
+                    .end_time;
+                // pure dags link tasks only
+                let pattern = fit_pattern(
+                    parent_cnts[ind],
+                    node_info.instance_cnt.min(INSTANCE_CAP),
+                    &parent_cnts,
+                    &[],
+                    sizes[ind] / input,
+                    child_start < parent_end,
+                );
+                patterns.insert(parent.index() as u32, pattern);
+            }
+            result.node_weight_mut(node_ind).unwrap().patterns = patterns;
+        }
+        return Ok(result);
     }
 
-    fn convert_to_inst_dag(&self, rnd: &mut ThreadRng, ccr: f64) -> InstanceDag {
+    // Instance edges share data size of task edge as dependency pattern of the edge splits it.
+    // With ccr edges are calibrated
+    fn convert_to_inst_dag<R: Rng>(&self, rnd: &mut R, ccr: Option<CcrTarget>) -> InstanceDag {
         let mut instance_dag = InstanceDag::new();
         let mut global_counter: usize = 0;
         let node_cnt = self.node_count();
         let mut start_task_index: Vec<usize> = vec![0; node_cnt];
-        let mut vector_edge: Vec<f64> = vec![0.0; node_cnt];
 
         for node_ind in self.node_indices() {
             let node_info = self.node_weight(node_ind).unwrap();
             // gen instance with flops
             let flops_sz = node_info.flops;
             start_task_index[node_ind.index()] = global_counter;
-            for i in 0..node_info.instance_cnt.min(40) {
+            for i in 0..node_info.instance_cnt.min(INSTANCE_CAP) {
+                let factor = if node_info.dispersion.is_uniform() {
+                    1.0
+                } else {
+                    node_info.dispersion.sample_factor(rnd)
+                };
                 instance_dag.add_node(InstDagVertex {
                     inst_name: format!("{}_{}", node_info.task_name, i).to_string(),
                     dependencies: Vec::new(),
-                    flops: flops_sz,
+                    flops: flops_sz * factor,
+                    cpu: node_info.cpu,
+                    mem: node_info.mem,
                 });
                 global_counter += 1;
             }
-            vector_edge[node_ind.index()] = flops_sz / ccr * rnd.gen_range(0.9..1.1);
         }
 
         for node_ind in self.node_indices() {
             let node_info = self.node_weight(node_ind).unwrap();
-            let mut parent_ins_sum = 0;
-            for parent in node_info.dependencies.iter() {
-                parent_ins_sum += self
-                    .node_weight(NodeIndex::new(*parent as usize))
-                    .unwrap()
-                    .instance_cnt
-                    .min(40);
-            }
-
-            if parent_ins_sum == node_info.instance_cnt.min(40) {
-                // case union
-                let mut node_ind_sl = start_task_index[node_ind.index()];
-
-                for parent in node_info.dependencies.iter() {
-                    let parent = NodeIndex::new(*parent as usize);
-                    let parent_info = self.node_weight(parent).unwrap();
-                    let mut parent_ind_sl = start_task_index[parent.index()];
-                    for _ in 0..parent_info.instance_cnt.min(40) {
-                        instance_dag.add_ins_edge(
-                            parent_ind_sl,
-                            node_ind_sl,
-                            vector_edge[parent.index()],
-                        );
-                        (node_ind_sl, parent_ind_sl) = (node_ind_sl + 1, parent_ind_sl + 1);
-                    }
+            let node_cnt = node_info.instance_cnt.min(INSTANCE_CAP) as usize;
+            // union fills instances of node one after another
+            let mut union_offset = 0;
+
+            for (parent, pattern) in dependency_patterns(self, node_ind) {
+                let parent_info = self.node_weight(parent).unwrap();
+                let data_size = match self.find_edge(parent, node_ind) {
+                    Some(edge) => self[edge] as f64,
+                    None => 1.0,
+                };
+                let parent_cnt = parent_info.instance_cnt.min(INSTANCE_CAP) as usize;
+                for (parent_sl, node_sl, share) in
+                    pattern.links(parent_cnt, node_cnt, union_offset, rnd)
+                {
+                    instance_dag.add_ins_edge(
+                        start_task_index[parent.index()] + parent_sl,
+                        start_task_index[node_ind.index()] + node_sl,
+                        data_size * share,
+                    );
                 }
-            } else {
-                for parent in node_info.dependencies.iter() {
-                    let parent = NodeIndex::new(*parent as usize);
-
-                    let parent_info = self.node_weight(parent).unwrap();
-                    let parent_edge_out = vector_edge[parent.index()];
-
-                    if parent_info.instance_cnt.min(40) == node_info.instance_cnt.min(40) {
-                        // case map, filter
-                        let mut parent_ind_sl = start_task_index[parent.index()];
-                        let mut node_ind_sl = start_task_index[node_ind.index()];
-
-                        for _ in 0..parent_info.instance_cnt.min(40) {
-                            instance_dag.add_ins_edge(parent_ind_sl, node_ind_sl, parent_edge_out);
-                            (node_ind_sl, parent_ind_sl) = (node_ind_sl + 1, parent_ind_sl + 1);
-                        }
-                    } else {
-                        // groupByKey
-                        for parent_ind_sl in (0..parent_info.instance_cnt.min(40))
-                            .map(|x| start_task_index[parent.index()] + x as usize)
-                        {
-                            for node_ind_sl in (0..node_info.instance_cnt.min(40))
-                                .map(|x| start_task_index[node_ind.index()] + x as usize)
-                            {
-                                instance_dag.add_ins_edge(
-                                    parent_ind_sl,
-                                    node_ind_sl,
-                                    parent_edge_out,
-                                );
-                            }
-                        }
-                    }
+                if pattern == DependencyPattern::Union {
+                    union_offset += parent_cnt;
                 }
             }
         }
+        if let Some(ccr) = ccr {
+            calibrate_ccr(&mut instance_dag, ccr);
+        }
         return instance_dag;
     }
 
-    fn save_to_file(&self, file_name: &str) {
-        let j = serde_json::to_string(&self).unwrap();
-
-        let path = Path::new(file_name);
-        let mut file = match File::create(&path) {
-            Err(why) => panic!("cant open file to write {}", why),
-            Ok(file) => file,
-        };
+    fn save_to_file(&self, file_name: &str) -> Result<()> {
+        let j = serde_json::to_string(&self).map_err(|why| Error::json(file_name, why))?;
+        return write_file(file_name, &j);
+    }
 
-        match file.write_all(j.as_bytes()) {
-            Err(why) => panic!("cant save serialization {}", why),
-            Ok(_) => {}
-        }
+    fn load_from_file(&mut self, file_name: &str) -> Result<()> {
+        let contents = read_file(file_name)?;
+        *self = serde_json::from_str(&contents).map_err(|why| Error::json(file_name, why))?;
+        return self.check_dependencies();
     }
 
-    fn load_from_file(&mut self, file_name: &str) {
-        let path = Path::new(file_name);
-        let mut file = match File::open(&path) {
-            Err(why) => panic!("cant open file to write {}", why),
-            Ok(file) => file,
-        };
-        let mut contents = String::new();
-        match file.read_to_string(&mut contents) {
-            Err(why) => panic!("couldn't read: {}", why),
-            Ok(_) => {}
+    // convert_to_inst_dag relies on dependencies pointing to existing nodes and on patterns
+    // given for them fitting instance counts
+    fn check_dependencies(&self) -> Result<()> {
+        for node_info in self.node_weights() {
+            for parent in node_info.dependencies.iter() {
+                if *parent as usize >= self.node_count() {
+                    return Err(Error::Task {
+                        task: node_info.task_name.clone(),
+                        reason: format!("dependence {} is out of graph", parent),
+                    });
+                }
+            }
+            let several_parents = node_info.dependencies.len() > 1;
+            for (parent, pattern) in node_info.patterns.iter() {
+                if !node_info.dependencies.contains(parent) {
+                    return Err(Error::Config(format!(
+                        "task {}: {} is given for {} which is not its dependence",
+                        node_info.task_name,
+                        pattern.name(),
+                        parent
+                    )));
+                }
+                let parent_cnt = self
+                    .node_weight(NodeIndex::new(*parent as usize))
+                    .unwrap()
+                    .instance_cnt;
+                if !pattern.fits_edge(parent_cnt, node_info.instance_cnt, several_parents) {
+                    return Err(Error::Config(format!(
+                        "task {}: {} does not fit edge from {} with {} to {} instances",
+                        node_info.task_name,
+                        pattern.name(),
+                        parent,
+                        parent_cnt,
+                        node_info.instance_cnt
+                    )));
+                }
+            }
         }
-
-        *self = serde_json::from_str(&contents).unwrap();
+        return Ok(());
     }
 
     fn add_task_endge(self: &mut TaskDag, child_ind: NodeIndex, parent_ind: NodeIndex) {
@@ -202,13 +337,12 @@ impl TaskDagFuncs for TaskDag {
 }
 
 pub trait SaveToFormatStructured {
-    fn save_to_dot(&self, filename: &str);
+    fn save_to_dot(&self, filename: &str) -> Result<()>;
 }
 use colors_transform::Rgb;
 
 impl SaveToFormatStructured for TaskDag {
-    fn save_to_dot(&self, filename: &str) {
-        let mut f = File::create(filename).unwrap();
+    fn save_to_dot(&self, filename: &str) -> Result<()> {
         let mut output = format!("digraph {{\n",);
 
         for node_ind in self.node_indices() {
@@ -243,7 +377,7 @@ impl SaveToFormatStructured for TaskDag {
             }
         }
         output.push_str("}\n");
-        f.write_all(&output.as_bytes()).unwrap();
+        return write_file(filename, &output);
     }
 }
 
@@ -254,6 +388,7 @@ pub trait FeatureCount {
     fn chain_ratio(&self) -> f64;
     fn pairwise_ins_ration(&self) -> Vec<f64>;
     fn pairwise_flops_ration(&self) -> Vec<f64>;
+    fn dependency_ratio<F: Fn(DependencyPattern) -> bool>(&self, matches: F) -> f64;
 }
 
 impl FeatureCount for TaskDag {
@@ -313,4 +448,141 @@ impl FeatureCount for TaskDag {
         }
         return result;
     }
+
+    // fraction of task edges of any of the kinds
+    fn dependency_ratio<F: Fn(DependencyPattern) -> bool>(&self, matches: F) -> f64 {
+        let mut matched: u32 = 0;
+        let mut total: u32 = 0;
+        for node_id in self.node_indices() {
+            for (_, pattern) in dependency_patterns(self, node_id) {
+                total += 1;
+                if matches(pattern) {
+                    matched += 1;
+                }
+            }
+        }
+        if total == 0 {
+            return 0.0;
+        }
+        return matched as f64 / total as f64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(name: &str, instance_cnt: u64) -> DagVertex {
+        return DagVertex {
+            task_name: name.to_string(),
+            dependencies: Vec::new(),
+            instance_cnt: instance_cnt,
+            flops: 10.0,
+            cpu: DEFAULT_CPU,
+            mem: DEFAULT_MEM,
+            dispersion: Dispersion::default(),
+            patterns: BTreeMap::new(),
+        };
+    }
+
+    // a, b -> c is union, c -> d one_to_one, c -> e all_to_all, d -> f range_partition as given
+    fn patterned_dag() -> TaskDag {
+        let mut dag = TaskDag::new();
+        for (name, instance_cnt) in [("a", 2), ("b", 2), ("c", 4), ("d", 4), ("e", 1), ("f", 2)] {
+            dag.add_node(task(name, instance_cnt));
+        }
+        for (parent, child) in [(0, 2), (1, 2), (2, 3), (2, 4), (3, 5)] {
+            dag.add_task_endge(NodeIndex::new(child), NodeIndex::new(parent));
+        }
+        dag.node_weight_mut(NodeIndex::new(5))
+            .unwrap()
+            .patterns
+            .insert(3, DependencyPattern::RangePartition);
+        return dag;
+    }
+
+    #[test]
+    fn dependency_ratios_by_hand() {
+        let dag = patterned_dag();
+        assert_eq!(dag.dependency_ratio(|x| !x.is_wide()), 3.0 / 5.0);
+        assert_eq!(
+            dag.dependency_ratio(|x| x == DependencyPattern::Union),
+            2.0 / 5.0
+        );
+        assert_eq!(dag.dependency_ratio(|x| x.is_wide()), 2.0 / 5.0);
+        assert_eq!(TaskDag::new().dependency_ratio(|x| x.is_wide()), 0.0);
+    }
+
+    // edges from a byte to seconds at reference bandwidth, small ones get clamped
+    fn calibration_dag() -> InstanceDag {
+        let mut dag = InstanceDag::new();
+        for flops in [1.0, 2.0, 3.0, 4.0, 5.0] {
+            dag.add_node(InstDagVertex {
+                inst_name: String::new(),
+                dependencies: Vec::new(),
+                flops: flops,
+                cpu: DEFAULT_CPU,
+                mem: DEFAULT_MEM,
+            });
+        }
+        for (parent, child, weight) in [
+            (0, 1, 1.0),
+            (0, 2, 1e3),
+            (1, 3, 1e8),
+            (2, 3, 2e9),
+            (3, 4, 5e9),
+        ] {
+            dag.add_ins_edge(parent, child, weight);
+        }
+        return dag;
+    }
+
+    #[test]
+    fn calibration_reaches_target() {
+        for definition in [CcrDefinition::Average, CcrDefinition::Total] {
+            for value in [0.05, 0.5, 1.0, 5.0] {
+                let target = CcrTarget {
+                    value: value,
+                    definition: definition,
+                };
+                let mut dag = calibration_dag();
+                calibrate_ccr(&mut dag, target);
+                let ccr = dag.ccr(definition);
+                assert!(target.is_reached(ccr), "{:?} gives {}", target, ccr);
+                assert!(dag.edge_weights().all(|x| *x >= MIN_EDGE_WEIGHT));
+            }
+        }
+    }
+
+    #[test]
+    fn patterns_not_fitting_edge_are_rejected() {
+        let filename = format!(
+            "{}/cursach_bad_patterns.json",
+            std::env::temp_dir().display()
+        );
+        let mut loaded = TaskDag::new();
+        patterned_dag().save_to_file(&filename).unwrap();
+        assert!(loaded.load_from_file(&filename).is_ok());
+
+        // c -> e has 4 and 1 instances, e has no other parents and a is not one
+        for (child, parent, pattern) in [
+            (4, 2, DependencyPattern::OneToOne),
+            (4, 2, DependencyPattern::Union),
+            (4, 0, DependencyPattern::AllToAll),
+        ] {
+            let mut dag = patterned_dag();
+            dag.node_weight_mut(NodeIndex::new(child))
+                .unwrap()
+                .patterns
+                .insert(parent, pattern);
+            dag.save_to_file(&filename).unwrap();
+            assert!(
+                matches!(loaded.load_from_file(&filename), Err(Error::Config(_))),
+                "{} from {} is loaded",
+                pattern.name(),
+                parent
+            );
+        }
+        std::fs::remove_file(&filename).unwrap();
+    }
 }
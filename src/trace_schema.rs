@@ -0,0 +1,103 @@
+use crate::error::{read_file, Error, Result};
+use serde::{Deserialize, Serialize};
+
+// column numbers in batch_task file
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TaskColumns {
+    pub task_name: usize,
+    pub instance_cnt: usize,
+    pub job: usize,
+    pub status: usize,
+    pub start_time: usize,
+    pub end_time: usize,
+}
+
+// column numbers in batch_instance file
+#[derive(Serialize, Deserialize, Clone)]
+pub struct InstanceColumns {
+    pub task_name: usize,
+    pub job: usize,
+    pub status: usize,
+    pub start_time: usize,
+    pub end_time: usize,
+    pub cpu_avg: usize,
+    pub cpu_max: usize,
+    pub mem_avg: usize,
+    pub mem_max: usize,
+}
+
+// Describes layout of trace csv files, default one is Alibaba cluster-trace-v2018
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TraceSchema {
+    pub has_header: bool,
+    pub task: TaskColumns,
+    pub instance: InstanceColumns,
+    // rows with other statuses make the whole job unterminated
+    pub accepted_statuses: Vec<String>,
+    // jobs which are known to be broken
+    pub skip_jobs: Vec<String>,
+}
+
+impl TraceSchema {
+    pub fn alibaba() -> Self {
+        return TraceSchema {
+            has_header: false,
+            task: TaskColumns {
+                task_name: 0,
+                instance_cnt: 1,
+                job: 2,
+                status: 4,
+                start_time: 5,
+                end_time: 6,
+            },
+            instance: InstanceColumns {
+                task_name: 1,
+                job: 2,
+                status: 4,
+                start_time: 5,
+                end_time: 6,
+                cpu_avg: 10,
+                cpu_max: 11,
+                mem_avg: 12,
+                mem_max: 13,
+            },
+            accepted_statuses: vec![String::from("Terminated")],
+            // wrong task_name format
+            skip_jobs: [
+                "j_4015961",
+                "j_1127318",
+                "j_2583771",
+                "j_3734942",
+                "j_2212822",
+                "j_1465012",
+                "j_1053726",
+                "j_3061955",
+                "j_2123594",
+                "j_94055",
+                "j_2428957",
+                "j_2598590",
+                "j_1575128",
+            ]
+            .iter()
+            .map(|x| x.to_string())
+            .collect(),
+        };
+    }
+
+    pub fn load_from_file(filename: &str) -> Result<Self> {
+        let contents = read_file(filename)?;
+        let schema: TraceSchema =
+            serde_json::from_str(&contents).map_err(|why| Error::json(filename, why))?;
+        if schema.accepted_statuses.is_empty() {
+            return Err(Error::Config(format!(
+                "{}: accepted_statuses is empty, every job would be dropped",
+                filename
+            )));
+        }
+        return Ok(schema);
+    }
+
+    pub fn accepts(&self, status: &str) -> bool {
+        return self.accepted_statuses.iter().any(|x| x == status);
+    }
+}
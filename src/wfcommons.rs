@@ -0,0 +1,307 @@
+use crate::error::{read_dir_sorted, read_file, Error, Result};
+use crate::pure_dag::{Instance, PureDag, PureTaskInfo};
+use crate::source::{dag_from_parents, JobSink, TraceSource};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+struct WfTask {
+    key: String,
+    parents: Vec<String>,
+    runtime: f64,
+    cpu: f64,
+    mem: f64,
+    inputs: HashMap<String, u64>,
+    outputs: HashMap<String, u64>,
+}
+
+// Directory of WfCommons wfformat instances (Montage, Epigenomics, ...), one workflow is one job
+pub struct WfCommonsSource {
+    pub workflow_dir: String,
+}
+
+fn strings(value: Option<&Value>) -> Vec<String> {
+    return match value {
+        Some(Value::Array(items)) => items
+            .iter()
+            .filter_map(|x| x.as_str().map(|x| x.to_string()))
+            .collect(),
+        _ => Vec::new(),
+    };
+}
+
+fn number(value: &Value, keys: &[&str]) -> Option<f64> {
+    return keys
+        .iter()
+        .find_map(|key| value.get(key).and_then(|x| x.as_f64()));
+}
+
+// cpu in percents of one core like in Alibaba trace
+fn task_cpu(value: &Value) -> f64 {
+    if let Some(avg_cpu) = number(value, &["avgCPU"]) {
+        return avg_cpu;
+    }
+    return number(value, &["coreCount", "cores"]).unwrap_or(1.0) * 100.0;
+}
+
+// synthetic workflows are written as run on this machine, memory of tasks is relative to it
+pub const REFERENCE_MACHINE: &str = "reference";
+pub const REFERENCE_MACHINE_MEMORY: u64 = 64 << 30;
+// bytes per second between reference machines, 10 Gbit/s
+pub const REFERENCE_BANDWIDTH: f64 = 1.25e9;
+
+// bytes, schema 1.4 has kilobytes in memory field
+fn memory_bytes(value: &Value) -> Option<f64> {
+    if let Some(bytes) = number(value, &["memoryInBytes"]) {
+        return Some(bytes);
+    }
+    return number(value, &["memory"]).map(|x| x * 1024.0);
+}
+
+// memory of machines by node name
+fn machine_memory(machines: Option<&Value>) -> HashMap<String, f64> {
+    let mut result = HashMap::new();
+    if let Some(Value::Array(machines)) = machines {
+        for machine in machines.iter() {
+            let name = machine.get("nodeName").and_then(|x| x.as_str());
+            if let (Some(name), Some(bytes)) = (name, memory_bytes(machine)) {
+                result.insert(name.to_string(), bytes);
+            }
+        }
+    }
+    return result;
+}
+
+// mem in percents of machine memory like in Alibaba trace, 0 if task or machine has none
+fn task_mem(value: &Value, machines: &HashMap<String, f64>) -> f64 {
+    let machine = match value.get("machines") {
+        Some(Value::Array(names)) => names.first().and_then(|x| x.as_str()),
+        _ => value.get("machine").and_then(|x| x.as_str()),
+    };
+    return match (memory_bytes(value), machine.and_then(|x| machines.get(x))) {
+        (Some(bytes), Some(total)) if *total > 0.0 => bytes / total * 100.0,
+        _ => 0.0,
+    };
+}
+
+fn task_error(filename: &str, reason: &str) -> Error {
+    return Error::Config(format!("{}: {}", filename, reason));
+}
+
+// schema 1.4 and older: everything is in workflow.tasks, parents and files are referenced by name
+fn read_tasks_v14(filename: &str, workflow: &Value, tasks: &Vec<Value>) -> Result<Vec<WfTask>> {
+    let machines = machine_memory(workflow.get("machines"));
+    let mut result = Vec::new();
+    for task in tasks.iter() {
+        let key = match task.get("name").and_then(|x| x.as_str()) {
+            Some(name) => name.to_string(),
+            None => return Err(task_error(filename, "task without name")),
+        };
+        let mut inputs = HashMap::new();
+        let mut outputs = HashMap::new();
+        if let Some(Value::Array(files)) = task.get("files") {
+            for file in files.iter() {
+                let name = match file.get("name").and_then(|x| x.as_str()) {
+                    Some(name) => name.to_string(),
+                    None => continue,
+                };
+                let size = number(file, &["sizeInBytes", "size"]).unwrap_or(0.0) as u64;
+                match file.get("link").and_then(|x| x.as_str()) {
+                    Some("input") => inputs.insert(name, size),
+                    Some("output") => outputs.insert(name, size),
+                    _ => None,
+                };
+            }
+        }
+        result.push(WfTask {
+            key: key,
+            parents: strings(task.get("parents")),
+            runtime: number(task, &["runtimeInSeconds", "runtime"]).unwrap_or(0.0),
+            cpu: task_cpu(task),
+            mem: task_mem(task, &machines),
+            inputs: inputs,
+            outputs: outputs,
+        });
+    }
+    return Ok(result);
+}
+
+// schema 1.5: structure in workflow.specification, measurements in workflow.execution,
+// tasks and files are referenced by id
+fn read_tasks_v15(filename: &str, workflow: &Value) -> Result<Vec<WfTask>> {
+    let specification = &workflow["specification"];
+    let mut file_sizes = HashMap::<String, u64>::new();
+    if let Some(Value::Array(files)) = specification.get("files") {
+        for file in files.iter() {
+            if let Some(id) = file.get("id").and_then(|x| x.as_str()) {
+                let size = number(file, &["sizeInBytes"]).unwrap_or(0.0) as u64;
+                file_sizes.insert(id.to_string(), size);
+            }
+        }
+    }
+    let machines = machine_memory(workflow["execution"].get("machines"));
+    let mut execution = HashMap::<String, &Value>::new();
+    if let Some(Value::Array(tasks)) = workflow["execution"].get("tasks") {
+        for task in tasks.iter() {
+            if let Some(id) = task.get("id").and_then(|x| x.as_str()) {
+                execution.insert(id.to_string(), task);
+            }
+        }
+    }
+    let sized = |ids: Vec<String>| -> HashMap<String, u64> {
+        return ids
+            .into_iter()
+            .map(|id| {
+                let size = file_sizes.get(&id).copied().unwrap_or(0);
+                (id, size)
+            })
+            .collect();
+    };
+
+    let tasks = match specification.get("tasks") {
+        Some(Value::Array(tasks)) => tasks,
+        _ => return Err(task_error(filename, "no workflow.specification.tasks")),
+    };
+    let mut result = Vec::new();
+    for task in tasks.iter() {
+        let key = match task.get("id").and_then(|x| x.as_str()) {
+            Some(id) => id.to_string(),
+            None => return Err(task_error(filename, "task without id")),
+        };
+        let (runtime, cpu, mem) = match execution.get(&key) {
+            Some(measure) => (
+                number(measure, &["runtimeInSeconds"]).unwrap_or(0.0),
+                task_cpu(measure),
+                task_mem(measure, &machines),
+            ),
+            None => (0.0, 100.0, 0.0),
+        };
+        result.push(WfTask {
+            key: key,
+            parents: strings(task.get("parents")),
+            runtime: runtime,
+            cpu: cpu,
+            mem: mem,
+            inputs: sized(strings(task.get("inputFiles"))),
+            outputs: sized(strings(task.get("outputFiles"))),
+        });
+    }
+    return Ok(result);
+}
+
+// Every task becomes one instance. Tasks start as soon as all parents end,
+// edge weight is amount of bytes parent writes and child reads
+fn to_pure_dag(filename: &str, tasks: Vec<WfTask>) -> Result<PureDag> {
+    let position: HashMap<&str, usize> = tasks
+        .iter()
+        .enumerate()
+        .map(|(ind, task)| (task.key.as_str(), ind))
+        .collect();
+
+    let mut parents = Vec::new();
+    for task in tasks.iter() {
+        let mut task_parents: Vec<(usize, u64)> = Vec::new();
+        for parent in task.parents.iter() {
+            let parent_ind = match position.get(parent.as_str()) {
+                Some(parent_ind) => *parent_ind,
+                None => {
+                    return Err(Error::Task {
+                        task: task.key.clone(),
+                        reason: format!("unknown parent {}", parent),
+                    })
+                }
+            };
+            if task_parents.iter().any(|x| x.0 == parent_ind) {
+                continue;
+            }
+            let data_size = task
+                .inputs
+                .iter()
+                .filter(|(file, _)| tasks[parent_ind].outputs.contains_key(*file))
+                .map(|(_, size)| size)
+                .sum();
+            task_parents.push((parent_ind, data_size));
+        }
+        parents.push(task_parents);
+    }
+
+    // times in whole seconds like in Alibaba trace
+    let durations: Vec<u64> = tasks.iter().map(|x| x.runtime.ceil() as u64).collect();
+    let mut end_times: Vec<Option<u64>> = vec![None; tasks.len()];
+    let mut left = tasks.len();
+    while left > 0 {
+        let before = left;
+        for ind in 0..tasks.len() {
+            if end_times[ind].is_some() {
+                continue;
+            }
+            let parent_ends: Option<Vec<u64>> =
+                parents[ind].iter().map(|x| end_times[x.0]).collect();
+            if let Some(parent_ends) = parent_ends {
+                let start = parent_ends.into_iter().max().unwrap_or(0);
+                end_times[ind] = Some(start + durations[ind]);
+                left -= 1;
+            }
+        }
+        if before == left {
+            return Err(task_error(filename, "found cycle in graph"));
+        }
+    }
+
+    let pure_tasks = tasks
+        .iter()
+        .enumerate()
+        .map(|(ind, task)| {
+            let end_time = end_times[ind].unwrap();
+            PureTaskInfo {
+                name: String::new(),
+                instance_cnt: 1,
+                start_time: end_time - durations[ind],
+                end_time: end_time,
+                dependences: Vec::new(),
+                instances: vec![Instance {
+                    time: durations[ind],
+                    cpu_avg: task.cpu,
+                    cpu_diff_max: 0.0,
+                    mem_avg: task.mem,
+                    mem_diff_max: 0.0,
+                }],
+            }
+        })
+        .collect();
+    return Ok(dag_from_parents(pure_tasks, &parents));
+}
+
+pub fn read_workflow(filename: &str) -> Result<PureDag> {
+    let contents = read_file(filename)?;
+    let value: Value = serde_json::from_str(&contents).map_err(|why| Error::json(filename, why))?;
+    let workflow = &value["workflow"];
+    let tasks = match workflow.get("tasks") {
+        Some(Value::Array(tasks)) => read_tasks_v14(filename, workflow, tasks)?,
+        _ => read_tasks_v15(filename, workflow)?,
+    };
+    if tasks.is_empty() {
+        return Err(task_error(filename, "workflow has no tasks"));
+    }
+    return to_pure_dag(filename, tasks);
+}
+
+impl TraceSource for WfCommonsSource {
+    fn ingest_stream(&self, sink: &mut dyn JobSink) -> Result<()> {
+        for path in read_dir_sorted(&self.workflow_dir)? {
+            if !path.ends_with(".json") {
+                continue;
+            }
+            let job_name = Path::new(&path)
+                .file_stem()
+                .unwrap()
+                .to_string_lossy()
+                .to_string();
+            match read_workflow(&path) {
+                Ok(dag) => sink.job(job_name, dag)?,
+                Err(err) => eprintln!("skip {}", err.in_job(&job_name)),
+            }
+        }
+        return sink.bucket_done(0);
+    }
+}
@@ -3,4 +3,23 @@ cargo run -- --action alib_art --stat-task-name chain_ration
 cargo run -- --action alib_art --stat-task-name in_deg
 cargo run -- --action alib_art --stat-task-name out_deg
 cargo run -- --action alib_art --stat-task-name ins_ratio
-cargo run -- --action alib_art --stat-task-name time_ratio
\ No newline at end of file
+cargo run -- --action alib_art --stat-task-name time_ratio
+cargo run -- --action alib_art --stat-task-name narrow_dependen
+cargo run -- --action alib_art --stat-task-name union_dependen
+cargo run -- --action alib_art --stat-task-name wide_dependen
+cargo run -- --action alib_art --stat-task-name width_profile
+cargo run -- --action alib_art --stat-task-name longest_path
+cargo run -- --action alib_art --stat-task-name avg_path_len
+cargo run -- --action alib_art --stat-task-name sources
+cargo run -- --action alib_art --stat-task-name sinks
+cargo run -- --action alib_art --stat-task-name tred_edges
+cargo run -- --action alib_art --stat-task-name jump_ratio
+cargo run -- --action alib_art --stat-task-name fan_in_gini
+cargo run -- --action alib_art --stat-task-name fan_out_gini
+cargo run -- --action alib_art --stat-task-name ccr
+cargo run -- --action alib_art --stat-task-name parallelism
+cargo run -- --action alib_art --stat-task-name regularity
+cargo run -- --action alib_art --stat-task-name sp_depth
+cargo run -- --action alib_art --stat-task-name sp_branching
+cargo run -- --action alib_art --stat-task-name sp_removed
+cargo run -- --action alib_art --stat-task-name instance_spread
\ No newline at end of file
@@ -1,12 +1,11 @@
 #!/bin/bash
 
 ### partition real dags into types
-# for i in {0..47}
-# do
-#    python3 form_part.py $i || exit 1
-#    cargo run -- --action from_csv || exit 1
-#    cargo run -- --action form --k-part $i || exit 1
-# done
+# cargo run -- --action from_csv --batch-instance ../datasets/batch_instance_.csv || exit 1
+# or Google cluster-data 2019 exported as json lines
+# cargo run -- --action from_csv --source google --collection-events ../datasets/collection_events.json --instance-events ../datasets/instance_events.json || exit 1
+# or WfCommons workflows (Montage, Epigenomics, ...), keep them apart from cluster jobs
+# cargo run -- --action from_csv --source wfcommons --workflow-dir ../datasets/wfcommons --source-dir ../by_graph_type_wf/ || exit 1
 
 
 ### generate tasks graphs
@@ -23,18 +22,38 @@
 # cargo run -- --action task --graph-type tree_decr --min-cp 19 --max-cp 24 || exit 1
 
 # cargo run -- --action pure --graph-type other || exit 1
+# times and instance counts from lognormal, Pareto or Weibull fitted by pure, best by AIC
+# cargo run -- --action pure --graph-type other --parametric || exit 1
 # cargo run -- --action task --graph-type other --min-cp 2 --max-cp 4 || exit 1
 # cargo run -- --action task --graph-type other --min-cp 5 --max-cp 7 || exit 1
 # cargo run -- --action task --graph-type other --min-cp 8 --max-cp 10 || exit 1
 # cargo run -- --action task --graph-type other --min-cp 11 --max-cp 14 || exit 1
 # cargo run -- --action task --graph-type other --min-cp 15 --max-cp 18 || exit 1
 # cargo run -- --action task --graph-type other --min-cp 19 --max-cp 24 || exit 1
+# or compose them from stage motifs mined by pure
+# cargo run -- --action task --graph-type other --generator motif --min-cp 5 --max-cp 7 || exit 1
+
+
+# finer classes, chains are kept too
+# for graph_type in chain diamond fork_join layered_bipartite series_parallel
+# do
+#     cargo run -- --action pure --graph-type $graph_type || exit 1
+#     cargo run -- --action task --graph-type $graph_type --min-cp 2 --max-cp 4 || exit 1
+#     cargo run -- --action task --graph-type $graph_type --min-cp 5 --max-cp 7 || exit 1
+#     cargo run -- --action task --graph-type $graph_type --min-cp 8 --max-cp 10 || exit 1
+# done
 
 
 ### create instance dags
+# edges keep data sizes learned by pure
+# cargo run -- --action ins --graph-type other || exit 1
+# or are calibrated to communication to computation ratio, --ccr-definition total for sums.
+# Runs after the first one add to instance dags of the sweep with --append-ins
+# append=""
 # for ccr in  0.5 1.0 5.0
 # do
-#     cargo run -- --action ins --graph-type tree_incr --ccr-set $ccr || exit 1
-#     cargo run -- --action ins --graph-type tree_decr --ccr-set $ccr || exit 1
-#     cargo run -- --action ins --graph-type other --ccr-set $ccr || exit 1
+#     cargo run -- --action ins --graph-type tree_incr --ccr-set $ccr $append || exit 1
+#     cargo run -- --action ins --graph-type tree_decr --ccr-set $ccr $append || exit 1
+#     cargo run -- --action ins --graph-type other --ccr-set $ccr $append || exit 1
+#     append="--append-ins"
 # done
\ No newline at end of file